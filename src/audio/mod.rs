@@ -0,0 +1,204 @@
+//! Audio synthesis.
+//!
+//! The Spu controller drives a Synth with the buzzer state on every sound
+//! timer tick, so a beep lasts exactly as long as the timer runs, however
+//! often the host collects the samples. Samples are mono f32 (-1 to 1) at the
+//! configured sample rate, passed to the host after each Core::run() (see
+//! Host::audio_samples()).
+//!
+//! Buzzer changes are smoothed with short linear envelopes (attack when it
+//! turns on, release when it turns off), avoiding clicks.
+//!
+//! Once an XO-CHIP program loads an audio pattern (F002), the buzzer plays
+//! its 128 bits in a loop instead of the configured waveform, at the rate set
+//! by the pitch register (FX3A, see pattern_rate()).
+
+use std::f64::consts::PI;
+use common::constants::spu::AUDIO_PATTERN_SIZE;
+use common::types::primative::*;
+
+/// Names of the waveforms, as used in config files.
+pub const WAVEFORM_NAMES: [&'static str; 4] = ["square", "triangle", "sawtooth", "sine"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    /// Parses a waveform name (see WAVEFORM_NAMES).
+    pub fn parse(name: &str) -> Result<Waveform, String> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("Unknown waveform '{}' (expected one of {})", name, WAVEFORM_NAMES.join(", "))),
+        }
+    }
+
+    /// Returns the value at the position within the period given (0 to 1).
+    pub fn sample(&self, phase: f64) -> f32 {
+        let value = match *self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+        };
+        value as f32
+    }
+}
+
+/// Returns the XO-CHIP audio pattern playback rate (bits per second) for the pitch register value.
+pub fn pattern_rate(pitch: uword) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AudioSettings {
+    /// Buzzer frequency (Hz).
+    pub frequency: f32,
+
+    pub waveform: Waveform,
+
+    /// Peak level (0 to 1).
+    pub volume: f32,
+
+    /// Samples per second of emulated time.
+    pub sample_rate: u32,
+
+    /// Envelope ramp times (ms), when the buzzer turns on (attack) and off (release).
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            frequency: 440.0,
+            waveform: Waveform::Square,
+            volume: 0.25,
+            sample_rate: 48000,
+            attack_ms: 2.0,
+            release_ms: 2.0,
+        }
+    }
+}
+
+pub struct Synth {
+    settings: AudioSettings,
+
+    /// Position within the wave period (0 to 1).
+    phase: f64,
+
+    /// XO-CHIP audio pattern and its playback rate (bits per second), if one was loaded.
+    pattern: Option<([uword; AUDIO_PATTERN_SIZE], f64)>,
+
+    /// Position within the pattern (bits).
+    pattern_position: f64,
+
+    /// Envelope level (0 to 1).
+    level: f32,
+
+    /// Fraction of a sample left over from the last call, so sample counts follow the emulated time exactly.
+    carry: f64,
+
+    /// Samples generated since they were last taken.
+    samples: Vec<f32>,
+}
+
+impl Synth {
+    pub fn new(settings: AudioSettings) -> Synth {
+        Synth {
+            settings,
+            phase: 0.0,
+            pattern: None,
+            pattern_position: 0.0,
+            level: 0.0,
+            carry: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &AudioSettings {
+        &self.settings
+    }
+
+    /// Returns the envelope level change per sample for a ramp of the time given (ms), or None for no ramp.
+    fn ramp_step(&self, time_ms: f32) -> Option<f32> {
+        let samples = time_ms / 1000.0 * self.settings.sample_rate as f32;
+        if samples >= 1.0 { Some(1.0 / samples) } else { None }
+    }
+
+    /// Sets the XO-CHIP audio pattern and pitch register value, or goes back to the configured waveform
+    /// if no pattern is given.
+    pub fn set_pattern(&mut self, pattern: Option<&[uword; AUDIO_PATTERN_SIZE]>, pitch: uword) {
+        self.pattern = pattern.map(|pattern| (*pattern, pattern_rate(pitch)));
+    }
+
+    /// Returns the next value of the pattern or waveform (-1 to 1), before the envelope and volume.
+    fn next_value(&mut self) -> f32 {
+        let sample_rate = self.settings.sample_rate as f64;
+        match self.pattern {
+            Some((ref pattern, rate)) => {
+                let bit = self.pattern_position as usize;
+                let value = if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 };
+                self.pattern_position = (self.pattern_position + rate / sample_rate) % (AUDIO_PATTERN_SIZE * 8) as f64;
+                value
+            },
+            None => {
+                let value = self.settings.waveform.sample(self.phase);
+                self.phase = (self.phase + self.settings.frequency as f64 / sample_rate) % 1.0;
+                value
+            },
+        }
+    }
+
+    /// Generates the samples for the time given (s), with the buzzer on or off.
+    pub fn generate(&mut self, on: bool, duration_s: f64) {
+        let exact = duration_s * self.settings.sample_rate as f64 + self.carry;
+        let count = exact.floor();
+        self.carry = exact - count;
+
+        let target = if on { 1.0 } else { 0.0 };
+        let step = self.ramp_step(if on { self.settings.attack_ms } else { self.settings.release_ms });
+
+        self.samples.reserve(count as usize);
+        for _ in 0..(count as usize) {
+            self.level = match step {
+                Some(step) if self.level < target => (self.level + step).min(target),
+                Some(step) if self.level > target => (self.level - step).max(target),
+                _ => target,
+            };
+
+            let value = self.next_value();
+            let sample = if self.level > 0.0 { value * self.level * self.settings.volume } else { 0.0 };
+            self.samples.push(sample);
+        }
+    }
+
+    /// Returns the samples generated since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::constants::spu::DEFAULT_PITCH;
+
+    #[test]
+    fn pattern_rates() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+
+        // Each 48 steps of pitch is an octave.
+        assert_eq!(pattern_rate(112), 8000.0);
+        assert_eq!(pattern_rate(16), 2000.0);
+        assert!((pattern_rate(0) - 1587.4).abs() < 0.1);
+        assert!((pattern_rate(255) - 63082.4).abs() < 0.1);
+    }
+}
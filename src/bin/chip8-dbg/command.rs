@@ -0,0 +1,222 @@
+//! Debugger command parsing.
+
+use std::fmt;
+use chip8::common::constants::memory::MEMORY_SIZE;
+use chip8::common::types::primative::*;
+use chip8::debugger::{parse_value, OpcodeClass, WatchKind};
+use chip8::debugger::condition::Condition;
+use chip8::sanitizer::SanitizerMode;
+
+pub const HELP: &'static str = "\
+break <addr> [if <cond>]   set a breakpoint, eg: break 0x2A4 if V3 == 0x10
+delete <addr>              remove the breakpoint at addr
+watch <addr> [len] [r|w]   set a memory watchpoint (default: 1 byte, read/write)
+unwatch <addr>             remove watchpoints starting at addr
+catch <opcode>             stop before an opcode class (eg: draw, keyr, unknown)
+uncatch <opcode>           stop catching an opcode class
+step [n]                   execute n instructions (default: 1)
+next                       step over subroutine calls
+finish                     run until the current subroutine returns
+continue                   run until stopped (press enter to interrupt)
+x/<n> <addr|I|PC>          show n bytes of memory in the memory view
+set <V0-VF|I|PC|[addr]> <value>
+                           set a register or memory byte
+key <0-F> [up]             press (or release) a key
+info                       list breakpoints, watchpoints, catches, sanitizer reports
+                           and bus regions
+sanitize <warn|stop|off>   enable (or disable) the memory access sanitizer
+protect <region> [off]     write-protect a bus region (font, interpreter, program)
+reset                      reset the machine and reload the rom
+help                       show this help
+quit                       exit the debugger
+An empty line repeats the last command.";
+
+/// A location in memory, resolved when displayed.
+#[derive(Debug, Copy, Clone)]
+pub enum Location {
+    Address(uptr),
+    I,
+    Pc,
+}
+
+/// A register or memory byte that can be assigned with 'set'.
+#[derive(Debug, Copy, Clone)]
+pub enum Target {
+    Gpr(usize),
+    I,
+    Pc,
+    Memory(uptr),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Target::Gpr(index) => write!(f, "V{:X}", index),
+            Target::I => write!(f, "I"),
+            Target::Pc => write!(f, "PC"),
+            Target::Memory(address) => write!(f, "[0x{:03X}]", address),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Break(uptr, Option<Condition>),
+    Delete(uptr),
+    Watch(uptr, usize, WatchKind),
+    Unwatch(uptr),
+    Catch(OpcodeClass),
+    Uncatch(OpcodeClass),
+    Step(usize),
+    Next,
+    Finish,
+    Continue,
+    Examine(usize, Location),
+    Set(Target, udword),
+    Key(usize, bool),
+    Info,
+    Sanitize(Option<SanitizerMode>),
+    Protect(String, bool),
+    Reset,
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let (name, args) = match line.find(|c: char| c.is_whitespace()) {
+            Some(position) => (&line[..position], line[position..].trim()),
+            None => (line, ""),
+        };
+        let words: Vec<&str> = args.split_whitespace().collect();
+
+        // Examine takes its count as part of the command name, eg: x/16.
+        if name == "x" || name.starts_with("x/") {
+            let count = if name.len() > 2 { parse_value(&name[2..])? as usize } else { 16 };
+            let location = match words.first() {
+                Some(word) => Command::parse_location(word)?,
+                None => Location::I,
+            };
+            return Ok(Command::Examine(count, location));
+        }
+
+        match name {
+            "break" | "b" => {
+                let address = parse_value(words.first().ok_or("Missing breakpoint address")?)?;
+                let condition = match args.find(" if ") {
+                    Some(position) => Some(Condition::parse(&args[position + 4..])?),
+                    None => None,
+                };
+                Ok(Command::Break(address, condition))
+            },
+            "delete" | "d" => {
+                Ok(Command::Delete(parse_value(words.first().ok_or("Missing breakpoint address")?)?))
+            },
+            "watch" | "w" => {
+                let address = parse_value(words.first().ok_or("Missing watchpoint address")?)?;
+                let mut length = 1;
+                let mut kind = WatchKind::Access;
+                for word in words.iter().skip(1) {
+                    match *word {
+                        "r" => kind = WatchKind::Read,
+                        "w" => kind = WatchKind::Write,
+                        "rw" => kind = WatchKind::Access,
+                        _ => length = parse_value(word)? as usize,
+                    }
+                }
+                if length == 0 {
+                    return Err("Watchpoint length must be at least 1".to_owned());
+                }
+                Ok(Command::Watch(address, length, kind))
+            },
+            "unwatch" => {
+                Ok(Command::Unwatch(parse_value(words.first().ok_or("Missing watchpoint address")?)?))
+            },
+            "catch" => {
+                Ok(Command::Catch(OpcodeClass::parse(words.first().ok_or("Missing opcode class")?)?))
+            },
+            "uncatch" => {
+                Ok(Command::Uncatch(OpcodeClass::parse(words.first().ok_or("Missing opcode class")?)?))
+            },
+            "step" | "s" => {
+                let count = match words.first() {
+                    Some(word) => parse_value(word)? as usize,
+                    None => 1,
+                };
+                Ok(Command::Step(count))
+            },
+            "next" | "n" => Ok(Command::Next),
+            "finish" | "f" => Ok(Command::Finish),
+            "continue" | "c" => Ok(Command::Continue),
+            "set" => {
+                if words.len() != 2 {
+                    return Err("Usage: set <V0-VF|I|PC|[addr]> <value>".to_owned());
+                }
+                let target = Command::parse_target(words[0])?;
+                let value = parse_value(words[1])?;
+                let max = match target {
+                    Target::Gpr(_) | Target::Memory(_) => 0xFF,
+                    Target::I | Target::Pc => MEMORY_SIZE as udword - 1,
+                };
+                if value > max {
+                    return Err(format!("Value 0x{:X} out of range for {} (max 0x{:X})", value, target, max));
+                }
+                Ok(Command::Set(target, value))
+            },
+            "key" | "k" => {
+                let word = words.first().ok_or("Missing key")?;
+                let key = usize::from_str_radix(word.trim_left_matches("0x"), 16).map_err(|_| format!("Invalid key '{}'", word))?;
+                let pressed = words.get(1) != Some(&"up");
+                Ok(Command::Key(key, pressed))
+            },
+            "info" | "i" => Ok(Command::Info),
+            "sanitize" => {
+                match words.first() {
+                    Some(&"warn") => Ok(Command::Sanitize(Some(SanitizerMode::Warn))),
+                    Some(&"stop") => Ok(Command::Sanitize(Some(SanitizerMode::Stop))),
+                    Some(&"off") => Ok(Command::Sanitize(None)),
+                    _ => Err("Usage: sanitize <warn|stop|off>".to_owned()),
+                }
+            },
+            "protect" => {
+                let region = words.first().ok_or("Missing region")?;
+                let enabled = words.get(1) != Some(&"off");
+                Ok(Command::Protect(region.to_string(), enabled))
+            },
+            "reset" => Ok(Command::Reset),
+            "help" | "h" | "?" => Ok(Command::Help),
+            "quit" | "q" => Ok(Command::Quit),
+            _ => Err(format!("Unknown command '{}' (see 'help')", name)),
+        }
+    }
+
+    fn parse_location(text: &str) -> Result<Location, String> {
+        match text.to_uppercase().as_str() {
+            "I" => Ok(Location::I),
+            "PC" => Ok(Location::Pc),
+            _ => Ok(Location::Address(parse_value(text)?)),
+        }
+    }
+
+    fn parse_target(text: &str) -> Result<Target, String> {
+        let upper = text.to_uppercase();
+        match upper.as_str() {
+            "I" => return Ok(Target::I),
+            "PC" => return Ok(Target::Pc),
+            _ => {},
+        }
+
+        if upper.len() == 2 && upper.starts_with('V') {
+            if let Ok(index) = usize::from_str_radix(&upper[1..], 16) {
+                return Ok(Target::Gpr(index));
+            }
+        }
+
+        if upper.starts_with('[') && upper.ends_with(']') {
+            return Ok(Target::Memory(parse_value(&text[1..text.len() - 1])?));
+        }
+
+        Err(format!("Unknown register '{}'", text))
+    }
+}
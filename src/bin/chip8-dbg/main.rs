@@ -0,0 +1,275 @@
+//! Interactive terminal debugger.
+//!
+//! Usage: chip8-dbg <rom path>
+//!
+//! Shows the disassembly around the pc, registers, stack, timers, a memory view
+//! and the framebuffer, redrawn after each command. See command::HELP for the
+//! accepted commands.
+
+extern crate chip8_rs as chip8;
+
+mod command;
+mod view;
+
+use std::env;
+use std::io::BufRead;
+use std::io::Write;
+use std::io::stdin;
+use std::io::stdout;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::mpsc::*;
+use chip8::Core;
+use chip8::Config;
+use chip8::debugger::StopReason;
+use command::{Command, Target, HELP};
+use view::View;
+
+/// Emulated time per Core::run() call (us), matching the SDL frontend.
+const TIME_DELTA_US: f64 = 20000.0;
+
+/// Interval between framebuffer redraws while running (ms).
+const RUNNING_RENDER_INTERVAL_MS: u64 = 100;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: chip8-dbg <rom path>");
+        process::exit(1);
+    }
+    let rom_path = args[1].clone();
+
+    let config = Config {
+        time_delta_us: TIME_DELTA_US,
+        ..Config::default()
+    };
+    let mut core = Core::new(Some(config));
+    if let Err(e) = reset(&mut core, &rom_path) {
+        eprintln!("Could not load rom: {}", e);
+        process::exit(1);
+    }
+
+    // Lines are read on a separate thread, so a running 'continue' can be interrupted.
+    let (line_tx, line_rx) = channel::<String>();
+    thread::spawn(move || {
+        let stdin = stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) => if line_tx.send(line).is_err() { break },
+                Err(_) => break,
+            }
+        }
+    });
+
+    let mut view = View::new();
+    view.set_status("Type 'help' for a list of commands.");
+    let mut last_line = String::new();
+
+    loop {
+        if let Err(e) = view.render(&mut core) {
+            eprintln!("Could not render: {}", e);
+            process::exit(1);
+        }
+        print!("(chip8-dbg) ");
+        stdout().flush().unwrap();
+
+        let mut line = match line_rx.recv() {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            line = last_line.clone();
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                view.set_status(&e);
+                continue;
+            }
+        };
+        last_line = line;
+
+        if let Command::Quit = command {
+            break;
+        }
+
+        match execute(&mut core, &mut view, command, &rom_path, &line_rx) {
+            Ok(status) => view.set_status(&status),
+            Err(e) => view.set_status(&format!("Error: {}", e)),
+        }
+    }
+}
+
+/// Executes a command, returning the status message to show.
+fn execute(core: &mut Core, view: &mut View, command: Command, rom_path: &str, line_rx: &Receiver<String>) -> Result<String, String> {
+    match command {
+        Command::Break(address, condition) => {
+            core.debugger().set_breakpoint(address, condition);
+            Ok(format!("Breakpoint set at 0x{:03X}", address))
+        },
+        Command::Delete(address) => {
+            if core.debugger().remove_breakpoint(address) {
+                Ok(format!("Breakpoint at 0x{:03X} removed", address))
+            } else {
+                Err(format!("No breakpoint at 0x{:03X}", address))
+            }
+        },
+        Command::Watch(address, length, kind) => {
+            core.debugger().add_watchpoint(address, length, kind);
+            Ok(format!("Watchpoint ({}) set at 0x{:03X} (+{})", kind, address, length))
+        },
+        Command::Unwatch(address) => {
+            if core.debugger().remove_watchpoint(address) {
+                Ok(format!("Watchpoint at 0x{:03X} removed", address))
+            } else {
+                Err(format!("No watchpoint at 0x{:03X}", address))
+            }
+        },
+        Command::Catch(class) => {
+            core.debugger().set_opcode_break(class, true);
+            Ok(format!("Catching {} instructions", class))
+        },
+        Command::Uncatch(class) => {
+            core.debugger().set_opcode_break(class, false);
+            Ok(format!("No longer catching {} instructions", class))
+        },
+        Command::Step(count) => {
+            let mut status = String::new();
+            for _ in 0..count {
+                core.debugger().step_instruction();
+                let reason = run_until_stop(core, view, line_rx)?;
+                status = reason.to_string();
+                match reason {
+                    StopReason::Step(_) => {},
+                    _ => break,
+                }
+            }
+            Ok(status)
+        },
+        Command::Next => {
+            core.debugger().step_over();
+            run_until_stop(core, view, line_rx).map(|reason| reason.to_string())
+        },
+        Command::Finish => {
+            core.debugger().step_out();
+            run_until_stop(core, view, line_rx).map(|reason| reason.to_string())
+        },
+        Command::Continue => {
+            core.debugger().resume();
+            run_until_stop(core, view, line_rx).map(|reason| reason.to_string())
+        },
+        Command::Examine(count, location) => {
+            view.set_memory_view(location, count);
+            Ok(String::new())
+        },
+        Command::Set(target, value) => {
+            match target {
+                Target::Gpr(index) => core.set_gpr(index, value as u8)?,
+                Target::I => core.set_i(value)?,
+                Target::Pc => core.set_pc(value)?,
+                Target::Memory(address) => core.write_memory(address as usize, &[value as u8])?,
+            }
+            Ok(format!("{} = 0x{:X}", target, value))
+        },
+        Command::Key(key, pressed) => {
+            core.set_key(key, pressed)?;
+            // Process the input event straight away (no time is emulated while stopped).
+            core.run()?;
+            Ok(format!("Key {:X} {}", key, if pressed { "pressed" } else { "released" }))
+        },
+        Command::Info => {
+            let mut info = String::new();
+            {
+                let debugger = core.debugger();
+                for (address, condition) in debugger.breakpoints().iter() {
+                    match *condition {
+                        Some(ref condition) => info.push_str(&format!("break 0x{:03X} if {}\n", address, condition)),
+                        None => info.push_str(&format!("break 0x{:03X}\n", address)),
+                    }
+                }
+                for wp in debugger.watchpoints().iter() {
+                    info.push_str(&format!("watch 0x{:03X} {} ({})\n", wp.address, wp.length, wp.kind));
+                }
+                for class in debugger.opcode_breaks().iter() {
+                    info.push_str(&format!("catch {}\n", class));
+                }
+            }
+            if let Some(sanitizer) = core.sanitizer() {
+                for report in sanitizer.reports().iter() {
+                    info.push_str(&format!("sanitizer: {}\n", report));
+                }
+            }
+            for region in core.bus_regions()?.iter() {
+                let kind = if region.is_device() { "device" } else { "memory" };
+                let protect = if region.write_protect { ", write-protected" } else { "" };
+                info.push_str(&format!("region {} 0x{:03X}-0x{:03X} ({}{})\n", region.name, region.range.start, region.range.end - 1, kind, protect));
+            }
+            Ok(info)
+        },
+        Command::Sanitize(Some(mode)) => {
+            core.enable_sanitizer(mode);
+            Ok(format!("Sanitizer enabled ({:?})", mode))
+        },
+        Command::Sanitize(None) => {
+            core.disable_sanitizer();
+            Ok("Sanitizer disabled".to_owned())
+        },
+        Command::Protect(region, enabled) => {
+            core.set_write_protect(&region, enabled)?;
+            Ok(format!("Region {} write protection {}", region, if enabled { "enabled" } else { "disabled" }))
+        },
+        Command::Reset => {
+            reset(core, rom_path)?;
+            Ok("Machine reset".to_owned())
+        },
+        Command::Help => {
+            Ok(HELP.to_owned())
+        },
+        Command::Quit => {
+            Ok(String::new())
+        },
+    }
+}
+
+/// Resets the core, stopping before the first instruction.
+fn reset(core: &mut Core, rom_path: &str) -> Result<(), String> {
+    core.reset(rom_path)?;
+    core.debugger().interrupt();
+    core.run()?;
+    Ok(())
+}
+
+/// Runs the core in real time until the debugger stops emulation, redrawing the
+/// framebuffer periodically. Any input line interrupts execution.
+fn run_until_stop(core: &mut Core, view: &mut View, line_rx: &Receiver<String>) -> Result<StopReason, String> {
+    let frame_duration = Duration::from_millis((TIME_DELTA_US / 1000.0) as u64);
+    let render_interval = Duration::from_millis(RUNNING_RENDER_INTERVAL_MS);
+    let mut last_render = Instant::now();
+
+    loop {
+        let frame_start = Instant::now();
+
+        if let Some(reason) = core.run()? {
+            return Ok(reason);
+        }
+
+        if line_rx.try_recv().is_ok() {
+            core.debugger().interrupt();
+        }
+
+        if last_render.elapsed() >= render_interval {
+            view.render_running(core)?;
+            last_render = Instant::now();
+        }
+
+        let elapsed = frame_start.elapsed();
+        if elapsed < frame_duration {
+            thread::sleep(frame_duration - elapsed);
+        }
+    }
+}
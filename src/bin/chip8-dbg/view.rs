@@ -0,0 +1,154 @@
+//! Terminal rendering of the machine state.
+//!
+//! Only uses ANSI escape codes and Unicode block characters, so it works in
+//! any terminal, including over SSH with no display.
+
+use std::io::Write;
+use std::io::stdout;
+use chip8::Core;
+use chip8::common::constants::cpu::{HORIZONTAL_RES, INSTRUCTION_SIZE};
+use chip8::common::constants::memory::MEMORY_SIZE;
+use chip8::common::types::primative::*;
+use chip8::resources::cpu::instruction::Instruction;
+use chip8::video::terminal::{text, TextMode};
+use command::Location;
+
+/// Amount of instructions shown before and after the pc.
+const DISASSEMBLY_CONTEXT: usize = 5;
+
+/// Amount of bytes shown per memory view line.
+const MEMORY_LINE_SIZE: usize = 16;
+
+pub struct View {
+    /// Memory view location and byte count.
+    memory_view: (Location, usize),
+
+    /// Status message shown above the prompt.
+    status: String,
+}
+
+impl View {
+    pub fn new() -> View {
+        View {
+            memory_view: (Location::I, 32),
+            status: String::new(),
+        }
+    }
+
+    pub fn set_memory_view(&mut self, location: Location, count: usize) {
+        self.memory_view = (location, count);
+    }
+
+    pub fn set_status(&mut self, status: &str) {
+        self.status = status.to_owned();
+    }
+
+    /// Clears the terminal and draws the full machine state.
+    pub fn render(&self, core: &mut Core) -> Result<(), String> {
+        let mut out = String::from("\x1b[H\x1b[2J");
+        self.render_registers(core, &mut out)?;
+        out.push('\n');
+        self.render_disassembly(core, &mut out)?;
+        out.push('\n');
+        self.render_memory(core, &mut out)?;
+        out.push('\n');
+        self.render_framebuffer(core, &mut out)?;
+        out.push_str(&self.status);
+        out.push('\n');
+
+        let mut stdout = stdout();
+        stdout.write_all(out.as_bytes()).map_err(|e| e.to_string())?;
+        stdout.flush().map_err(|e| e.to_string())
+    }
+
+    /// Redraws only the framebuffer in place, used while running.
+    pub fn render_running(&self, core: &mut Core) -> Result<(), String> {
+        let mut out = String::from("\x1b[H\x1b[2J");
+        self.render_framebuffer(core, &mut out)?;
+        out.push_str("Running... (press enter to interrupt)\n");
+
+        let mut stdout = stdout();
+        stdout.write_all(out.as_bytes()).map_err(|e| e.to_string())?;
+        stdout.flush().map_err(|e| e.to_string())
+    }
+
+    fn render_registers(&self, core: &mut Core, out: &mut String) -> Result<(), String> {
+        for row in 0..2 {
+            for index in (row * 8)..(row * 8 + 8) {
+                out.push_str(&format!(" V{:X} {:02X} ", index, core.gpr(index)?));
+            }
+            out.push('\n');
+        }
+
+        out.push_str(&format!(" I 0x{:03X}   PC 0x{:03X}   DT {:02X}   ST {:02X}\n", core.i()?, core.pc()?, core.delay_timer()?, core.sound_timer()?));
+
+        out.push_str(" Stack:");
+        for (depth, address) in core.stack()?.iter().enumerate() {
+            out.push_str(&format!(" [{}] 0x{:03X}", depth, address));
+        }
+        out.push('\n');
+
+        Ok(())
+    }
+
+    fn render_disassembly(&self, core: &mut Core, out: &mut String) -> Result<(), String> {
+        let pc = core.pc()? as usize;
+        let start = pc.saturating_sub(DISASSEMBLY_CONTEXT * INSTRUCTION_SIZE);
+        let end = (pc + (DISASSEMBLY_CONTEXT + 1) * INSTRUCTION_SIZE).min(MEMORY_SIZE - 1);
+
+        let mut address = start;
+        while address < end {
+            let mut bytes = [0; INSTRUCTION_SIZE];
+            core.read_memory(address, &mut bytes)?;
+            let value = ((bytes[0] as udword) << 8) | (bytes[1] as udword);
+
+            let marker = if address == pc { '>' } else { ' ' };
+            let breakpoint = if core.debugger().breakpoints().contains_key(&(address as uptr)) { '*' } else { ' ' };
+            out.push_str(&format!(" {}{} 0x{:03X}  {:04X}  {}\n", marker, breakpoint, address, value, Instruction::new(value)));
+
+            address += INSTRUCTION_SIZE;
+        }
+
+        Ok(())
+    }
+
+    fn render_memory(&self, core: &mut Core, out: &mut String) -> Result<(), String> {
+        let (location, count) = self.memory_view;
+        let start = match location {
+            Location::Address(address) => address as usize,
+            Location::I => core.i()? as usize,
+            Location::Pc => core.pc()? as usize,
+        };
+        let end = (start + count).min(MEMORY_SIZE);
+
+        let mut address = start;
+        while address < end {
+            let mut bytes = vec![0; (end - address).min(MEMORY_LINE_SIZE)];
+            core.read_memory(address, &mut bytes)?;
+
+            out.push_str(&format!(" 0x{:03X}:", address));
+            for byte in bytes.iter() {
+                out.push_str(&format!(" {:02X}", byte));
+            }
+            out.push('\n');
+
+            address += bytes.len();
+        }
+
+        Ok(())
+    }
+
+    /// Draws the framebuffer with Unicode half blocks (see video::terminal), in a border.
+    fn render_framebuffer(&self, core: &mut Core, out: &mut String) -> Result<(), String> {
+        let framebuffer = core.framebuffer()?;
+        let border: String = (0..HORIZONTAL_RES).map(|_| '─').collect();
+
+        out.push_str(&format!(" ┌{}┐\n", border));
+        for line in text(&framebuffer, TextMode::HalfBlock) {
+            out.push_str(&format!(" │{}│\n", line));
+        }
+        out.push_str(&format!(" └{}┘\n", border));
+
+        Ok(())
+    }
+}
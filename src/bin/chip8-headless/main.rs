@@ -0,0 +1,262 @@
+//! Headless runner, for capturing gameplay and scripted runs without a display.
+//!
+//! Usage: chip8-headless <rom path> [--frames <n>] [--screenshot <frame>]... [--record <first>-<last>]
+//!                       [--video <path>] [--audio <path>] [--wav <path>] [--config <path>] [--set section.field=value]...
+//!
+//! Runs the rom for a number of frames as fast as possible. Each frame is a Core::run()
+//! of 1/60 s (rather than emulator.time_delta_us), so every emulated display frame is
+//! captured exactly once. Screenshots are saved after each frame given, and the frames
+//! in the record range are recorded to an animated GIF, both in the configured palette
+//! and video.scale (see capture). Captures are written to workspace/captures/.
+//!
+//! For encoders, the whole run can also be streamed as y4m video at 60 fps (a video
+//! frame per run) and raw PCM audio (see capture::stream), to files or to stdout with
+//! '-'. Messages are written to stderr, so they don't mix with a stream. The audio of
+//! the whole run can also be recorded to a WAV file. Both audio outputs need
+//! audio.enabled (see audio).
+
+extern crate chip8_rs as chip8;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write, stdout};
+use std::process;
+use std::rc::Rc;
+use chip8::Core;
+use chip8::config::{ConfigFile, CONFIG_PATH};
+use chip8::capture::stream::{Y4mWriter, PcmWriter, FRAME_RATE};
+use chip8::host::Host;
+
+/// Frames run when not given.
+const DEFAULT_FRAMES: usize = 300;
+
+const USAGE: &'static str = "Usage: chip8-headless <rom path> [--frames <n>] [--screenshot <frame>]... [--record <first>-<last>] [--video <path>] [--audio <path>] [--wav <path>] [--config <path>] [--set section.field=value]...";
+
+/// Collects the audio synthesised during a run, for the audio stream.
+struct AudioBuffer {
+    samples: Vec<f32>,
+}
+
+impl Host for AudioBuffer {
+    fn audio_samples(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+}
+
+struct Options {
+    rom_path: String,
+    frames: usize,
+
+    /// Frames after which screenshots are saved (counting from 1).
+    screenshots: Vec<usize>,
+
+    /// First and last frames recorded (counting from 1).
+    record: Option<(usize, usize)>,
+
+    /// Stream paths ('-' for stdout).
+    video_path: Option<String>,
+    audio_path: Option<String>,
+
+    /// WAV recording path.
+    wav_path: Option<String>,
+
+    config_file: ConfigFile,
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_frame(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(frame) if frame > 0 => Ok(frame),
+        _ => Err(format!("'{}' is not a frame number (counting from 1)", value)),
+    }
+}
+
+fn parse_options() -> Result<Options, String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut rom_path = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut screenshots = Vec::new();
+    let mut record = None;
+    let mut video_path = None;
+    let mut audio_path = None;
+    let mut wav_path = None;
+    let mut config_path = None;
+    let mut overrides = Vec::new();
+
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        if !arg.starts_with("--") {
+            if rom_path.is_some() {
+                return Err(USAGE.to_owned());
+            }
+            rom_path = Some(arg.to_owned());
+            index += 1;
+            continue;
+        }
+
+        let value = match args.get(index + 1) {
+            Some(value) => value.as_str(),
+            None => return Err(USAGE.to_owned()),
+        };
+        match arg {
+            "--frames" => frames = parse_frame(value)?,
+            "--screenshot" => screenshots.push(parse_frame(value)?),
+            "--record" => {
+                let range: Vec<&str> = value.splitn(2, '-').collect();
+                if range.len() != 2 {
+                    return Err(format!("'{}' is not a frame range (expected <first>-<last>)", value));
+                }
+                let (first, last) = (parse_frame(range[0])?, parse_frame(range[1])?);
+                if first > last {
+                    return Err(format!("'{}' is not a frame range (the first frame is after the last)", value));
+                }
+                record = Some((first, last));
+            },
+            "--video" => video_path = Some(value.to_owned()),
+            "--audio" => audio_path = Some(value.to_owned()),
+            "--wav" => wav_path = Some(value.to_owned()),
+            "--config" => config_path = Some(value.to_owned()),
+            "--set" => overrides.push(value.to_owned()),
+            _ => return Err(USAGE.to_owned()),
+        }
+        index += 2;
+    }
+
+    if let Some(&frame) = screenshots.iter().find(|&&frame| frame > frames) {
+        return Err(format!("Screenshot frame {} is after the last frame ({})", frame, frames));
+    }
+    if video_path.is_some() && video_path == audio_path {
+        return Err("The video and audio streams must be written to different paths".to_owned());
+    }
+    if let Some((first, _)) = record {
+        if first > frames {
+            return Err(format!("Recording starts after the last frame ({})", frames));
+        }
+    }
+
+    let config_file = match config_path {
+        Some(path) => ConfigFile::load(&path, true, &overrides)?,
+        None => ConfigFile::load(&format!("./workspace/{}", CONFIG_PATH), false, &overrides)?,
+    };
+    if (audio_path.is_some() || wav_path.is_some()) && !config_file.audio.enabled {
+        return Err("Audio output needs audio.enabled".to_owned());
+    }
+    let rom_path = match rom_path.or_else(|| config_file.rom.clone()) {
+        Some(rom_path) => rom_path,
+        None => return Err(USAGE.to_owned()),
+    };
+
+    Ok(Options {
+        rom_path,
+        frames,
+        screenshots,
+        record,
+        video_path,
+        audio_path,
+        wav_path,
+        config_file,
+    })
+}
+
+/// Opens a stream output, '-' being stdout.
+fn open_output(path: &str) -> Result<Box<Write>, String> {
+    if path == "-" {
+        return Ok(Box::new(BufWriter::new(stdout())));
+    }
+    let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let audio_buffer = Rc::new(RefCell::new(AudioBuffer { samples: Vec::new() }));
+    let mut config = options.config_file.config()?;
+    config.multithreaded_pool = options.config_file.multithreaded_pool(false);
+    config.host = Some(Box::new(audio_buffer.clone()));
+
+    // Runs are whole display frames, so the video stream samples the display on every frame
+    // boundary instead of resampling longer runs (repeating displays).
+    let time_delta_us = 1e6 / FRAME_RATE as f64;
+    config.time_delta_us = time_delta_us;
+    let mut core = Core::new(Some(config));
+    core.reset(&options.rom_path)?;
+
+    // The palette recommended for the rom is used, if any (see Core::rom_palette()).
+    let palette = match core.rom_palette()? {
+        Some(palette) => palette,
+        None => options.config_file.palette()?,
+    };
+    let scale = options.config_file.video.scale as usize;
+
+    let mut video = match options.video_path {
+        Some(ref path) => Some(Y4mWriter::new(open_output(path)?, &palette, scale)?),
+        None => None,
+    };
+    let mut audio = match options.audio_path {
+        Some(ref path) => Some(PcmWriter::new(open_output(path)?)),
+        None => None,
+    };
+    if let Some(ref path) = options.wav_path {
+        eprintln!("Recording audio to {}", core.start_audio_recording(Some(path))?);
+    }
+
+    for frame in 1..(options.frames + 1) {
+        if options.record.map_or(false, |(first, _)| first == frame) {
+            eprintln!("Recording to {}", core.start_recording(None, &palette, scale)?);
+        }
+
+        core.run()?;
+        let samples: Vec<f32> = audio_buffer.borrow_mut().samples.drain(..).collect();
+
+        if let Some(ref mut video) = video {
+            video.add_frame(&core.framebuffer()?, time_delta_us)?;
+        }
+        if let Some(ref mut audio) = audio {
+            audio.add_samples(&samples)?;
+        }
+
+        if options.screenshots.contains(&frame) {
+            eprintln!("Screenshot of frame {} saved to {}", frame, core.screenshot(None, &palette, scale)?);
+        }
+        if options.record.map_or(false, |(_, last)| last == frame) {
+            stop_recording(&mut core)?;
+        }
+    }
+
+    if let Some(ref mut video) = video {
+        video.flush()?;
+        eprintln!("Streamed {} video frames", video.frames());
+    }
+    if let Some(ref mut audio) = audio {
+        audio.flush()?;
+        eprintln!("Streamed {} audio samples", audio.samples());
+    }
+
+    // Recordings past the last frame end with it.
+    stop_recording(&mut core)?;
+    if let Some((path, samples)) = core.stop_audio_recording()? {
+        eprintln!("Recorded {} audio samples to {}", samples, path);
+    }
+    Ok(())
+}
+
+fn stop_recording(core: &mut Core) -> Result<(), String> {
+    if let Some((path, frames)) = core.stop_recording()? {
+        eprintln!("Recorded {} frames to {}", frames, path);
+    }
+    Ok(())
+}
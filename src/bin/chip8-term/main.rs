@@ -0,0 +1,291 @@
+//! Terminal frontend, for playing over SSH or on machines without a display.
+//!
+//! Usage: chip8-term [rom path] [--config <path>] [--set section.field=value]...
+//!
+//! Draws the display with Unicode characters (see video::terminal) and reads
+//! keys in raw mode. Settings come from the same config file as the SDL
+//! frontend: the keys section maps characters to the Chip8 keys (the arrow
+//! keys are named Up, Down, Left and Right), the terminal section sets the
+//! text mode, key release timeout and sound indicator, and pacing.speed the
+//! emulation speed. Esc or Ctrl-C quits.
+
+extern crate chip8_rs as chip8;
+extern crate termion;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::io::{Read, Write, stdout};
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::{clear, color, cursor, style};
+use termion::event::{Event, Key, parse_event};
+use termion::raw::IntoRawMode;
+use chip8::Core;
+use chip8::config::{ConfigFile, CONFIG_PATH};
+use chip8::host::Host;
+use chip8::pacing::Pacer;
+use chip8::keymap::{Keymap, HostInput};
+use chip8::video::palette::Palette;
+use chip8::video::terminal::{TextMode, text};
+use chip8::common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+
+/// Key names besides single characters, as written in the keys section.
+const KEY_NAMES: [&'static str; 7] = ["Up", "Down", "Left", "Right", "Space", "Return", "Tab"];
+
+/// The core's host: keeps the framebuffer until it is drawn, and the sound and key state.
+struct TerminalHost {
+    framebuffer: [bool; HORIZONTAL_RES * VERTICAL_RES],
+
+    /// Set when the display or the sound changed since it was last drawn.
+    dirty: bool,
+
+    sound: bool,
+
+    /// Set when the sound was turned on, until the bell is rung.
+    bell: bool,
+
+    /// Key changes from the keymap, applied on the next run.
+    key_changes: Vec<(usize, bool)>,
+}
+
+impl Host for TerminalHost {
+    fn video(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES]) {
+        self.framebuffer = *framebuffer;
+        self.dirty = true;
+    }
+
+    fn audio(&mut self, on: bool) {
+        self.sound = on;
+        self.bell |= on;
+        self.dirty = true;
+    }
+
+    fn input(&mut self) -> Vec<(usize, bool)> {
+        self.key_changes.drain(..).collect()
+    }
+}
+
+/// Typed keys, released after a timeout as terminals don't report key releases.
+struct HeldKeys {
+    keymap: Keymap,
+    hold: Duration,
+
+    /// Release time of each held input.
+    release_at: HashMap<HostInput, Instant>,
+}
+
+impl HeldKeys {
+    /// Presses the input (or keeps it pressed, if already held), returning the Chip8 key changes.
+    fn press(&mut self, input: HostInput) -> Vec<(usize, bool)> {
+        self.release_at.insert(input.clone(), Instant::now() + self.hold);
+        self.keymap.update(input, true).into_iter().collect()
+    }
+
+    /// Releases the inputs held past their timeout, returning the Chip8 key changes.
+    fn release_expired(&mut self) -> Vec<(usize, bool)> {
+        let now = Instant::now();
+        let expired: Vec<HostInput> = self.release_at.iter()
+            .filter(|&(_, &release_at)| release_at <= now)
+            .map(|(input, _)| input.clone())
+            .collect();
+
+        let mut changes = Vec::new();
+        for input in expired {
+            self.release_at.remove(&input);
+            changes.extend(self.keymap.update(input, false));
+        }
+        changes
+    }
+}
+
+fn main() {
+    let (rom_path, config_file) = match load_config_file() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
+    };
+    if let Err(e) = run(&rom_path, &config_file) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Loads the config file named by '--config' (or workspace/config/config.yml if present), applying the
+/// '--set' overrides. Returns the rom path (the argument given, or the config file's rom) and the config file.
+fn load_config_file() -> Result<(String, ConfigFile), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut rom_path = None;
+    let mut config_path = None;
+    let mut overrides = Vec::new();
+
+    let mut index = 0;
+    while index < args.len() {
+        match args[index].as_str() {
+            "--config" | "--set" if index + 1 < args.len() => {
+                if args[index] == "--config" {
+                    config_path = Some(args[index + 1].clone());
+                } else {
+                    overrides.push(args[index + 1].clone());
+                }
+                index += 1;
+            },
+            arg if !arg.starts_with("--") && rom_path.is_none() => rom_path = Some(arg.to_owned()),
+            _ => return Err("Usage: chip8-term [rom path] [--config <path>] [--set section.field=value]...".to_owned()),
+        }
+        index += 1;
+    }
+
+    let config_file = match config_path {
+        Some(path) => ConfigFile::load(&path, true, &overrides)?,
+        None => ConfigFile::load(&format!("./workspace/{}", CONFIG_PATH), false, &overrides)?,
+    };
+    match rom_path.or_else(|| config_file.rom.clone()) {
+        Some(rom_path) => Ok((rom_path, config_file)),
+        None => Err("No rom given (pass a rom path, or set rom in the config file)".to_owned()),
+    }
+}
+
+/// Returns true if the input can be typed into a terminal. Game controller bindings are skipped.
+fn is_known_input(input: &HostInput) -> bool {
+    match *input {
+        HostInput::Key(ref name) => name.chars().count() == 1 || KEY_NAMES.iter().any(|key_name| key_name.to_lowercase() == *name),
+        HostInput::Button(_) | HostInput::Axis(..) => true,
+    }
+}
+
+/// Returns the input for a typed key, if it can be bound.
+fn key_input(key: Key) -> Option<HostInput> {
+    match key {
+        Key::Char(' ') => Some(HostInput::key("Space")),
+        Key::Char('\n') | Key::Char('\r') => Some(HostInput::key("Return")),
+        Key::Char('\t') => Some(HostInput::key("Tab")),
+        Key::Char(character) => Some(HostInput::key(&character.to_string())),
+        Key::Up => Some(HostInput::key("Up")),
+        Key::Down => Some(HostInput::key("Down")),
+        Key::Left => Some(HostInput::key("Left")),
+        Key::Right => Some(HostInput::key("Right")),
+        _ => None,
+    }
+}
+
+/// Returns the keymap and palette, with the rom's database entry keymap (see romdb) and recommended
+/// palette (see Core::rom_palette()) applied.
+fn rom_settings(config_file: &ConfigFile, core: &Core) -> Result<(Keymap, Palette), String> {
+    let mut keymap = config_file.keymap()?;
+    keymap.validate("keys", is_known_input)?;
+    let mut palette = config_file.palette()?;
+
+    if let Some(entry) = core.rom_entry() {
+        keymap.apply("keymap", &entry.keymap).map_err(|e| format!("Invalid rom database keymap: {}", e))?;
+    }
+    if let Some(rom_palette) = core.rom_palette()? {
+        palette = rom_palette;
+    }
+    Ok((keymap, palette))
+}
+
+fn run(rom_path: &str, config_file: &ConfigFile) -> Result<(), String> {
+    let host = Rc::new(RefCell::new(TerminalHost {
+        framebuffer: [false; HORIZONTAL_RES * VERTICAL_RES],
+        dirty: true,
+        sound: false,
+        bell: false,
+        key_changes: Vec::new(),
+    }));
+
+    // Terminals can't play samples, so the sound timer is only signalled (see terminal.sound).
+    let mut config = config_file.config()?;
+    config.multithreaded_pool = config_file.multithreaded_pool(false);
+    config.host = Some(Box::new(host.clone()));
+    config.audio = None;
+    let time_delta_us = config.time_delta_us;
+    let mut core = Core::new(Some(config));
+    core.reset(rom_path)?;
+
+    let (keymap, palette) = rom_settings(config_file, &core)?;
+    let mut held_keys = HeldKeys {
+        keymap,
+        hold: Duration::from_millis(config_file.terminal.key_hold_ms),
+        release_at: HashMap::new(),
+    };
+    let mode = TextMode::parse(&config_file.terminal.mode)?;
+    let mut pacer = Pacer::new(time_delta_us, config_file.pacing_settings()?);
+
+    // Raw mode is left when the terminal is dropped, including on errors.
+    let mut terminal = stdout().into_raw_mode().map_err(|e| format!("Could not enter raw mode: {}", e))?;
+    let mut stdin = termion::async_stdin();
+    write!(terminal, "{}{}", clear::All, cursor::Hide).map_err(|e| e.to_string())?;
+
+    let result = (|| -> Result<(), String> {
+        loop {
+            let mut bytes = Vec::new();
+            stdin.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+            let mut bytes = bytes.into_iter().map(Ok).peekable();
+            while let Some(Ok(byte)) = bytes.next() {
+                // A lone escape byte is the Esc key, rather than the start of a sequence.
+                let key = match parse_event(byte, &mut bytes) {
+                    _ if byte == 0x1B && bytes.peek().is_none() => Key::Esc,
+                    Ok(Event::Key(key)) => key,
+                    _ => continue,
+                };
+                if key == Key::Esc || key == Key::Ctrl('c') {
+                    return Ok(());
+                }
+                if let Some(input) = key_input(key) {
+                    let changes = held_keys.press(input);
+                    host.borrow_mut().key_changes.extend(changes);
+                }
+            }
+            let changes = held_keys.release_expired();
+            host.borrow_mut().key_changes.extend(changes);
+
+            for _ in 0..pacer.runs(Instant::now()) {
+                core.run()?;
+            }
+
+            if host.borrow().dirty {
+                draw(&mut terminal, &mut host.borrow_mut(), mode, &palette, config_file)?;
+            }
+
+            if let Some(wait) = pacer.time_until_run(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+    })();
+
+    write!(terminal, "{}{}{}{}", style::Reset, clear::All, cursor::Goto(1, 1), cursor::Show).map_err(|e| e.to_string())?;
+    terminal.flush().map_err(|e| e.to_string())?;
+    result
+}
+
+/// Draws the display, followed by the status line.
+fn draw<W: Write>(terminal: &mut W, host: &mut TerminalHost, mode: TextMode, palette: &Palette, config_file: &ConfigFile) -> Result<(), String> {
+    let mut output = format!("{}", cursor::Goto(1, 1));
+    let (foreground, background) = (palette.foreground(), palette.background());
+    for line in text(&host.framebuffer, mode) {
+        if config_file.terminal.colour {
+            output.push_str(&format!("{}{}", color::Fg(color::Rgb(foreground[0], foreground[1], foreground[2])), color::Bg(color::Rgb(background[0], background[1], background[2]))));
+        }
+        output.push_str(&line);
+        output.push_str(&format!("{}\r\n", style::Reset));
+    }
+
+    let sound = match config_file.terminal.sound.as_str() {
+        "visual" if host.sound => format!("{} SOUND {}", style::Invert, style::Reset),
+        "visual" => "       ".to_owned(),
+        _ => String::new(),
+    };
+    output.push_str(&format!("{} Esc quits{}", sound, clear::UntilNewline));
+    if config_file.terminal.sound == "bell" && host.bell {
+        output.push('\x07');
+    }
+
+    host.dirty = false;
+    host.bell = false;
+    terminal.write_all(output.as_bytes()).and_then(|_| terminal.flush()).map_err(|e| format!("Could not draw: {}", e))
+}
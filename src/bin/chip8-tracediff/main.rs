@@ -0,0 +1,82 @@
+//! Instruction trace comparison.
+//!
+//! Usage: chip8-tracediff [--ignore-cycles] <trace a> <trace b>
+//!
+//! Compares two traces recorded with Core::start_trace() (in either format) and
+//! reports the first divergent instruction, along with the instructions leading
+//! up to it. Exits with status 1 if the traces differ.
+
+extern crate chip8_rs as chip8;
+
+use std::collections::VecDeque;
+use std::env;
+use std::process;
+use chip8::trace::TraceRecord;
+use chip8::trace::reader::TraceReader;
+
+/// Amount of matching records shown before the divergence.
+const CONTEXT_RECORDS: usize = 5;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let ignore_cycles = args.iter().any(|arg| arg == "--ignore-cycles");
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    if paths.len() != 2 {
+        eprintln!("Usage: chip8-tracediff [--ignore-cycles] <trace a> <trace b>");
+        process::exit(2);
+    }
+
+    match diff(paths[0], paths[1], ignore_cycles) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        },
+    }
+}
+
+/// Compares the traces, printing the first divergence. Returns if the traces match.
+fn diff(path_a: &str, path_b: &str, ignore_cycles: bool) -> Result<bool, String> {
+    let mut reader_a = TraceReader::open(path_a)?;
+    let mut reader_b = TraceReader::open(path_b)?;
+    let mut context: VecDeque<TraceRecord> = VecDeque::with_capacity(CONTEXT_RECORDS);
+    let mut index = 0;
+
+    loop {
+        let record_a = reader_a.next_record()?;
+        let record_b = reader_b.next_record()?;
+
+        let matches = match (&record_a, &record_b) {
+            (&None, &None) => {
+                println!("Traces match ({} instructions)", index);
+                return Ok(true);
+            },
+            (&Some(ref a), &Some(ref b)) => a.same_effects(b) && (ignore_cycles || a.cycle == b.cycle),
+            _ => false,
+        };
+
+        if !matches {
+            println!("Traces diverge at instruction {}:", index);
+            for record in context.iter() {
+                println!("  {}", record);
+            }
+            print_record(path_a, &record_a);
+            print_record(path_b, &record_b);
+            return Ok(false);
+        }
+
+        if context.len() == CONTEXT_RECORDS {
+            context.pop_front();
+        }
+        context.push_back(record_a.unwrap());
+        index += 1;
+    }
+}
+
+fn print_record(path: &str, record: &Option<TraceRecord>) {
+    match *record {
+        Some(ref record) => println!("- {}\n  {}", path, record),
+        None => println!("- {}\n  (end of trace)", path),
+    }
+}
@@ -0,0 +1,145 @@
+//! Rom browser.
+//!
+//! Lists the files in the workspace roms folder and draws the list into a
+//! framebuffer with the Chip8 font (see font::text_glyph()), so a frontend can
+//! show it in place of the display. Navigation and loading the selected rom
+//! are left to the frontend.
+
+use std::fs;
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES, SPRITE_SIZE};
+use font::text_glyph;
+
+/// Path of the roms folder, relative to the workspace.
+pub const ROMS_PATH: &'static str = "roms/";
+
+/// Glyph cell size, including spacing (pixels).
+const CELL_WIDTH: usize = 5;
+const ROW_HEIGHT: usize = SPRITE_SIZE + 1;
+
+/// Number of entries shown at once.
+pub const VISIBLE_ROWS: usize = VERTICAL_RES / ROW_HEIGHT;
+
+/// Number of characters shown per entry (longer names are cut off).
+pub const VISIBLE_COLUMNS: usize = HORIZONTAL_RES / CELL_WIDTH;
+
+pub struct RomBrowser {
+    directory: String,
+
+    /// File names, sorted.
+    entries: Vec<String>,
+
+    selected: usize,
+
+    /// Index of the first entry shown.
+    scroll: usize,
+}
+
+impl RomBrowser {
+    /// Lists the roms in the directory (hidden files and subdirectories are skipped).
+    pub fn open(directory: &str) -> Result<RomBrowser, String> {
+        let read_dir = fs::read_dir(directory).map_err(|e| format!("Could not list roms in {}: {}", directory, e))?;
+
+        let mut entries = Vec::new();
+        for entry in read_dir {
+            let entry = entry.map_err(|e| format!("Could not list roms in {}: {}", directory, e))?;
+            let is_file = entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false);
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if is_file && !name.starts_with('.') {
+                entries.push(name);
+            }
+        }
+        entries.sort();
+
+        Ok(RomBrowser {
+            directory: directory.trim_right_matches('/').to_owned(),
+            entries,
+            selected: 0,
+            scroll: 0,
+        })
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Returns the path of the selected rom, or None if there are no roms.
+    pub fn selected_path(&self) -> Option<String> {
+        self.entries.get(self.selected).map(|name| format!("{}/{}", self.directory, name))
+    }
+
+    /// Selects the entry with the given path, if listed (eg: the rom currently loaded).
+    pub fn select_path(&mut self, path: &str) {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if let Some(index) = self.entries.iter().position(|entry| entry == name) {
+            self.select(index);
+        }
+    }
+
+    /// Moves the selection by offset entries, stopping at the first and last entries.
+    pub fn move_selection(&mut self, offset: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() as isize - 1;
+        let index = (self.selected as isize).saturating_add(offset).max(0).min(last);
+        self.select(index as usize);
+    }
+
+    /// Moves the selection by a page.
+    pub fn move_page(&mut self, pages: isize) {
+        self.move_selection(pages.saturating_mul(VISIBLE_ROWS as isize));
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + VISIBLE_ROWS {
+            self.scroll = self.selected + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    /// Draws the visible entries, the selected one inverted.
+    pub fn render(&self, framebuffer: &mut [bool; HORIZONTAL_RES * VERTICAL_RES]) {
+        for pixel in framebuffer.iter_mut() {
+            *pixel = false;
+        }
+
+        if self.entries.is_empty() {
+            draw_text(framebuffer, "NO ROMS", 0, false);
+            return;
+        }
+
+        let visible = self.entries.iter().enumerate().skip(self.scroll).take(VISIBLE_ROWS);
+        for (row, (index, name)) in visible.enumerate() {
+            draw_text(framebuffer, name, row, index == self.selected);
+        }
+    }
+}
+
+/// Draws a line of text in the given row, optionally inverted.
+fn draw_text(framebuffer: &mut [bool; HORIZONTAL_RES * VERTICAL_RES], text: &str, row: usize, inverted: bool) {
+    let top = row * ROW_HEIGHT;
+    if inverted {
+        for y in top..(top + ROW_HEIGHT) {
+            for x in 0..HORIZONTAL_RES {
+                framebuffer[y * HORIZONTAL_RES + x] = true;
+            }
+        }
+    }
+
+    for (column, character) in text.chars().take(VISIBLE_COLUMNS).enumerate() {
+        let left = column * CELL_WIDTH + 1;
+        for (glyph_y, &line) in text_glyph(character).iter().enumerate() {
+            for glyph_x in 0..4 {
+                if (line << glyph_x) & 0x80 != 0 {
+                    framebuffer[(top + 1 + glyph_y) * HORIZONTAL_RES + left + glyph_x] = !inverted;
+                }
+            }
+        }
+    }
+}
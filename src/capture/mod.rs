@@ -0,0 +1,169 @@
+//! Screenshots and animated GIF recordings of the display.
+//!
+//! Captures use the palette and integer scale given by the frontend (phosphor
+//! persistence and post-processing are not applied). Without a path, they are
+//! written to the workspace captures folder, named after the rom (see
+//! capture_path()).
+//!
+//! Recordings are frame accurate: the display is sampled after every
+//! Core::run(), and each frame is shown for the emulated time it lasted.
+//! Unchanged samples extend the previous frame rather than adding one. GIF
+//! frames last at least 2 centiseconds, so a display changing faster than 50
+//! times a second has frames dropped, keeping the overall timing.
+//!
+//! For long recordings, the display and buzzer can be streamed to external
+//! encoders instead (see stream). The synthesised audio can also be recorded
+//! to a WAV file (see wav).
+
+pub mod stream;
+pub mod wav;
+
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use gif;
+use png;
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+use video;
+use video::palette::Palette;
+use video::filter::{Image, scale_nearest};
+
+/// Path of the captures folder, relative to the workspace.
+pub const CAPTURES_PATH: &'static str = "captures/";
+
+/// Shortest GIF frame delay (cs). Decoders show shorter delays much slower, so shorter frames are
+/// replaced by the next one.
+const MIN_GIF_DELAY_CS: f64 = 2.0;
+
+/// Returns an unused path in the captures folder (created if needed) for the rom, eg:
+/// "./workspace/captures/BLINKY-0003.png".
+pub fn capture_path(workspace_path: &str, rom_name: &str, extension: &str) -> Result<String, String> {
+    let directory = format!("{}{}", workspace_path, CAPTURES_PATH);
+    fs::create_dir_all(&directory).map_err(|e| format!("Could not create {}: {}", directory, e))?;
+
+    let name = if rom_name.is_empty() { "capture" } else { rom_name };
+    for number in 1.. {
+        let path = format!("{}{}-{:04}.{}", directory, name, number, extension);
+        if !Path::new(&path).exists() {
+            return Ok(path);
+        }
+    }
+    unreachable!()
+}
+
+/// Writes the image to the path as an RGBA PNG.
+pub fn write_png(path: &str, image: &Image) -> Result<(), String> {
+    use png::HasParameters;
+
+    let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width() as u32, image.height() as u32);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| format!("Could not write {}: {}", path, e))?;
+    writer.write_image_data(&image.to_rgba_bytes()).map_err(|e| format!("Could not write {}: {}", path, e))
+}
+
+/// Writes the display to the path as a PNG, scaled by the integer factor given.
+pub fn screenshot(path: &str, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], palette: &Palette, scale: usize) -> Result<(), String> {
+    let image = video::image(framebuffer, palette, None);
+    write_png(path, &scale_nearest(&image, scale.max(1)))
+}
+
+/// Records the display to an animated GIF.
+pub struct GifRecorder {
+    path: String,
+    encoder: gif::Encoder<BufWriter<File>>,
+    scale: usize,
+
+    /// Frame waiting to be written (it may still be extended), and its length (us).
+    pending: Option<([bool; HORIZONTAL_RES * VERTICAL_RES], f64)>,
+
+    /// Time not yet accounted for in the frame delays written so far (cs), as delays are whole centiseconds.
+    carry_cs: f64,
+
+    frames: usize,
+}
+
+impl GifRecorder {
+    /// Creates the GIF at the path, looping forever, with the palette and the integer scale given.
+    pub fn new(path: &str, palette: &Palette, scale: usize) -> Result<GifRecorder, String> {
+        use gif::SetParameter;
+
+        let scale = scale.max(1);
+        let (width, height) = (HORIZONTAL_RES * scale, VERTICAL_RES * scale);
+        if width > u16::max_value() as usize || height > u16::max_value() as usize {
+            return Err(format!("Scale {} is too large for a GIF", scale));
+        }
+
+        let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+        let colours: Vec<u8> = palette.colours.iter().flat_map(|colour| colour.iter().cloned()).collect();
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &colours)
+            .map_err(|e| format!("Could not write {}: {}", path, e))?;
+        encoder.set(gif::Repeat::Infinite).map_err(|e| format!("Could not write {}: {}", path, e))?;
+
+        Ok(GifRecorder {
+            path: path.to_owned(),
+            encoder,
+            scale,
+            pending: None,
+            carry_cs: 0.0,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Adds a sample of the display, shown for the time given (us).
+    pub fn add_frame(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], duration_us: f64) -> Result<(), String> {
+        if let Some((ref mut pending, ref mut pending_us)) = self.pending {
+            if pending[..] == framebuffer[..] {
+                *pending_us += duration_us;
+                return Ok(());
+            }
+            if (*pending_us / 10000.0 + self.carry_cs).round() < MIN_GIF_DELAY_CS {
+                *pending = *framebuffer;
+                *pending_us += duration_us;
+                return Ok(());
+            }
+        }
+
+        self.write_pending()?;
+        self.pending = Some((*framebuffer, duration_us));
+        Ok(())
+    }
+
+    /// Writes the last frame, finishing the GIF. Returns the number of frames written.
+    pub fn finish(mut self) -> Result<usize, String> {
+        self.write_pending()?;
+        Ok(self.frames)
+    }
+
+    fn write_pending(&mut self) -> Result<(), String> {
+        let (framebuffer, duration_us) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let exact_cs = duration_us / 10000.0 + self.carry_cs;
+        let delay_cs = exact_cs.round().max(MIN_GIF_DELAY_CS).min(u16::max_value() as f64);
+        self.carry_cs = exact_cs - delay_cs;
+
+        let width = HORIZONTAL_RES * self.scale;
+        let mut buffer = vec![0u8; width * VERTICAL_RES * self.scale];
+        for (index, pixel) in buffer.iter_mut().enumerate() {
+            let (x, y) = (index % width / self.scale, index / width / self.scale);
+            *pixel = framebuffer[y * HORIZONTAL_RES + x] as u8;
+        }
+
+        let mut frame = gif::Frame::default();
+        frame.width = width as u16;
+        frame.height = (VERTICAL_RES * self.scale) as u16;
+        frame.delay = delay_cs as u16;
+        frame.buffer = buffer.into();
+        self.encoder.write_frame(&frame).map_err(|e| format!("Could not write {}: {}", self.path, e))?;
+        self.frames += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Raw streams, for piping to external encoders.
+//!
+//! The display is written as a YUV4MPEG2 (y4m) stream at exactly FRAME_RATE
+//! frames per second of emulated time, and the synthesised audio (see audio)
+//! as raw PCM (signed 16 bit little endian, mono, at the configured sample
+//! rate). Both follow emulated time, so they stay in sync however fast the
+//! host runs, eg (at the default 48000 Hz):
+//!
+//! ```sh
+//! ffmpeg -i video.y4m -f s16le -ar 48000 -ac 1 -i audio.pcm output.mp4
+//! ```
+
+use std::io::Write;
+use common::bytes::le_u16;
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+use video;
+use video::palette::Palette;
+use video::filter::{Rgba, scale_nearest};
+
+/// Video frames per second of emulated time.
+pub const FRAME_RATE: u32 = 60;
+
+/// Returns the limited range BT.601 Y'CbCr of the colour, as assumed for y4m streams.
+fn ycbcr(colour: Rgba) -> (u8, u8, u8) {
+    let (r, g, b) = (colour[0] as f32, colour[1] as f32, colour[2] as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    (y.round() as u8, cb.round() as u8, cr.round() as u8)
+}
+
+/// Writes the display as a 4:2:0 y4m stream, in the palette and integer scale given.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    palette: Palette,
+    scale: usize,
+
+    /// Emulated time covered so far (us).
+    elapsed_us: f64,
+
+    frames: u64,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header.
+    pub fn new(mut writer: W, palette: &Palette, scale: usize) -> Result<Y4mWriter<W>, String> {
+        let scale = scale.max(1);
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg", HORIZONTAL_RES * scale, VERTICAL_RES * scale, FRAME_RATE)
+            .map_err(|e| format!("Could not write video stream: {}", e))?;
+        Ok(Y4mWriter {
+            writer,
+            palette: *palette,
+            scale,
+            elapsed_us: 0.0,
+            frames: 0,
+        })
+    }
+
+    /// Returns the number of frames written.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Adds the display, shown for the time given (us). Writes a frame for every frame time within it,
+    /// so displays can be repeated (shown longer than a frame) or skipped (shown for less).
+    pub fn add_frame(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], duration_us: f64) -> Result<(), String> {
+        self.elapsed_us += duration_us;
+
+        // Frame times are compared in frames, with some leeway for rounding in the time step
+        // (eg: 16666.667 us runs give one frame each for over an hour).
+        let end_frame = (self.elapsed_us * FRAME_RATE as f64 / 1e6 - 0.01).ceil() as u64;
+        if self.frames >= end_frame {
+            return Ok(());
+        }
+
+        let frame = self.encode(framebuffer);
+        while self.frames < end_frame {
+            self.writer.write_all(b"FRAME\n").and_then(|_| self.writer.write_all(&frame))
+                .map_err(|e| format!("Could not write video stream: {}", e))?;
+            self.frames += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns the frame as planar Y'CbCr, the chroma planes averaged over each 2x2 block.
+    fn encode(&self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES]) -> Vec<u8> {
+        let image = scale_nearest(&video::image(framebuffer, &self.palette, None), self.scale);
+        let (width, height) = (image.width(), image.height());
+        let pixels: Vec<(u8, u8, u8)> = image.pixels().iter().map(|&pixel| ycbcr(pixel)).collect();
+
+        let mut frame: Vec<u8> = pixels.iter().map(|&(y, _, _)| y).collect();
+        let mut cr_plane = Vec::with_capacity(width * height / 4);
+        for block_y in 0..(height / 2) {
+            for block_x in 0..(width / 2) {
+                let (mut cb, mut cr) = (0u32, 0u32);
+                for &(x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                    let (_, pixel_cb, pixel_cr) = pixels[(block_y * 2 + y) * width + block_x * 2 + x];
+                    cb += pixel_cb as u32;
+                    cr += pixel_cr as u32;
+                }
+                frame.push(((cb + 2) / 4) as u8);
+                cr_plane.push(((cr + 2) / 4) as u8);
+            }
+        }
+        frame.extend(cr_plane);
+        frame
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(|e| format!("Could not write video stream: {}", e))
+    }
+}
+
+/// Writes audio samples as raw PCM.
+pub struct PcmWriter<W: Write> {
+    writer: W,
+    samples: u64,
+}
+
+impl<W: Write> PcmWriter<W> {
+    pub fn new(writer: W) -> PcmWriter<W> {
+        PcmWriter {
+            writer,
+            samples: 0,
+        }
+    }
+
+    /// Returns the number of samples written.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Returns the underlying writer (eg: to fill in a header after the samples).
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Adds the samples (-1 to 1).
+    pub fn add_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            let value = (sample.max(-1.0).min(1.0) * i16::max_value() as f32) as i16;
+            bytes.extend_from_slice(&le_u16(value as u16));
+        }
+        self.samples += samples.len() as u64;
+        self.writer.write_all(&bytes).map_err(|e| format!("Could not write audio stream: {}", e))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(|e| format!("Could not write audio stream: {}", e))
+    }
+}
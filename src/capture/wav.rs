@@ -0,0 +1,75 @@
+//! WAV export of the synthesised audio (see audio).
+//!
+//! The samples are written as raw PCM (see stream::PcmWriter) after a WAV
+//! header, whose sizes are filled in when finished.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use common::bytes::{le_u16, le_u32};
+use capture::stream::PcmWriter;
+
+/// Size of the RIFF and format headers, before the sample data (bytes).
+const HEADER_SIZE: u32 = 44;
+
+/// Writes mono 16 bit PCM WAV files. The header sizes are filled in by finish().
+pub struct WavWriter {
+    path: String,
+    pcm: PcmWriter<BufWriter<File>>,
+}
+
+impl WavWriter {
+    pub fn new(path: &str, sample_rate: u32) -> Result<WavWriter, String> {
+        let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&le_u32(0));
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&le_u32(16));
+        header.extend_from_slice(&le_u16(1)); // PCM
+        header.extend_from_slice(&le_u16(1)); // Mono
+        header.extend_from_slice(&le_u32(sample_rate));
+        header.extend_from_slice(&le_u32(sample_rate * 2));
+        header.extend_from_slice(&le_u16(2));
+        header.extend_from_slice(&le_u16(16));
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&le_u32(0));
+        writer.write_all(&header).map_err(|e| format!("Could not write {}: {}", path, e))?;
+
+        Ok(WavWriter {
+            path: path.to_owned(),
+            pcm: PcmWriter::new(writer),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Adds the samples (-1 to 1).
+    pub fn add_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+        let path = &self.path;
+        self.pcm.add_samples(samples).map_err(|e| format!("{} ({})", e, path))
+    }
+
+    /// Fills in the header sizes, finishing the file. Returns the number of samples written.
+    pub fn finish(mut self) -> Result<u32, String> {
+        let samples = self.pcm.samples().min(u32::max_value() as u64) as u32;
+        let data_size = samples.saturating_mul(2);
+        let riff_size = data_size.saturating_add(HEADER_SIZE - 8);
+        self.write_at(4, riff_size)?;
+        self.write_at(HEADER_SIZE as u64 - 4, data_size)?;
+        self.pcm.get_mut().flush().map_err(|e| format!("Could not write {}: {}", self.path, e))?;
+        Ok(samples)
+    }
+
+    /// Writes a header size at the position given.
+    fn write_at(&mut self, position: u64, value: u32) -> Result<(), String> {
+        let WavWriter { ref path, ref mut pcm } = *self;
+        let writer = pcm.get_mut();
+        writer.seek(SeekFrom::Start(position))
+            .and_then(|_| writer.write_all(&le_u32(value)))
+            .map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+}
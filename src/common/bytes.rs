@@ -0,0 +1,27 @@
+//! Little endian byte conversions, for binary file formats (eg: traces, WAV).
+
+pub fn le_u16(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+pub fn le_u32(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+pub fn le_u64(value: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (index * 8)) as u8;
+    }
+    bytes
+}
+
+/// Reads a u16 from the first 2 bytes.
+pub fn u16_from_le(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) | ((bytes[1] as u16) << 8)
+}
+
+/// Reads a u64 from the first 8 bytes.
+pub fn u64_from_le(bytes: &[u8]) -> u64 {
+    bytes[..8].iter().enumerate().fold(0, |value, (index, &byte)| value | ((byte as u64) << (index * 8)))
+}
@@ -5,7 +5,7 @@ pub mod cpu {
     use common::types::primative::*;
 
     pub const INSTRUCTION_SIZE: usize = mem::size_of::<udword>();
-    pub const INSTRUCTION_COUNT: usize = 35;
+    pub const INSTRUCTION_COUNT: usize = 38;
     pub const CLOCK_SPEED: f64 = 500.0;
     pub const SPRITE_SIZE: usize = 5;
     pub const HORIZONTAL_RES: usize = 64;
@@ -13,8 +13,19 @@ pub mod cpu {
     pub const KEYS_COUNT: usize = 16;
 }
 
+pub mod memory {
+    pub const MEMORY_SIZE: usize = 0x1000;
+    pub const PROGRAM_START: usize = 0x200;
+}
+
 pub mod spu {
+    use common::types::primative::*;
+
     pub const CLOCK_SPEED: f64 = 60.0;    
+    /// XO-CHIP audio pattern size (bytes), played from the most significant bit of the first byte.
+    pub const AUDIO_PATTERN_SIZE: usize = 16;
+    /// XO-CHIP pitch on reset, playing the pattern at 4000 bits per second.
+    pub const DEFAULT_PITCH: uword = 64;
 }
 
 pub mod timer {
@@ -1,2 +1,3 @@
 pub mod types;
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod bytes;
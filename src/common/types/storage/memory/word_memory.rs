@@ -11,10 +11,19 @@ use std::cell::UnsafeCell;
 use common::types::storage::*;
 use common::types::primative::*;
 
+/// Access kind flags, recorded per byte when access tracking is enabled.
+pub const ACCESS_EXECUTE: uword = 0x1;
+pub const ACCESS_SPRITE: uword = 0x2;
+pub const ACCESS_READ: uword = 0x4;
+pub const ACCESS_WRITE: uword = 0x8;
+
 /// Word memory.
 #[derive(Debug)]
 pub struct WordMemory {
     values: UnsafeCell<Vec<uword>>,
+
+    /// Access kind flags per byte, if access tracking is enabled.
+    access_map: UnsafeCell<Option<Vec<uword>>>,
 }
 
 impl WordMemory {
@@ -32,14 +41,21 @@ impl WordMemory {
     pub fn new(size: usize) -> WordMemory {
         WordMemory {
             values: UnsafeCell::new(vec![0; size]),
+            access_map: UnsafeCell::new(None),
         }
     }
 
-    pub fn read_file(&self, offset: usize, path: &str) -> Result<()> {
+    /// Returns the size of the memory (in words).
+    pub fn size(&self) -> usize {
+        unsafe { (*self.values.get()).len() }
+    }
+
+    /// Zeroes the memory. The access map (if enabled) is kept.
+    pub fn clear(&self) {
         unsafe {
-            let mut file = File::open(path)?;
-            file.read(&mut (*self.values.get())[offset..])?;
-            Ok(())
+            for value in (*self.values.get()).iter_mut() {
+                *value = 0;
+            }
         }
     }
 
@@ -50,6 +66,38 @@ impl WordMemory {
             Ok(())
         }
     }
+
+    /// Records an access of the given kind (see ACCESS_*) to the byte at offset.
+    /// Does nothing if access tracking is disabled.
+    pub fn mark_access(&self, offset: usize, kind: uword) {
+        unsafe {
+            if let Some(ref mut access_map) = *self.access_map.get() {
+                if offset < access_map.len() {
+                    access_map[offset] |= kind;
+                }
+            }
+        }
+    }
+
+    /// Returns the access kind flags recorded per byte, if access tracking is enabled.
+    pub fn access_map(&self) -> Option<&[uword]> {
+        unsafe { (*self.access_map.get()).as_ref().map(|access_map| &access_map[..]) }
+    }
+
+    /// Enables access tracking with the given flags per byte, or disables it if None.
+    pub fn set_access_map(&mut self, access_map: Option<Vec<uword>>) {
+        unsafe {
+            *self.access_map.get() = access_map.map(|mut access_map| {
+                access_map.resize((*self.values.get()).len(), 0);
+                access_map
+            });
+        }
+    }
+
+    /// Takes the access kind flags recorded, disabling access tracking.
+    pub fn take_access_map(&mut self) -> Option<Vec<uword>> {
+        unsafe { (*self.access_map.get()).take() }
+    }
 }
 
 impl Storage<uword> for WordMemory {
@@ -8,9 +8,23 @@ pub mod register;
 /// Describes a bus access context, used to implement custom behaviour.
 /// Where the storage is not accessed through a bus, use 'Raw'
 /// (For example, a register attached directly to a CPU.)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum BusContext {
     Raw,
+
+    /// Instruction fetch by the CPU.
+    CpuFetch,
+
+    /// Data access by an executing instruction. Subject to write protection.
+    CpuData,
+
+    /// Host inspection or modification, such as from a debugger.
+    /// Never triggers watchpoints, and ignores write protection.
+    Debugger,
+
+    /// Bulk transfers outside of the CPU, such as loading the font set and rom.
+    /// Ignores write protection.
+    Dma,
 }
 
 /// Trait for read/writing to storage.
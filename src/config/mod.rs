@@ -0,0 +1,457 @@
+//! Configuration file.
+//!
+//! A YAML file (workspace/config/config.yml by default) holding the emulator
+//! settings (see Config) along with the frontend settings: key mapping, audio,
+//! video, pacing and the terminal frontend. Every field is optional, defaulting to Config::default() and
+//! the defaults below. Values can be overridden from the command line as
+//! 'section.field=value' (see ConfigFile::load()).
+//!
+//! Validation errors name the offending field, eg: "emulator.cpu_bias: must
+//! be greater than 0 (was -1)".
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use futures_cpupool::CpuPool;
+use serde_yaml;
+use serde_yaml::{Mapping, Value};
+use Config;
+use audio::{AudioSettings, Waveform};
+use pacing::PacingSettings;
+use common::constants::memory::PROGRAM_START;
+use font::{FontSet, BigFontSet};
+use keymap::{Keymap, KeyBindings};
+use quirks::QuirkOverrides;
+use resources::layout::MemoryLayout;
+use video::palette::{Palette, parse_colour};
+use video::filter::{PostProcess, Scaler, rgba};
+use video::terminal::TextMode;
+
+/// Path of the config file, relative to the workspace.
+pub const CONFIG_PATH: &'static str = "config/config.yml";
+
+/// Emulator settings, see Config. Absent fields keep the Config::default() value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmulatorConfig {
+    pub time_delta_us: Option<f64>,
+
+    /// Runs the controllers on a thread pool.
+    pub multithreaded: Option<bool>,
+
+    /// Overrides the Cpu speed recommended for the rom, if set (see Core::cpu_bias()).
+    pub cpu_bias: Option<f64>,
+    pub spu_bias: Option<f64>,
+    pub timer_bias: Option<f64>,
+
+    /// Font set names, see FontSet::parse() and BigFontSet::parse().
+    pub font: Option<String>,
+    pub big_font: Option<String>,
+    pub font_address: Option<usize>,
+
+    /// Memory layout name, see MemoryLayout::parse().
+    pub memory_layout: Option<String>,
+
+    pub rom_database: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub enabled: bool,
+
+    /// Beep frequency (Hz).
+    pub frequency: f32,
+
+    /// Beep volume (0 to 1).
+    pub volume: f32,
+
+    /// Beep waveform, see audio::WAVEFORM_NAMES.
+    pub waveform: String,
+
+    /// Samples per second.
+    pub sample_rate: u32,
+
+    /// Fade in and out times of the beep (ms), avoiding clicks.
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> AudioConfig {
+        AudioConfig {
+            enabled: true,
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: "square".to_owned(),
+            sample_rate: 48000,
+            attack_ms: 2.0,
+            release_ms: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteConfig {
+    /// Built-in palette, see video::palette::PRESET_NAMES.
+    pub preset: String,
+
+    /// Colours replacing the preset's, as "#RRGGBB" (see video::palette::Palette).
+    pub background: Option<String>,
+    pub foreground: Option<String>,
+    pub foreground2: Option<String>,
+    pub blend: Option<String>,
+}
+
+impl Default for PaletteConfig {
+    fn default() -> PaletteConfig {
+        PaletteConfig {
+            preset: "mono".to_owned(),
+            background: None,
+            foreground: None,
+            foreground2: None,
+            blend: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VideoConfig {
+    /// Initial window size, as a multiple of the display resolution.
+    pub scale: u32,
+
+    pub palette: PaletteConfig,
+
+    /// Post-processing, see video::filter::PostProcess.
+    pub scaler: String,
+    pub scanlines: f32,
+    pub grid: f32,
+
+    /// Phosphor persistence: the fraction of a pixel's brightness kept each frame (1/60 s of
+    /// emulated time) after it is turned off (0 to disable, see video::phosphor).
+    pub phosphor: f32,
+}
+
+impl Default for VideoConfig {
+    fn default() -> VideoConfig {
+        VideoConfig {
+            scale: 12,
+            palette: PaletteConfig::default(),
+            scaler: "nearest".to_owned(),
+            scanlines: 0.0,
+            grid: 0.0,
+            phosphor: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PacingConfig {
+    /// Emulation speed, as a multiple of real time.
+    pub speed: f32,
+
+    /// Speeds while fast-forwarding and in slow motion.
+    pub fast_forward: f32,
+    pub slow_motion: f32,
+
+    /// Most frames skipped when the host can't keep up, before the emulation slows down (see pacing).
+    pub max_frame_skip: u32,
+}
+
+impl Default for PacingConfig {
+    fn default() -> PacingConfig {
+        let settings = PacingSettings::default();
+        PacingConfig {
+            speed: settings.speed as f32,
+            fast_forward: settings.fast_forward as f32,
+            slow_motion: settings.slow_motion as f32,
+            max_frame_skip: settings.max_frame_skip,
+        }
+    }
+}
+
+/// Sound indicators of the terminal frontend, as used in config files.
+pub const SOUND_INDICATOR_NAMES: [&'static str; 3] = ["visual", "bell", "off"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalConfig {
+    /// Characters used to draw the display, see video::terminal::TextMode.
+    pub mode: String,
+
+    /// Draws in the palette colours (needs 24-bit colour support), rather than the terminal's.
+    pub colour: bool,
+
+    /// Time a key stays pressed after it was typed (ms). Terminals don't report key releases,
+    /// so a held key stays pressed through the terminal's key repeat only if this is longer
+    /// than the repeat interval.
+    pub key_hold_ms: u64,
+
+    /// How the sound timer is signalled (see SOUND_INDICATOR_NAMES): an indicator below the
+    /// display, the terminal bell or not at all.
+    pub sound: String,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> TerminalConfig {
+        TerminalConfig {
+            mode: "halfblock".to_owned(),
+            colour: true,
+            key_hold_ms: 150,
+            sound: "visual".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Rom loaded on start.
+    pub rom: Option<String>,
+
+    pub emulator: EmulatorConfig,
+
+    /// Quirks set here override those recommended for the rom (see quirks).
+    pub quirks: QuirkOverrides,
+
+    /// Bindings by Chip8 key (hex digit), eg: "4" => ["Q", "button:x"]. Unlisted keys keep the defaults (see keymap).
+    pub keys: BTreeMap<String, KeyBindings>,
+
+    pub audio: AudioConfig,
+    pub video: VideoConfig,
+    pub pacing: PacingConfig,
+    pub terminal: TerminalConfig,
+}
+
+/// Sets the value at the dotted path within the YAML document, creating sections as needed.
+fn set_value(document: &mut Value, path: &[&str], value: Value) -> Result<(), String> {
+    if let Value::Null = *document {
+        *document = Value::Mapping(Mapping::new());
+    }
+
+    match *document {
+        Value::Mapping(ref mut mapping) => {
+            let key = Value::String(path[0].to_owned());
+            if path.len() == 1 {
+                mapping.insert(key, value);
+                return Ok(());
+            }
+            match mapping.get(&key) {
+                None => { mapping.insert(key.clone(), Value::Null); },
+                Some(&Value::Null) | Some(&Value::Mapping(_)) => {},
+                Some(_) => return Err(format!("{} is not a section", path[0])),
+            }
+            set_value(mapping.get_mut(&key).unwrap(), &path[1..], value)
+        },
+        _ => Err("the document is not a mapping".to_owned()),
+    }
+}
+
+fn check_positive(field: &str, value: Option<f64>) -> Result<(), String> {
+    match value {
+        Some(value) if value.is_nan() || value <= 0.0 || value.is_infinite() => Err(format!("{}: must be greater than 0 (was {})", field, value)),
+        _ => Ok(()),
+    }
+}
+
+fn check_range(field: &str, value: f32, min: f32, max: f32) -> Result<(), String> {
+    if value.is_nan() || value < min || value > max {
+        return Err(format!("{}: must be between {} and {} (was {})", field, min, max, value));
+    }
+    Ok(())
+}
+
+impl ConfigFile {
+    /// Loads the config file, then applies the overrides (each 'section.field=value', the value in YAML syntax).
+    /// A missing file gives the defaults, unless required.
+    pub fn load(path: &str, required: bool, overrides: &[String]) -> Result<ConfigFile, String> {
+        let mut text = String::new();
+        match File::open(path) {
+            Ok(mut file) => { file.read_to_string(&mut text).map_err(|e| format!("Could not read config file {}: {}", path, e))?; },
+            Err(ref e) if e.kind() == ErrorKind::NotFound && !required => {},
+            Err(e) => return Err(format!("Could not read config file {}: {}", path, e)),
+        }
+
+        // Overrides are applied to the document, so they are validated the same way as the file.
+        if !overrides.is_empty() {
+            let mut document: Value = if text.trim().is_empty() {
+                Value::Null
+            } else {
+                serde_yaml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path, e))?
+            };
+
+            for setting in overrides {
+                let (field, value) = match setting.find('=') {
+                    Some(index) => (&setting[..index], &setting[(index + 1)..]),
+                    None => return Err(format!("Invalid config override '{}' (expected section.field=value)", setting)),
+                };
+                let value: Value = serde_yaml::from_str(value).map_err(|e| format!("{}: invalid value: {}", field, e))?;
+                let field_path: Vec<&str> = field.split('.').collect();
+                set_value(&mut document, &field_path, value).map_err(|e| format!("Invalid config override '{}': {}", setting, e))?;
+            }
+
+            text = serde_yaml::to_string(&document).map_err(|e| e.to_string())?;
+        }
+
+        let config_file: ConfigFile = if text.trim().is_empty() {
+            ConfigFile::default()
+        } else {
+            serde_yaml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path, e))?
+        };
+        config_file.validate().map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+        Ok(config_file)
+    }
+
+    /// Checks all fields, returning an error naming the first invalid field.
+    pub fn validate(&self) -> Result<(), String> {
+        self.config()?;
+
+        self.keymap()?;
+
+        self.audio_settings()?;
+
+        if self.video.scale < 1 || self.video.scale > 32 {
+            return Err(format!("video.scale: must be between 1 and 32 (was {})", self.video.scale));
+        }
+        self.palette()?;
+        check_range("video.phosphor", self.video.phosphor, 0.0, 0.95)?;
+        self.post_process()?;
+
+        self.pacing_settings()?;
+
+        TextMode::parse(&self.terminal.mode).map_err(|e| format!("terminal.mode: {}", e))?;
+        if self.terminal.key_hold_ms < 10 || self.terminal.key_hold_ms > 5000 {
+            return Err(format!("terminal.key_hold_ms: must be between 10 and 5000 (was {})", self.terminal.key_hold_ms));
+        }
+        if !SOUND_INDICATOR_NAMES.contains(&self.terminal.sound.as_str()) {
+            return Err(format!("terminal.sound: unknown indicator '{}' (expected one of {})", self.terminal.sound, SOUND_INDICATOR_NAMES.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the default keymap with the keys section applied.
+    pub fn keymap(&self) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        keymap.apply("keys", &self.keys)?;
+        Ok(keymap)
+    }
+
+    /// Returns the palette preset with the configured colours replacing its own.
+    pub fn palette(&self) -> Result<Palette, String> {
+        let config = &self.video.palette;
+        let mut palette = Palette::preset(&config.preset).map_err(|e| format!("video.palette.preset: {}", e))?;
+
+        let colours = [("background", &config.background), ("foreground", &config.foreground), ("foreground2", &config.foreground2), ("blend", &config.blend)];
+        for (index, &(name, colour)) in colours.iter().enumerate() {
+            if let Some(ref colour) = *colour {
+                palette.colours[index] = parse_colour(colour).map_err(|e| format!("video.palette.{}: {}", name, e))?;
+            }
+        }
+        Ok(palette)
+    }
+
+    /// Returns the synthesis settings of the audio section (whether or not it is enabled).
+    pub fn audio_settings(&self) -> Result<AudioSettings, String> {
+        let audio = &self.audio;
+        check_range("audio.frequency", audio.frequency, 20.0, 20000.0)?;
+        check_range("audio.volume", audio.volume, 0.0, 1.0)?;
+        if audio.sample_rate < 8000 || audio.sample_rate > 192000 {
+            return Err(format!("audio.sample_rate: must be between 8000 and 192000 (was {})", audio.sample_rate));
+        }
+        check_range("audio.attack_ms", audio.attack_ms, 0.0, 100.0)?;
+        check_range("audio.release_ms", audio.release_ms, 0.0, 100.0)?;
+        Ok(AudioSettings {
+            frequency: audio.frequency,
+            waveform: Waveform::parse(&audio.waveform).map_err(|e| format!("audio.waveform: {}", e))?,
+            volume: audio.volume,
+            sample_rate: audio.sample_rate,
+            attack_ms: audio.attack_ms,
+            release_ms: audio.release_ms,
+        })
+    }
+
+    /// Returns the post-processing settings, scaling by video.scale.
+    pub fn post_process(&self) -> Result<PostProcess, String> {
+        check_range("video.scanlines", self.video.scanlines, 0.0, 1.0)?;
+        check_range("video.grid", self.video.grid, 0.0, 1.0)?;
+        Ok(PostProcess {
+            scaler: Scaler::parse(&self.video.scaler).map_err(|e| format!("video.scaler: {}", e))?,
+            scale: self.video.scale as usize,
+            scanlines: self.video.scanlines,
+            grid: self.video.grid,
+            border: rgba(self.palette()?.background()),
+        })
+    }
+
+    /// Returns the pacing settings.
+    pub fn pacing_settings(&self) -> Result<PacingSettings, String> {
+        let pacing = &self.pacing;
+        check_range("pacing.speed", pacing.speed, 0.05, 16.0)?;
+        check_range("pacing.fast_forward", pacing.fast_forward, 1.0, 16.0)?;
+        check_range("pacing.slow_motion", pacing.slow_motion, 0.05, 1.0)?;
+        if pacing.max_frame_skip > 30 {
+            return Err(format!("pacing.max_frame_skip: must be between 0 and 30 (was {})", pacing.max_frame_skip));
+        }
+        Ok(PacingSettings {
+            speed: pacing.speed as f64,
+            fast_forward: pacing.fast_forward as f64,
+            slow_motion: pacing.slow_motion as f64,
+            max_frame_skip: pacing.max_frame_skip,
+        })
+    }
+
+    /// Returns the thread pool for the controllers if emulator.multithreaded is set (or, if unset,
+    /// the frontend's default). Spawns the pool's threads, so it is only built where the Core is
+    /// created rather than by config() (which validation runs).
+    pub fn multithreaded_pool(&self, default: bool) -> Option<CpuPool> {
+        if self.emulator.multithreaded.unwrap_or(default) {
+            Some(CpuPool::new_num_cpus())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the emulator config. The host (and the thread pool, see multithreaded_pool()) are
+    /// left for the frontend to set.
+    pub fn config(&self) -> Result<Config, String> {
+        let emulator = &self.emulator;
+        let mut config = Config::default();
+
+        check_positive("emulator.time_delta_us", emulator.time_delta_us)?;
+        check_positive("emulator.cpu_bias", emulator.cpu_bias)?;
+        check_positive("emulator.spu_bias", emulator.spu_bias)?;
+        check_positive("emulator.timer_bias", emulator.timer_bias)?;
+        config.time_delta_us = emulator.time_delta_us.unwrap_or(config.time_delta_us);
+        config.cpu_bias = emulator.cpu_bias;
+        config.spu_bias = emulator.spu_bias.unwrap_or(config.spu_bias);
+        config.timer_bias = emulator.timer_bias.unwrap_or(config.timer_bias);
+
+        if let Some(ref font) = emulator.font {
+            config.font_set = FontSet::parse(font).map_err(|e| format!("emulator.font: {}", e))?;
+        }
+        if let Some(ref big_font) = emulator.big_font {
+            config.big_font_set = BigFontSet::parse(big_font).map_err(|e| format!("emulator.big_font: {}", e))?;
+        }
+        if let Some(font_address) = emulator.font_address {
+            if font_address >= PROGRAM_START {
+                return Err(format!("emulator.font_address: must be below the program area 0x{:X} (was 0x{:X})", PROGRAM_START, font_address));
+            }
+            config.font_address = font_address;
+        }
+
+        if let Some(ref memory_layout) = emulator.memory_layout {
+            config.memory_layout = MemoryLayout::parse(memory_layout).map_err(|e| format!("emulator.memory_layout: {}", e))?;
+        }
+        config.rom_database = emulator.rom_database.unwrap_or(config.rom_database);
+        config.quirks = self.quirks;
+        if self.audio.enabled {
+            config.audio = Some(self.audio_settings()?);
+        }
+
+        Ok(config)
+    }
+}
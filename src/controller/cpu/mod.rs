@@ -4,13 +4,20 @@ use std::sync::mpsc::*;
 use Core;
 use CoreEvent;
 use common::constants::cpu::*;
+use common::constants::memory::MEMORY_SIZE;
 use common::types::primative::*;
 use common::types::storage::*;
 use common::types::storage::register::*;
+use common::types::storage::memory::word_memory::{ACCESS_EXECUTE, ACCESS_SPRITE, ACCESS_READ, ACCESS_WRITE};
 use resources::Resources;
+use resources::layout::{MemoryLayout, VIP_DISPLAY_START};
 use resources::cpu::*;
 use resources::cpu::instruction::*;
 use controller::*;
+use debugger::WatchKind;
+use debugger::StopReason;
+use common::constants::spu::AUDIO_PATTERN_SIZE;
+use font::{BIG_SPRITE_SIZE, big_font_address};
 
 pub struct Cpu<'a> {
     /// Core manager.
@@ -35,32 +42,69 @@ impl<'a> Controller for Cpu<'a> {
                 while amount > 0 {
                     // Aquire resources.
                     let res = self.core().resources()?;
+                    let debugger = self.core().debugger_state();
                     
-                    // If we are halted, don't do anything.
-                    if res.cpu.halted {
+                    // If we are halted or stopped by the debugger, don't do anything.
+                    if res.cpu.halted || debugger.is_stopped() {
                         break;
                     }
 
                     // Grab current instruction value at PC.
                     let pc: uptr = res.cpu.pc.read(BusContext::Raw, 0);
-                    let inst_value: udword = res.memory.read(BusContext::Raw, pc as usize);
+                    let inst_value: udword = res.bus.read_dword(BusContext::CpuFetch, pc as usize);
 
-                    if cfg!(build = "debug") {
-                        debug!("Cpu: pc = 0x{:04X}, inst_value = {:04X}", pc, inst_value);
-                    }                    
+                    // Get instruction details, and check with the debugger if we should stop before executing it.
+                    let inst = Instruction::new(inst_value);
+                    if debugger.before_execute(res, pc, &inst) {
+                        break;
+                    }
+
+                    // A return with an empty stack has nowhere to go, so stop before it (the debugger or gdb
+                    // client can then inspect it).
+                    if inst.mnemonic() == Some("ret") && res.stack_depth() == 0 {
+                        debugger.stop_before_execute(StopReason::StackUnderflow(pc));
+                        break;
+                    }
+
+                    // Snapshot state for the trace, if enabled.
+                    if let Some(ref mut tracer) = *self.core().tracer_state() {
+                        tracer.before_execute(res, pc, inst_value);
+                    }
+
+                    // Check the instruction with the sanitizer, if enabled.
+                    if let Some(ref mut sanitizer) = *self.core().sanitizer_state() {
+                        if let Some(report) = sanitizer.before_execute(res, pc, &inst) {
+                            debugger.stop_before_execute(StopReason::Sanitizer(report));
+                            break;
+                        }
+                    }
+
+                    // Count the instruction for the profile, if enabled.
+                    if let Some(ref mut profiler) = *self.core().profiler_state() {
+                        profiler.before_execute(pc, &inst);
+                    }
+
+                    res.bus.memory().mark_access(pc as usize, ACCESS_EXECUTE);
+                    res.bus.memory().mark_access(pc as usize + 1, ACCESS_EXECUTE);
 
                     // Update PC.
                     res.cpu.pc.write(BusContext::Raw, 0, pc + INSTRUCTION_SIZE as uptr);
 
-                    // Get instruction details.
-                    let inst = Instruction::new(inst_value);
-                    let inst_index = inst.index().ok_or(format!("Cpu encountered unknown instruction 0x{:X}", inst_value))?;
-
                     // Perform instruction.
+                    let inst_index = inst.index().ok_or(format!("Cpu encountered unknown instruction 0x{:X}", inst_value))?;
                     (self.instruction_table[inst_index])(self.core(), res, &inst.raw());
-                    
+
                     // Finished one cycle.
                     amount -= 1;
+                    res.cpu.cycles += 1;
+
+                    if let Some(ref mut tracer) = *self.core().tracer_state() {
+                        tracer.after_execute(res)?;
+                    }
+
+                    if debugger.after_execute(res) {
+                        break;
+                    }
                 }
             },
             ControllerEvent::Input(key, pressed) => {
@@ -89,7 +133,7 @@ impl<'a> Controller for Cpu<'a> {
 
     fn gen_tick_event(&self, time_delta_us: f64) -> Result<(), String> {
         let clock_state = &mut self.core().resources()?.cpu.clock_state;
-        let bias = self.core().config().cpu_bias;
+        let bias = self.core().cpu_bias();
         clock_state.produce(time_delta_us, bias * CLOCK_SPEED);
         let ticks = clock_state.consume_whole();
         self.event_queue_tx.send(ControllerEvent::Tick(ticks as isize)).unwrap();
@@ -140,6 +184,9 @@ impl<'a> Cpu<'a> {
                 Cpu::bcd, 
                 Cpu::save, 
                 Cpu::load,
+                Cpu::bigsprite_i,
+                Cpu::audio_i,
+                Cpu::pitch,
             ],
         }
     }
@@ -148,15 +195,51 @@ impl<'a> Cpu<'a> {
         self.core
     }
 
-    fn cls(_core: &Core, res: &mut Resources, _inst: &RawInstruction) {
-        for pixel in res.cpu.framebuffer.iter_mut() {
-            *pixel = false;
+    /// Reads a word of data memory, notifying the debugger and sanitizer of the access.
+    /// The access kind (see ACCESS_*) is recorded for coverage.
+    fn read_data(core: &Core, res: &mut Resources, addr: usize, kind: uword) -> uword {
+        let addr = addr % res.bus.size();
+        core.debugger_state().on_memory_access(BusContext::CpuData, addr, WatchKind::Read);
+        res.bus.memory().mark_access(addr, kind);
+        if let Some(ref mut sanitizer) = *core.sanitizer_state() {
+            if let Some(report) = sanitizer.on_memory_read(addr) {
+                core.debugger_state().stop_after_execute(StopReason::Sanitizer(report));
+            }
         }
+        res.bus.read(BusContext::CpuData, addr)
+    }
+
+    /// Writes a word of data memory, notifying the debugger, sanitizer and trace recorder of the access.
+    /// Writes blocked by bus write protection are not recorded in the trace.
+    fn write_data(core: &Core, res: &mut Resources, addr: usize, value: uword) {
+        let addr = addr % res.bus.size();
+        core.debugger_state().on_memory_access(BusContext::CpuData, addr, WatchKind::Write);
+        res.bus.memory().mark_access(addr, ACCESS_WRITE);
+        if let Some(ref mut sanitizer) = *core.sanitizer_state() {
+            if let Some(report) = sanitizer.on_memory_write(addr) {
+                core.debugger_state().stop_after_execute(StopReason::Sanitizer(report));
+            }
+        }
+        if res.bus.write(BusContext::CpuData, addr, value) {
+            if let Some(ref mut tracer) = *core.tracer_state() {
+                tracer.on_memory_write(addr, value);
+            }
+            // The display changes when the program writes to it directly (VIP layout).
+            if res.layout == MemoryLayout::Vip && addr >= VIP_DISPLAY_START {
+                core.send_event(CoreEvent::Video);
+            }
+        }
+    }
+
+    fn cls(_core: &Core, res: &mut Resources, _inst: &RawInstruction) {
+        res.clear_framebuffer();
     }
 
     fn ret(_core: &Core, res: &mut Resources, _inst: &RawInstruction) {
-        let ret_pc = res.cpu.stack.pop().unwrap();
-        res.cpu.pc.write(BusContext::Raw, 0, ret_pc);
+        // Returns with an empty stack are stopped before executing (see step()).
+        if let Some(ret_pc) = res.pop_stack() {
+            res.cpu.pc.write(BusContext::Raw, 0, ret_pc);
+        }
     }
 
     fn call_rca1802(_core: &Core, _res: &mut Resources, _inst: &RawInstruction) {
@@ -169,13 +252,13 @@ impl<'a> Cpu<'a> {
 
     fn call(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let pc = res.cpu.pc.read(BusContext::Raw, 0);
-        res.cpu.stack.push(pc);
+        res.push_stack(pc);
         res.cpu.pc.write(BusContext::Raw, 0, inst.address());
     }
 
     fn sifeqi(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
         if value == inst.immediate() {
             let pc: uptr = res.cpu.pc.read(BusContext::Raw, 0);
             res.cpu.pc.write(BusContext::Raw, 0, pc + INSTRUCTION_SIZE as uptr);
@@ -184,7 +267,7 @@ impl<'a> Cpu<'a> {
 
     fn sifnei(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
         if value != inst.immediate() {
             let pc: uptr = res.cpu.pc.read(BusContext::Raw, 0);
             res.cpu.pc.write(BusContext::Raw, 0, pc + INSTRUCTION_SIZE as uptr);
@@ -194,8 +277,8 @@ impl<'a> Cpu<'a> {
     fn sifeq(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
         if x_value == y_value {
             let pc: uptr = res.cpu.pc.read(BusContext::Raw, 0);
             res.cpu.pc.write(BusContext::Raw, 0, pc + INSTRUCTION_SIZE as uptr);
@@ -204,96 +287,107 @@ impl<'a> Cpu<'a> {
 
     fn movi(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, inst.immediate());
+        res.set_gpr(x_index, inst.immediate());
     }
 
     fn addi(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
         let (result, _of) = value.overflowing_add(inst.immediate());
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, result);
+        res.set_gpr(x_index, result);
     }
 
     fn mov(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, value);
+        let value = res.gpr(y_index);
+        res.set_gpr(x_index, value);
     }
 
-    fn or(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn or(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, x_value | y_value);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
+        res.set_gpr(x_index, x_value | y_value);
+        if core.quirks().logic {
+            res.set_gpr(0xF, 0);
+        }
     }
 
-    fn and(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn and(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, x_value & y_value);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
+        res.set_gpr(x_index, x_value & y_value);
+        if core.quirks().logic {
+            res.set_gpr(0xF, 0);
+        }
     }
 
-    fn xor(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn xor(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, x_value ^ y_value);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
+        res.set_gpr(x_index, x_value ^ y_value);
+        if core.quirks().logic {
+            res.set_gpr(0xF, 0);
+        }
     }
 
     fn add(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
         let (result, of) = x_value.overflowing_add(y_value);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, result);
-        res.cpu.gpr[0xF].write(BusContext::Raw, 0, of as uword);
+        res.set_gpr(x_index, result);
+        res.set_gpr(0xF, of as uword);
     }
 
     fn sub(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
         let (result, of) = x_value.overflowing_sub(y_value);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, result);
-        res.cpu.gpr[0xF].write(BusContext::Raw, 0, (!of) as uword);
+        res.set_gpr(x_index, result);
+        res.set_gpr(0xF, (!of) as uword);
     }
 
-    fn shr1(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn shr1(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, value.wrapping_shr(1));
-        res.cpu.gpr[0xF].write(BusContext::Raw, 0, value & 1);
+        let source_index = if core.quirks().shift { x_index } else { inst.y_register() };
+        let value = res.gpr(source_index);
+        res.set_gpr(x_index, value.wrapping_shr(1));
+        res.set_gpr(0xF, value & 1);
     }
 
     fn rsub(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
         let (result, of) = y_value.overflowing_sub(x_value);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, result);
-        res.cpu.gpr[0xF].write(BusContext::Raw, 0, (!of) as uword);
+        res.set_gpr(x_index, result);
+        res.set_gpr(0xF, (!of) as uword);
     }
 
-    fn shl1(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn shl1(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, value.wrapping_shl(1));
-        res.cpu.gpr[0xF].write(BusContext::Raw, 0, value & 0x80);
+        let source_index = if core.quirks().shift { x_index } else { inst.y_register() };
+        let value = res.gpr(source_index);
+        res.set_gpr(x_index, value.wrapping_shl(1));
+        res.set_gpr(0xF, value & 0x80);
     }
 
     fn sifne(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let y_value = res.cpu.gpr[y_index].read(BusContext::Raw, 0);
+        let x_value = res.gpr(x_index);
+        let y_value = res.gpr(y_index);
         if x_value != y_value {
             let pc:uptr = res.cpu.pc.read(BusContext::Raw, 0);
             res.cpu.pc.write(BusContext::Raw, 0, pc + INSTRUCTION_SIZE as uptr);
@@ -305,44 +399,53 @@ impl<'a> Cpu<'a> {
         res.cpu.i.write(BusContext::Raw, 0, addr);
     }
 
-    fn jumpr(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
-        let base = res.cpu.gpr[0x0].read(BusContext::Raw, 0);
+    fn jumpr(core: &Core, res: &mut Resources, inst: &RawInstruction) {
+        let base_index = if core.quirks().jump { inst.x_register() } else { 0x0 };
+        let base = res.gpr(base_index);
         res.cpu.pc.write(BusContext::Raw, 0, base as uptr + inst.address());
     }
 
     fn rand(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let num: u8 = rand::thread_rng().gen();
         let x_index = inst.x_register();
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, num & inst.immediate());
+        res.set_gpr(x_index, num & inst.immediate());
     }
 
     fn draw(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
         let y_index = inst.y_register();
-        let x_coord = res.cpu.gpr[x_index].read(BusContext::Raw, 0) as usize;
-        let y_coord = res.cpu.gpr[y_index].read(BusContext::Raw, 0) as usize;
+        let x_coord = res.gpr(x_index) as usize;
+        let y_coord = res.gpr(y_index) as usize;
         let height = inst.low_nibble();
 
-        res.cpu.gpr[0xF].write(BusContext::Raw, 0, 0);
+        // With the clip quirk, the sprite starts at the wrapped position and is clipped at the screen edges.
+        let clip = core.quirks().clip;
+        let (x_coord, y_coord) = if clip { (x_coord % HORIZONTAL_RES, y_coord % VERTICAL_RES) } else { (x_coord, y_coord) };
+
+        res.set_gpr(0xF, 0);
 
         for line in 0..height {
             let y_coord = y_coord + (line as usize);
+            // Sprites extending past the end of memory wrap around to the start (see sanitizer).
             let addr: uptr = res.cpu.i.read(BusContext::Raw, 0);
-            let row_value: uword = res.memory.read(BusContext::Raw, (addr as usize) + (line as usize));
+            let row_value: uword = Cpu::read_data(core, res, ((addr as usize) + (line as usize)) % MEMORY_SIZE, ACCESS_SPRITE);
             
             for bit in 0..8 {
                 // Calc pixel array position. If the sprite is drawn outside the screen, 
-                // it is wrapped around to the start (see Cowgod's docs).
+                // it is wrapped around to the start (see Cowgod's docs), unless clipped.
                 let x_coord = x_coord + (bit as usize);
+                if clip && (x_coord >= HORIZONTAL_RES || y_coord >= VERTICAL_RES) {
+                    continue;
+                }
                 let px_index = ((y_coord * HORIZONTAL_RES) + x_coord) % (HORIZONTAL_RES * VERTICAL_RES);
 
-                let old_value: bool = res.cpu.framebuffer[px_index];
+                let old_value: bool = res.pixel(px_index);
                 let new_value: bool = (row_value & (0x80 >> bit)) > 0;
 
-                res.cpu.framebuffer[px_index] = new_value ^ old_value;
+                res.set_pixel(px_index, new_value ^ old_value);
 
                 if old_value == true && new_value == true {
-                    res.cpu.gpr[0xF].write(BusContext::Raw, 0, 1);
+                    res.set_gpr(0xF, 1);
                 }
             }
         }
@@ -352,7 +455,7 @@ impl<'a> Cpu<'a> {
 
     fn sifkeq(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let key = res.cpu.gpr[x_index].read(BusContext::Raw, 0) as usize;
+        let key = res.gpr(x_index) as usize;
         let key_value = res.cpu.keys.read_bitfield(BusContext::Raw, 0, KEYS[key]);
 
         if key_value == 1 {
@@ -363,7 +466,7 @@ impl<'a> Cpu<'a> {
 
     fn sifkne(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let key = res.cpu.gpr[x_index].read(BusContext::Raw, 0) as usize;
+        let key = res.gpr(x_index) as usize;
         let key_value = res.cpu.keys.read_bitfield(BusContext::Raw, 0, KEYS[key]);
 
         if key_value == 0 {
@@ -375,14 +478,14 @@ impl<'a> Cpu<'a> {
     fn timerr(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let timer_value = res.timer.counter.read(BusContext::Raw, 0);
         let x_index = inst.x_register();
-        res.cpu.gpr[x_index].write(BusContext::Raw, 0, timer_value);
+        res.set_gpr(x_index, timer_value);
     }
 
     fn keyr(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         match res.cpu.halted_wake_key {
             Some(key) => {
                 let x_index = inst.x_register();
-                res.cpu.gpr[x_index].write(BusContext::Raw, 0, key);
+                res.set_gpr(x_index, key);
                 res.cpu.halted_wake_key = None;
             },
             None => {
@@ -395,61 +498,91 @@ impl<'a> Cpu<'a> {
 
     fn timerw(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
         res.timer.counter.write(BusContext::Raw, 0, value);
     }
 
     fn soundw(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
         res.spu.counter.write(BusContext::Raw, 0, value);
     }
 
     fn add_i(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
         let i_value: udword = res.cpu.i.read(BusContext::Raw, 0);
         res.cpu.i.write(BusContext::Raw, 0, i_value + (value as udword));
     }
 
-    fn sprite_i(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn sprite_i(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
-        let addr = (SPRITE_SIZE * value as usize) as uptr;
+        let value = res.gpr(x_index);
+        let addr = (core.config().font_address + SPRITE_SIZE * value as usize) as uptr;
         res.cpu.i.write(BusContext::Raw, 0, addr as udword);
     }
 
-    fn bcd(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn bigsprite_i(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
-        let value: uword = res.cpu.gpr[x_index].read(BusContext::Raw, 0);
+        let value = res.gpr(x_index);
+        let addr = (big_font_address(core.config().font_address) + BIG_SPRITE_SIZE * (value & 0xF) as usize) as uptr;
+        res.cpu.i.write(BusContext::Raw, 0, addr as udword);
+    }
+
+    fn audio_i(core: &Core, res: &mut Resources, _inst: &RawInstruction) {
+        let addr: uptr = res.cpu.i.read(BusContext::Raw, 0);
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        for (offset, value) in pattern.iter_mut().enumerate() {
+            *value = Cpu::read_data(core, res, addr as usize + offset, ACCESS_READ);
+        }
+        res.spu.pattern.load(pattern);
+    }
+
+    fn pitch(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+        let x_index = inst.x_register();
+        let value = res.gpr(x_index);
+        res.spu.pitch.write(BusContext::Raw, 0, value);
+    }
+
+    fn bcd(core: &Core, res: &mut Resources, inst: &RawInstruction) {
+        let x_index = inst.x_register();
+        let value: uword = res.gpr(x_index);
 
         let hundreds = value / 100;
         let tens = (value % 100) / 10;
         let ones = (value % 10) / 1;
 
         let addr: uptr = res.cpu.i.read(BusContext::Raw, 0);
-        res.memory.write(BusContext::Raw, addr as usize, hundreds);
-        res.memory.write(BusContext::Raw, (addr + 1) as usize, tens);
-        res.memory.write(BusContext::Raw, (addr + 2) as usize, ones);
+        Cpu::write_data(core, res, addr as usize, hundreds);
+        Cpu::write_data(core, res, (addr + 1) as usize, tens);
+        Cpu::write_data(core, res, (addr + 2) as usize, ones);
     }
 
-    fn save(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn save(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
+        let base: udword = res.cpu.i.read(BusContext::Raw, 0);
         for idx in 0..(x_index + 1) {
-            let value = res.cpu.gpr[idx].read(BusContext::Raw, 0);
+            let value = res.gpr(idx);
             let addr: uptr = res.cpu.i.read(BusContext::Raw, 0);
             res.cpu.i.write(BusContext::Raw, 0, (addr as udword) + 1);
-            res.memory.write(BusContext::Raw, addr as usize, value);
+            Cpu::write_data(core, res, addr as usize, value);
+        }
+        if core.quirks().load_store {
+            res.cpu.i.write(BusContext::Raw, 0, base);
         }
     }
 
-    fn load(_core: &Core, res: &mut Resources, inst: &RawInstruction) {
+    fn load(core: &Core, res: &mut Resources, inst: &RawInstruction) {
         let x_index = inst.x_register();
+        let base: udword = res.cpu.i.read(BusContext::Raw, 0);
         for idx in 0..(x_index + 1) {
             let addr: uptr = res.cpu.i.read(BusContext::Raw, 0);
             res.cpu.i.write(BusContext::Raw, 0, (addr as udword) + 1);
-            let value = res.memory.read(BusContext::Raw, addr as usize);
-            res.cpu.gpr[idx].write(BusContext::Raw, 0, value);
+            let value = Cpu::read_data(core, res, addr as usize, ACCESS_READ);
+            res.set_gpr(idx, value);
+        }
+        if core.quirks().load_store {
+            res.cpu.i.write(BusContext::Raw, 0, base);
         }
     }
 }
\ No newline at end of file
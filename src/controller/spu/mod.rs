@@ -48,7 +48,12 @@ impl<'a> Controller for Spu<'a> {
                         let counter = &res.spu.counter;
                         let _guard = counter.scope_guard();
 
+                        // The buzzer sounds for the tick if the counter was non-zero at its start.
                         let value = counter.read(BusContext::Raw, 0);
+                        if let Some(ref mut synth) = *self.core().synth_state() {
+                            synth.set_pattern(res.spu.pattern.pattern().as_ref(), res.spu.pitch.read(BusContext::Raw, 0));
+                            synth.generate(value > 0, 1.0 / (self.core().config().spu_bias * CLOCK_SPEED));
+                        }
                         if value > 0 {
                             counter.write(BusContext::Raw, 0, value - 1);
                         }
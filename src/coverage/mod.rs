@@ -0,0 +1,100 @@
+//! Coverage reports, built from the access kinds recorded per byte of memory
+//! (see Core::enable_coverage()).
+//!
+//! The listing disassembles bytes executed as code and shows the remaining
+//! bytes as data (with sprite data drawn as pixels). The heatmap shows one
+//! character per byte, giving an overview of a whole rom image.
+
+use std::ops::Range;
+use common::constants::cpu::INSTRUCTION_SIZE;
+use common::types::primative::*;
+use common::types::storage::memory::word_memory::{ACCESS_EXECUTE, ACCESS_SPRITE, ACCESS_READ, ACCESS_WRITE};
+use resources::cpu::instruction::Instruction;
+
+/// Runs of untouched bytes at least this long are collapsed into one listing line.
+const UNTOUCHED_COLLAPSE_SIZE: usize = 8;
+
+/// Amount of bytes per heatmap row.
+const HEATMAP_ROW_SIZE: usize = 32;
+
+/// Returns the listing flags column for a byte, eg: "X..W".
+fn flags(access: uword) -> String {
+    [(ACCESS_EXECUTE, 'X'), (ACCESS_SPRITE, 'S'), (ACCESS_READ, 'R'), (ACCESS_WRITE, 'W')].iter()
+        .map(|&(kind, symbol)| if access & kind != 0 { symbol } else { '.' })
+        .collect()
+}
+
+/// Returns the heatmap symbol for a byte.
+fn symbol(access: uword) -> char {
+    match access {
+        0 => '.',
+        ACCESS_EXECUTE => 'X',
+        ACCESS_SPRITE => 'S',
+        ACCESS_READ => 'R',
+        ACCESS_WRITE => 'W',
+        _ => '+',
+    }
+}
+
+/// Returns a summary line of the amount of bytes per access kind within range.
+pub fn summary(access_map: &[uword], range: Range<usize>) -> String {
+    let accesses = &access_map[range.clone()];
+    let count = |kind: uword| accesses.iter().filter(|&&access| access & kind != 0).count();
+    format!("0x{:03X}-0x{:03X} ({} bytes): {} executed, {} sprite data, {} read, {} written, {} untouched",
+        range.start, range.end.saturating_sub(1), accesses.len(),
+        count(ACCESS_EXECUTE), count(ACCESS_SPRITE), count(ACCESS_READ), count(ACCESS_WRITE),
+        accesses.iter().filter(|&&access| access == 0).count())
+}
+
+/// Returns an annotated listing of memory within range.
+pub fn listing(memory: &[uword], access_map: &[uword], range: Range<usize>) -> String {
+    let mut listing = format!("; Coverage of {}\n", summary(access_map, range.clone()));
+    listing.push_str("; Flags: X = executed, S = sprite data (draw), R = read (load), W = written (save/bcd)\n");
+
+    let mut address = range.start;
+    while address < range.end {
+        let access = access_map[address];
+
+        // Bytes executed as code (both bytes of the instruction fetched) are disassembled.
+        if access & ACCESS_EXECUTE != 0 && address + 1 < range.end && access_map[address + 1] & ACCESS_EXECUTE != 0 {
+            let value = ((memory[address] as udword) << 8) | (memory[address + 1] as udword);
+            listing.push_str(&format!("0x{:03X}  {:04X}  {}  {}\n", address, value, flags(access | access_map[address + 1]), Instruction::new(value)));
+            address += INSTRUCTION_SIZE;
+            continue;
+        }
+
+        let untouched = access_map[address..range.end].iter().take_while(|&&access| access == 0).count();
+        if untouched >= UNTOUCHED_COLLAPSE_SIZE {
+            listing.push_str(&format!("0x{:03X}  ; 0x{:03X}-0x{:03X} untouched ({} bytes)\n", address, address, address + untouched - 1, untouched));
+            address += untouched;
+            continue;
+        }
+
+        let value = memory[address];
+        if access & ACCESS_SPRITE != 0 {
+            let pixels: String = (0..8).map(|bit| if value & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+            listing.push_str(&format!("0x{:03X}  {:02X}    {}  db 0x{:02X}  ; {}\n", address, value, flags(access), value, pixels));
+        } else {
+            listing.push_str(&format!("0x{:03X}  {:02X}    {}  db 0x{:02X}\n", address, value, flags(access), value));
+        }
+        address += 1;
+    }
+
+    listing
+}
+
+/// Returns a heatmap of memory within range, one character per byte.
+pub fn heatmap(access_map: &[uword], range: Range<usize>) -> String {
+    let mut heatmap = format!("; Coverage of {}\n", summary(access_map, range.clone()));
+    heatmap.push_str("; . = untouched, X = executed, S = sprite data, R = read, W = written, + = multiple\n");
+
+    let mut address = range.start;
+    while address < range.end {
+        let end = (address + HEATMAP_ROW_SIZE).min(range.end);
+        let row: String = access_map[address..end].iter().map(|&access| symbol(access)).collect();
+        heatmap.push_str(&format!("0x{:03X}  {}\n", address, row));
+        address = end;
+    }
+
+    heatmap
+}
@@ -0,0 +1,245 @@
+//! Breakpoint conditions.
+//!
+//! A condition compares two operands (registers, timers, memory or values),
+//! for example "V3 == 0x10" or "[0x300] != I".
+
+use std::fmt;
+use common::constants::memory::MEMORY_SIZE;
+use common::types::primative::*;
+use common::types::storage::*;
+use resources::Resources;
+use debugger::parse_value;
+
+/// An operand used within a condition.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    /// General purpose register V0-VF.
+    Gpr(usize),
+
+    /// Index register I.
+    I,
+
+    /// Program counter.
+    Pc,
+
+    /// Stack depth.
+    Sp,
+
+    /// Delay timer counter.
+    Dt,
+
+    /// Sound timer counter.
+    St,
+
+    /// Memory word at the given address.
+    Memory(uptr),
+
+    /// Constant value.
+    Value(udword),
+}
+
+impl Operand {
+    /// Parses an operand: V0-VF, I, PC, SP, DT, ST, [address] or a value.
+    pub fn parse(text: &str) -> Result<Operand, String> {
+        let text = text.trim();
+        let upper = text.to_uppercase();
+
+        match upper.as_str() {
+            "I" => return Ok(Operand::I),
+            "PC" => return Ok(Operand::Pc),
+            "SP" => return Ok(Operand::Sp),
+            "DT" => return Ok(Operand::Dt),
+            "ST" => return Ok(Operand::St),
+            _ => {},
+        }
+
+        if upper.len() == 2 && upper.starts_with('V') {
+            if let Ok(index) = usize::from_str_radix(&upper[1..], 16) {
+                return Ok(Operand::Gpr(index));
+            }
+        }
+
+        if upper.starts_with('[') && upper.ends_with(']') {
+            let address = parse_value(&text[1..text.len() - 1])?;
+            if address as usize >= MEMORY_SIZE {
+                return Err(format!("Address 0x{:X} is outside of memory", address));
+            }
+            return Ok(Operand::Memory(address));
+        }
+
+        Ok(Operand::Value(parse_value(text)?))
+    }
+
+    /// Returns the current value of the operand.
+    pub fn evaluate(&self, res: &Resources) -> udword {
+        match *self {
+            Operand::Gpr(index) => {
+                let value: uword = res.gpr(index);
+                value as udword
+            },
+            Operand::I => res.cpu.i.read(BusContext::Raw, 0),
+            Operand::Pc => res.cpu.pc.read(BusContext::Raw, 0),
+            Operand::Sp => res.stack_depth() as udword,
+            Operand::Dt => res.timer.counter.read(BusContext::Raw, 0) as udword,
+            Operand::St => res.spu.counter.read(BusContext::Raw, 0) as udword,
+            Operand::Memory(address) => {
+                let value: uword = res.bus.read(BusContext::Debugger, address as usize);
+                value as udword
+            },
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Gpr(index) => write!(f, "V{:X}", index),
+            Operand::I => write!(f, "I"),
+            Operand::Pc => write!(f, "PC"),
+            Operand::Sp => write!(f, "SP"),
+            Operand::Dt => write!(f, "DT"),
+            Operand::St => write!(f, "ST"),
+            Operand::Memory(address) => write!(f, "[0x{:03X}]", address),
+            Operand::Value(value) => write!(f, "0x{:X}", value),
+        }
+    }
+}
+
+/// Comparison performed between the two condition operands.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Comparison operator symbols, ordered so that two character operators are matched first.
+    const SYMBOLS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+/// A condition attached to a breakpoint, which must hold for the breakpoint to stop execution.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub comparison: Comparison,
+    pub rhs: Operand,
+}
+
+impl Condition {
+    pub fn new(lhs: Operand, comparison: Comparison, rhs: Operand) -> Condition {
+        Condition { lhs, comparison, rhs }
+    }
+
+    /// Parses a condition of the form "<operand> <comparison> <operand>", eg: "V3 == 0x10".
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        for &(symbol, comparison) in Comparison::SYMBOLS.iter() {
+            if let Some(position) = text.find(symbol) {
+                let lhs = Operand::parse(&text[..position])?;
+                let rhs = Operand::parse(&text[position + symbol.len()..])?;
+                return Ok(Condition::new(lhs, comparison, rhs));
+            }
+        }
+
+        Err(format!("No comparison operator found in condition '{}'", text))
+    }
+
+    /// Returns if the condition currently holds.
+    pub fn evaluate(&self, res: &Resources) -> bool {
+        let lhs = self.lhs.evaluate(res);
+        let rhs = self.rhs.evaluate(res);
+        match self.comparison {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.comparison.symbol(), self.rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use resources::bus::Bus;
+    use resources::layout::MemoryLayout;
+
+    #[test]
+    fn parse_operands() {
+        assert_eq!(Operand::parse("v3").unwrap(), Operand::Gpr(3));
+        assert_eq!(Operand::parse(" VF ").unwrap(), Operand::Gpr(0xF));
+        assert_eq!(Operand::parse("pc").unwrap(), Operand::Pc);
+        assert_eq!(Operand::parse("SP").unwrap(), Operand::Sp);
+        assert_eq!(Operand::parse("[0x300]").unwrap(), Operand::Memory(0x300));
+        assert_eq!(Operand::parse("$1F").unwrap(), Operand::Value(0x1F));
+        assert_eq!(Operand::parse("16").unwrap(), Operand::Value(16));
+
+        assert!(Operand::parse("[0x1000]").is_err());
+        assert!(Operand::parse("VG").is_err());
+        assert!(Operand::parse("").is_err());
+    }
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(Condition::parse("V3 == 0x10").unwrap(), Condition::new(Operand::Gpr(3), Comparison::Eq, Operand::Value(0x10)));
+        assert_eq!(Condition::parse("[0x300]!=I").unwrap(), Condition::new(Operand::Memory(0x300), Comparison::Ne, Operand::I));
+
+        // Two character operators aren't split into one character ones.
+        assert_eq!(Condition::parse("DT <= 5").unwrap().comparison, Comparison::Le);
+        assert_eq!(Condition::parse("DT >= 5").unwrap().comparison, Comparison::Ge);
+        assert_eq!(Condition::parse("DT < 5").unwrap().comparison, Comparison::Lt);
+
+        assert!(Condition::parse("V3 0x10").is_err());
+        assert!(Condition::parse("V3 == ").is_err());
+
+        // Displayed conditions parse back to the same condition.
+        let condition = Condition::parse("v1 > [0x2A0]").unwrap();
+        assert_eq!(condition.to_string(), "V1 > [0x2A0]");
+        assert_eq!(Condition::parse(&condition.to_string()).unwrap(), condition);
+    }
+
+    #[test]
+    fn evaluate_conditions() {
+        let mut res = Resources::with_bus(Bus::new(MEMORY_SIZE, 0..0), MemoryLayout::Default);
+        res.set_gpr(3, 0x10);
+        res.cpu.i.write(BusContext::Raw, 0, 0x300 as udword);
+        res.bus.write(BusContext::Raw, 0x300, 0x42);
+        res.push_stack(0x202);
+
+        assert!(Condition::parse("V3 == 0x10").unwrap().evaluate(&res));
+        assert!(!Condition::parse("V3 != 16").unwrap().evaluate(&res));
+        assert!(Condition::parse("I == 0x300").unwrap().evaluate(&res));
+        assert!(Condition::parse("[0x300] > V3").unwrap().evaluate(&res));
+        assert!(Condition::parse("SP <= 1").unwrap().evaluate(&res));
+        assert!(!Condition::parse("SP < 1").unwrap().evaluate(&res));
+    }
+}
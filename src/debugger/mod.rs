@@ -0,0 +1,599 @@
+//! Debugger core.
+//!
+//! Holds the breakpoint, watchpoint and stepping state used to pause emulation.
+//! The Cpu controller consults the debugger before and after each instruction,
+//! and on each data memory access. Once a stop condition is hit, emulation is
+//! paused until resumed through one of the execution control functions, and the
+//! reason is returned from Core::run().
+
+pub mod condition;
+
+use std::fmt;
+use std::mem;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use common::constants::cpu::INSTRUCTION_SIZE;
+use common::types::primative::*;
+use common::types::storage::*;
+use resources::Resources;
+use resources::cpu::instruction::Instruction;
+use resources::cpu::instruction_lookup::{lookup_mnemonic, MNEMONICS};
+use debugger::condition::Condition;
+use sanitizer::Report;
+
+/// Parses a value in either hexadecimal (prefixed with "0x" or "$") or decimal.
+pub fn parse_value(text: &str) -> Result<udword, String> {
+    let text = text.trim();
+    let result = if text.starts_with("0x") || text.starts_with("0X") {
+        udword::from_str_radix(&text[2..], 16)
+    } else if text.starts_with('$') {
+        udword::from_str_radix(&text[1..], 16)
+    } else {
+        text.parse::<udword>()
+    };
+
+    result.map_err(|_| format!("Invalid value '{}'", text))
+}
+
+/// Kind of memory access a watchpoint triggers on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    /// Returns if an access of the given kind (Read or Write) triggers this kind of watchpoint.
+    pub fn matches(&self, access: WatchKind) -> bool {
+        match *self {
+            WatchKind::Access => true,
+            _ => *self == access,
+        }
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+/// A memory watchpoint, covering [address, address + length).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub address: uptr,
+    pub length: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// Returns if the given address is covered by this watchpoint.
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.address as usize && address < (self.address as usize + self.length)
+    }
+}
+
+/// A class of opcodes that execution can be stopped on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OpcodeClass {
+    /// A decoded instruction, by its unique instruction index (see instruction_lookup).
+    Instruction(usize),
+
+    /// Any instruction that could not be decoded.
+    Unknown,
+}
+
+impl OpcodeClass {
+    /// Parses an opcode class from an instruction mnemonic (eg: "draw", "keyr") or "unknown".
+    pub fn parse(text: &str) -> Result<OpcodeClass, String> {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("unknown") {
+            return Ok(OpcodeClass::Unknown);
+        }
+
+        lookup_mnemonic(text)
+            .map(OpcodeClass::Instruction)
+            .ok_or(format!("Unknown opcode class '{}'", text))
+    }
+
+    /// Returns the opcode class of a looked up instruction.
+    pub fn of(inst: &Instruction) -> OpcodeClass {
+        match inst.index() {
+            Some(index) => OpcodeClass::Instruction(index),
+            None => OpcodeClass::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for OpcodeClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpcodeClass::Instruction(index) => write!(f, "{}", MNEMONICS[index]),
+            OpcodeClass::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The reason emulation was stopped by the debugger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// Execution reached a breakpoint, and its condition (if any) held.
+    Breakpoint(uptr),
+
+    /// The instruction at pc accessed a watched memory address.
+    Watchpoint { pc: uptr, address: uptr, kind: WatchKind },
+
+    /// An instruction of a watched opcode class is about to execute at pc.
+    Opcode { pc: uptr, class: OpcodeClass },
+
+    /// A step request completed, stopping at the given pc.
+    Step(uptr),
+
+    /// The host requested a break, stopping at the given pc.
+    Interrupted(uptr),
+
+    /// The sanitizer found a violation (in stop mode).
+    Sanitizer(Report),
+
+    /// The instruction at pc returns from a subroutine with an empty call stack.
+    StackUnderflow(uptr),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StopReason::Breakpoint(pc) => write!(f, "breakpoint at 0x{:03X}", pc),
+            StopReason::Watchpoint { pc, address, kind } => write!(f, "watchpoint {} of 0x{:03X} by instruction at 0x{:03X}", kind, address, pc),
+            StopReason::Opcode { pc, class } => write!(f, "{} instruction at 0x{:03X}", class, pc),
+            StopReason::Step(pc) => write!(f, "step completed at 0x{:03X}", pc),
+            StopReason::Interrupted(pc) => write!(f, "interrupted at 0x{:03X}", pc),
+            StopReason::Sanitizer(ref report) => write!(f, "sanitizer: {}", report),
+            StopReason::StackUnderflow(pc) => write!(f, "return with an empty stack at 0x{:03X}", pc),
+        }
+    }
+}
+
+/// Stepping request, resolved on the first instruction executed after resuming.
+#[derive(Debug, Copy, Clone)]
+enum Step {
+    /// Stop after one instruction.
+    Instruction,
+
+    /// Step over a call. Resolved to the (return pc, stack depth) to stop at.
+    Over(Option<(uptr, usize)>),
+
+    /// Run until the current subroutine returns. Resolved to the stack depth to return from.
+    Out(Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Running,
+    Stepping(Step),
+    Stopped(StopReason),
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: BTreeMap<uptr, Option<Condition>>,
+    watchpoints: Vec<Watchpoint>,
+    opcode_breaks: HashSet<OpcodeClass>,
+    state: State,
+
+    /// Skips breakpoint checks for the first instruction executed after resuming,
+    /// so execution can continue past the breakpoint it stopped at.
+    skip_break: bool,
+
+    /// Host requested a break.
+    interrupt: bool,
+
+    /// Pc of the instruction currently executing.
+    current_pc: uptr,
+
+    /// Watchpoint hit by the instruction currently executing.
+    pending_stop: Option<StopReason>,
+
+    /// Set when execution stops; returned once from Core::run().
+    stop_event: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            watchpoints: Vec::new(),
+            opcode_breaks: HashSet::new(),
+            state: State::Running,
+            skip_break: false,
+            interrupt: false,
+            current_pc: 0,
+            pending_stop: None,
+            stop_event: None,
+        }
+    }
+
+    /// Resets the execution state, called when the core is reset.
+    /// Breakpoints and watchpoints are kept.
+    pub fn reset(&mut self) {
+        self.state = State::Running;
+        self.skip_break = false;
+        self.interrupt = false;
+        self.pending_stop = None;
+        self.stop_event = None;
+    }
+
+    /// Sets a breakpoint at the given address, replacing any existing one.
+    /// When a condition is given, execution only stops if it holds.
+    pub fn set_breakpoint(&mut self, address: uptr, condition: Option<Condition>) {
+        self.breakpoints.insert(address, condition);
+    }
+
+    /// Removes the breakpoint at the given address. Returns false if there was none.
+    pub fn remove_breakpoint(&mut self, address: uptr) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    pub fn breakpoints(&self) -> &BTreeMap<uptr, Option<Condition>> {
+        &self.breakpoints
+    }
+
+    /// Adds a watchpoint over [address, address + length).
+    pub fn add_watchpoint(&mut self, address: uptr, length: usize, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { address, length, kind });
+    }
+
+    /// Removes all watchpoints starting at the given address. Returns false if there were none.
+    pub fn remove_watchpoint(&mut self, address: uptr) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|wp| wp.address != address);
+        self.watchpoints.len() != count
+    }
+
+    /// Removes the watchpoints of the given kind starting at the given address. Returns false if there were none.
+    pub fn remove_watchpoint_of_kind(&mut self, address: uptr, kind: WatchKind) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|wp| wp.address != address || wp.kind != kind);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Enables or disables stopping before instructions of the given opcode class.
+    pub fn set_opcode_break(&mut self, class: OpcodeClass, enabled: bool) {
+        if enabled {
+            self.opcode_breaks.insert(class);
+        } else {
+            self.opcode_breaks.remove(&class);
+        }
+    }
+
+    pub fn opcode_breaks(&self) -> &HashSet<OpcodeClass> {
+        &self.opcode_breaks
+    }
+
+    /// Removes all breakpoints, watchpoints and opcode breaks.
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.opcode_breaks.clear();
+    }
+
+    /// Returns if emulation is currently stopped.
+    pub fn is_stopped(&self) -> bool {
+        match self.state {
+            State::Stopped(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the reason emulation is currently stopped, if it is.
+    pub fn stop_reason(&self) -> Option<&StopReason> {
+        match self.state {
+            State::Stopped(ref reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Resumes execution until the next stop condition.
+    pub fn resume(&mut self) {
+        self.resume_with(State::Running);
+    }
+
+    /// Resumes execution for a single instruction.
+    pub fn step_instruction(&mut self) {
+        self.resume_with(State::Stepping(Step::Instruction));
+    }
+
+    /// Resumes execution for a single instruction, running through any subroutine it calls.
+    pub fn step_over(&mut self) {
+        self.resume_with(State::Stepping(Step::Over(None)));
+    }
+
+    /// Resumes execution until the current subroutine returns.
+    pub fn step_out(&mut self) {
+        self.resume_with(State::Stepping(Step::Out(None)));
+    }
+
+    /// Requests emulation to stop before the next instruction.
+    pub fn interrupt(&mut self) {
+        if !self.is_stopped() {
+            self.interrupt = true;
+        }
+    }
+
+    /// Returns the new stop reason if execution stopped since the last call.
+    /// Used by Core::run().
+    pub fn take_stop_event(&mut self) -> Option<StopReason> {
+        self.stop_event.take()
+    }
+
+    /// Stops on a pending host interrupt that could not be delivered by the Cpu
+    /// (for example when no instructions were executed because it is halted).
+    /// Used by Core::run().
+    pub fn poll_interrupt(&mut self, pc: uptr) {
+        if self.interrupt {
+            self.interrupt = false;
+            self.stop(StopReason::Interrupted(pc));
+        }
+    }
+
+    /// Called by the Cpu controller before executing an instruction.
+    /// Returns true if execution should stop (the instruction is not executed).
+    pub fn before_execute(&mut self, res: &Resources, pc: uptr, inst: &Instruction) -> bool {
+        self.current_pc = pc;
+        let skip_break = mem::replace(&mut self.skip_break, false);
+
+        if self.interrupt {
+            self.interrupt = false;
+            self.stop(StopReason::Interrupted(pc));
+            return true;
+        }
+
+        // Resolve step requests now that the first instruction is known.
+        if let State::Stepping(step) = self.state {
+            let resolved = match step {
+                Step::Over(None) => {
+                    if inst.mnemonic() == Some("call") {
+                        Step::Over(Some((pc + INSTRUCTION_SIZE as uptr, res.stack_depth())))
+                    } else {
+                        Step::Instruction
+                    }
+                },
+                Step::Out(None) => Step::Out(Some(res.stack_depth())),
+                _ => step,
+            };
+            self.state = State::Stepping(resolved);
+        }
+
+        if skip_break {
+            return false;
+        }
+
+        if let Some(condition) = self.breakpoints.get(&pc) {
+            if condition.map_or(true, |c| c.evaluate(res)) {
+                self.stop(StopReason::Breakpoint(pc));
+                return true;
+            }
+        }
+
+        let class = OpcodeClass::of(inst);
+        if self.opcode_breaks.contains(&class) {
+            self.stop(StopReason::Opcode { pc, class });
+            return true;
+        }
+
+        false
+    }
+
+    /// Called by the Cpu controller after executing an instruction.
+    /// Returns true if execution should stop.
+    pub fn after_execute(&mut self, res: &Resources) -> bool {
+        if let Some(reason) = self.pending_stop.take() {
+            self.stop(reason);
+            return true;
+        }
+
+        let step_done = match self.state {
+            State::Stepping(Step::Instruction) => true,
+            State::Stepping(Step::Over(Some((return_pc, depth)))) => {
+                let pc: uptr = res.cpu.pc.read(BusContext::Raw, 0);
+                pc == return_pc && res.stack_depth() == depth
+            },
+            State::Stepping(Step::Out(Some(depth))) => res.stack_depth() < depth,
+            _ => false,
+        };
+
+        if step_done {
+            let pc: uptr = res.cpu.pc.read(BusContext::Raw, 0);
+            self.stop(StopReason::Step(pc));
+        }
+
+        step_done
+    }
+
+    /// Called by the Cpu controller when the executing instruction accesses data memory.
+    /// A hit watchpoint stops execution once the instruction completes.
+    /// Only CPU accesses are watched; debugger and DMA accesses are ignored.
+    pub fn on_memory_access(&mut self, ctx: BusContext, address: usize, access: WatchKind) {
+        match ctx {
+            BusContext::CpuData | BusContext::CpuFetch => {},
+            _ => return,
+        }
+
+        if self.pending_stop.is_some() {
+            return;
+        }
+
+        let hit = self.watchpoints.iter().any(|wp| wp.contains(address) && wp.kind.matches(access));
+        if hit {
+            self.pending_stop = Some(StopReason::Watchpoint { pc: self.current_pc, address: address as uptr, kind: access });
+        }
+    }
+
+    /// Stops execution before the instruction being checked executes.
+    /// Used by checks outside of the debugger, such as the sanitizer.
+    pub fn stop_before_execute(&mut self, reason: StopReason) {
+        self.stop(reason);
+    }
+
+    /// Stops execution once the instruction currently executing completes.
+    /// Used by checks outside of the debugger, such as the sanitizer.
+    pub fn stop_after_execute(&mut self, reason: StopReason) {
+        if self.pending_stop.is_none() {
+            self.pending_stop = Some(reason);
+        }
+    }
+
+    fn resume_with(&mut self, state: State) {
+        self.skip_break = self.is_stopped();
+        self.state = state;
+        self.pending_stop = None;
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.state = State::Stopped(reason.clone());
+        self.stop_event = Some(reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::constants::memory::MEMORY_SIZE;
+    use debugger::condition::Condition;
+    use resources::bus::Bus;
+    use resources::layout::MemoryLayout;
+
+    fn resources() -> Resources {
+        Resources::with_bus(Bus::new(MEMORY_SIZE, 0..0), MemoryLayout::Default)
+    }
+
+    /// Executes the instruction at pc as far as the debugger sees it, moving the pc to next.
+    /// Returns true if the debugger stopped (before or after it).
+    fn execute(debugger: &mut Debugger, res: &mut Resources, pc: uptr, opcode: udword, next: uptr) -> bool {
+        if debugger.before_execute(res, pc, &Instruction::new(opcode)) {
+            return true;
+        }
+        res.cpu.pc.write(BusContext::Raw, 0, next);
+        debugger.after_execute(res)
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse_value("0x2A4"), Ok(0x2A4));
+        assert_eq!(parse_value("$ff"), Ok(0xFF));
+        assert_eq!(parse_value(" 42 "), Ok(42));
+        assert!(parse_value("0x10000").is_err());
+        assert!(parse_value("2A4").is_err());
+    }
+
+    #[test]
+    fn opcode_classes() {
+        let draw = OpcodeClass::parse("draw").unwrap();
+        assert_eq!(OpcodeClass::of(&Instruction::new(0xD125)), draw);
+        assert_eq!(draw.to_string(), "draw");
+        assert_eq!(OpcodeClass::parse("UNKNOWN").unwrap(), OpcodeClass::Unknown);
+        assert!(OpcodeClass::parse("jump_nowhere").is_err());
+    }
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut res = resources();
+        let mut debugger = Debugger::new();
+        debugger.set_breakpoint(0x204, Some(Condition::parse("V0 == 2").unwrap()));
+
+        res.set_gpr(0, 1);
+        assert!(!execute(&mut debugger, &mut res, 0x204, 0x7001, 0x206));
+
+        res.set_gpr(0, 2);
+        assert!(execute(&mut debugger, &mut res, 0x204, 0x7001, 0x206));
+        assert_eq!(debugger.take_stop_event(), Some(StopReason::Breakpoint(0x204)));
+        assert!(debugger.take_stop_event().is_none());
+
+        // Resuming runs past the breakpoint it stopped at.
+        debugger.resume();
+        assert!(!execute(&mut debugger, &mut res, 0x204, 0x7001, 0x206));
+        assert!(!debugger.is_stopped());
+
+        assert!(debugger.remove_breakpoint(0x204));
+        assert!(!debugger.remove_breakpoint(0x204));
+    }
+
+    #[test]
+    fn watchpoints_by_kind_and_context() {
+        let res = resources();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(0x300, 2, WatchKind::Write);
+        debugger.add_watchpoint(0x300, 1, WatchKind::Read);
+
+        // Debugger accesses and accesses of other kinds or addresses are ignored.
+        debugger.before_execute(&res, 0x200, &Instruction::new(0xF055));
+        debugger.on_memory_access(BusContext::Debugger, 0x300, WatchKind::Write);
+        debugger.on_memory_access(BusContext::CpuData, 0x301, WatchKind::Read);
+        debugger.on_memory_access(BusContext::CpuData, 0x302, WatchKind::Write);
+        assert!(!debugger.after_execute(&res));
+
+        debugger.before_execute(&res, 0x202, &Instruction::new(0xF055));
+        debugger.on_memory_access(BusContext::CpuData, 0x301, WatchKind::Write);
+        res.cpu.pc.write(BusContext::Raw, 0, 0x204 as uptr);
+        assert!(debugger.after_execute(&res));
+        assert_eq!(debugger.stop_reason(), Some(&StopReason::Watchpoint { pc: 0x202, address: 0x301, kind: WatchKind::Write }));
+
+        // Only the kind given is removed.
+        assert!(debugger.remove_watchpoint_of_kind(0x300, WatchKind::Read));
+        assert_eq!(debugger.watchpoints(), &[Watchpoint { address: 0x300, length: 2, kind: WatchKind::Write }]);
+        assert!(!debugger.remove_watchpoint_of_kind(0x300, WatchKind::Read));
+        assert!(debugger.remove_watchpoint(0x300));
+        assert!(debugger.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn opcode_break() {
+        let mut res = resources();
+        let mut debugger = Debugger::new();
+        debugger.set_opcode_break(OpcodeClass::parse("draw").unwrap(), true);
+
+        assert!(!execute(&mut debugger, &mut res, 0x200, 0x00E0, 0x202));
+        assert!(execute(&mut debugger, &mut res, 0x202, 0xD125, 0x204));
+        assert_eq!(debugger.take_stop_event(), Some(StopReason::Opcode { pc: 0x202, class: OpcodeClass::parse("draw").unwrap() }));
+    }
+
+    #[test]
+    fn stepping() {
+        let mut res = resources();
+        let mut debugger = Debugger::new();
+        debugger.interrupt();
+        assert!(execute(&mut debugger, &mut res, 0x200, 0x00E0, 0x202));
+        assert_eq!(debugger.take_stop_event(), Some(StopReason::Interrupted(0x200)));
+
+        debugger.step_instruction();
+        assert!(execute(&mut debugger, &mut res, 0x200, 0x00E0, 0x202));
+        assert_eq!(debugger.take_stop_event(), Some(StopReason::Step(0x202)));
+
+        // Stepping over a call runs until it returns.
+        debugger.step_over();
+        assert!(!debugger.before_execute(&res, 0x202, &Instruction::new(0x2300)));
+        res.push_stack(0x204);
+        res.cpu.pc.write(BusContext::Raw, 0, 0x300 as uptr);
+        assert!(!debugger.after_execute(&res));
+        assert!(!execute(&mut debugger, &mut res, 0x300, 0x00E0, 0x302));
+        res.pop_stack();
+        assert!(execute(&mut debugger, &mut res, 0x302, 0x00EE, 0x204));
+        assert_eq!(debugger.take_stop_event(), Some(StopReason::Step(0x204)));
+
+        // Stepping out runs until the stack is shallower than when stepping.
+        res.push_stack(0x206);
+        debugger.step_out();
+        assert!(!execute(&mut debugger, &mut res, 0x300, 0x00E0, 0x302));
+        res.pop_stack();
+        assert!(execute(&mut debugger, &mut res, 0x302, 0x00EE, 0x206));
+        assert_eq!(debugger.take_stop_event(), Some(StopReason::Step(0x206)));
+    }
+}
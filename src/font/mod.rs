@@ -0,0 +1,243 @@
+//! Built-in font sets.
+//!
+//! Different interpreters used different glyph shapes, which some roms depend
+//! on. The small font (16 glyphs of 4x5 pixels, used by FX29) and the big font
+//! (8x10 pixel glyphs, used by FX30) can be selected in the Config, or loaded
+//! from a file in the workspace fonts folder.
+//!
+//! The big font is placed directly after the small font, as on the SCHIP.
+
+use std::fs::File;
+use std::io::Read;
+use common::constants::cpu::SPRITE_SIZE;
+use common::types::primative::*;
+
+/// Size of a big font glyph (bytes).
+pub const BIG_SPRITE_SIZE: usize = 10;
+
+/// Size of the small font (bytes).
+pub const SMALL_FONT_SIZE: usize = 16 * SPRITE_SIZE;
+
+/// Maximum size of the big font (bytes).
+pub const BIG_FONT_SIZE: usize = 16 * BIG_SPRITE_SIZE;
+
+/// Small (4x5) font set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontSet {
+    /// The common font found in most documentation and interpreters.
+    Default,
+
+    /// The COSMAC VIP interpreter font.
+    Vip,
+
+    Dream6800,
+    Eti660,
+    FishNChips,
+
+    /// Loaded from a file within workspace/fonts (80 bytes).
+    Custom(String),
+}
+
+/// Big (8x10) font set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BigFontSet {
+    None,
+
+    /// The SCHIP 1.1 font (digits 0-9 only).
+    Schip,
+
+    /// The SCHIP font shapes, extended with hex digits A-F (as used by Octo).
+    SchipHex,
+
+    /// Loaded from a file within workspace/fonts (100 or 160 bytes).
+    Custom(String),
+}
+
+impl FontSet {
+    /// Parses a font set name, or 'custom:<file>' for a workspace font file.
+    pub fn parse(name: &str) -> Result<FontSet, String> {
+        match name {
+            "default" => Ok(FontSet::Default),
+            "vip" => Ok(FontSet::Vip),
+            "dream6800" => Ok(FontSet::Dream6800),
+            "eti660" => Ok(FontSet::Eti660),
+            "fishnchips" => Ok(FontSet::FishNChips),
+            _ if name.starts_with("custom:") => Ok(FontSet::Custom(name["custom:".len()..].to_owned())),
+            _ => Err(format!("Unknown font set '{}' (expected default, vip, dream6800, eti660, fishnchips or custom:<file>)", name)),
+        }
+    }
+
+    /// Returns the font data, reading custom fonts from the workspace.
+    pub fn data(&self, workspace_path: &str) -> Result<Vec<uword>, String> {
+        match *self {
+            FontSet::Default => Ok(FONT_DEFAULT.to_vec()),
+            FontSet::Vip => Ok(FONT_VIP.to_vec()),
+            FontSet::Dream6800 => Ok(FONT_DREAM6800.to_vec()),
+            FontSet::Eti660 => Ok(FONT_ETI660.to_vec()),
+            FontSet::FishNChips => Ok(FONT_FISHNCHIPS.to_vec()),
+            FontSet::Custom(ref file) => {
+                let data = read_font_file(workspace_path, file)?;
+                if data.len() != SMALL_FONT_SIZE {
+                    return Err(format!("Font file {} must be {} bytes (was {})", file, SMALL_FONT_SIZE, data.len()));
+                }
+                Ok(data)
+            },
+        }
+    }
+}
+
+impl BigFontSet {
+    /// Parses a big font set name, or 'custom:<file>' for a workspace font file.
+    pub fn parse(name: &str) -> Result<BigFontSet, String> {
+        match name {
+            "none" => Ok(BigFontSet::None),
+            "schip" => Ok(BigFontSet::Schip),
+            "schiphex" => Ok(BigFontSet::SchipHex),
+            _ if name.starts_with("custom:") => Ok(BigFontSet::Custom(name["custom:".len()..].to_owned())),
+            _ => Err(format!("Unknown big font set '{}' (expected none, schip, schiphex or custom:<file>)", name)),
+        }
+    }
+
+    /// Returns the font data, reading custom fonts from the workspace.
+    pub fn data(&self, workspace_path: &str) -> Result<Vec<uword>, String> {
+        match *self {
+            BigFontSet::None => Ok(Vec::new()),
+            BigFontSet::Schip => Ok(BIG_FONT_SCHIP.to_vec()),
+            BigFontSet::SchipHex => {
+                let mut data = BIG_FONT_SCHIP.to_vec();
+                data.extend_from_slice(&BIG_FONT_HEX);
+                Ok(data)
+            },
+            BigFontSet::Custom(ref file) => {
+                let data = read_font_file(workspace_path, file)?;
+                if data.len() != 10 * BIG_SPRITE_SIZE && data.len() != BIG_FONT_SIZE {
+                    return Err(format!("Big font file {} must be {} or {} bytes (was {})", file, 10 * BIG_SPRITE_SIZE, BIG_FONT_SIZE, data.len()));
+                }
+                Ok(data)
+            },
+        }
+    }
+}
+
+/// Returns the address of the big font, given the small font address.
+pub fn big_font_address(font_address: usize) -> usize {
+    font_address + SMALL_FONT_SIZE
+}
+
+/// Returns the 4x5 glyph of a character for host-side text (eg: the rom browser).
+/// Hex digits use the default font, other letters and a few symbols use glyphs in the same style.
+/// Letters are case insensitive; unsupported characters give '?'.
+pub fn text_glyph(character: char) -> &'static [uword] {
+    let character = match character {
+        'a'...'z' => (character as u8 - b'a' + b'A') as char,
+        _ => character,
+    };
+    if let Some(digit) = character.to_digit(16) {
+        let offset = digit as usize * SPRITE_SIZE;
+        return &FONT_DEFAULT[offset..(offset + SPRITE_SIZE)];
+    }
+
+    let index = match character {
+        'G'...'Z' => character as usize - 'G' as usize,
+        ' ' => 20,
+        '.' => 21,
+        '-' => 22,
+        '_' => 23,
+        _ => 24,
+    };
+    &TEXT_FONT[(index * SPRITE_SIZE)..((index + 1) * SPRITE_SIZE)]
+}
+
+fn read_font_file(workspace_path: &str, file: &str) -> Result<Vec<uword>, String> {
+    let path = format!("{}fonts/{}", workspace_path, file);
+    let mut data = Vec::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(|e| format!("Could not read font file {}: {}", path, e))?;
+    Ok(data)
+}
+
+static FONT_DEFAULT: [uword; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0,
+    0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40,
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0,
+    0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+static FONT_VIP: [uword; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0,
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10,
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0,
+    0xF0, 0x80, 0x80, 0x80, 0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+static FONT_DREAM6800: [uword; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0, 0x20, 0xE0, 0x20, 0xE0,
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20,
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0, 0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0,
+    0xE0, 0x80, 0x80, 0x80, 0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+static FONT_ETI660: [uword; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0x20, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0, 0x20, 0xE0, 0x20, 0xE0,
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20,
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0, 0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0x80, 0x80, 0xE0, 0xA0, 0xE0,
+    0xE0, 0x80, 0x80, 0x80, 0xE0, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+static FONT_FISHNCHIPS: [uword; SMALL_FONT_SIZE] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, 0x40, 0xC0, 0x40, 0x40, 0xE0, 0xC0, 0x20, 0x40, 0x80, 0xE0, 0xC0, 0x20, 0x40, 0x20, 0xC0,
+    0x20, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xC0, 0x20, 0xC0, 0x40, 0x80, 0xC0, 0xA0, 0x40, 0xE0, 0x20, 0x60, 0x40, 0x40,
+    0x40, 0xA0, 0x40, 0xA0, 0x40, 0x40, 0xA0, 0x60, 0x20, 0x40, 0x40, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xC0, 0xA0, 0xC0,
+    0x60, 0x80, 0x80, 0x80, 0x60, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xC0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+static BIG_FONT_SCHIP: [uword; 10 * BIG_SPRITE_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+static BIG_FONT_HEX: [uword; 6 * BIG_SPRITE_SIZE] = [
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+static TEXT_FONT: [uword; 25 * SPRITE_SIZE] = [
+    0xF0, 0x80, 0xB0, 0x90, 0xF0, // G
+    0x90, 0x90, 0xF0, 0x90, 0x90, // H
+    0xE0, 0x40, 0x40, 0x40, 0xE0, // I
+    0x10, 0x10, 0x10, 0x90, 0xF0, // J
+    0x90, 0xA0, 0xC0, 0xA0, 0x90, // K
+    0x80, 0x80, 0x80, 0x80, 0xF0, // L
+    0x90, 0xF0, 0xF0, 0x90, 0x90, // M
+    0x90, 0xD0, 0xB0, 0x90, 0x90, // N
+    0x60, 0x90, 0x90, 0x90, 0x60, // O
+    0xF0, 0x90, 0xF0, 0x80, 0x80, // P
+    0xF0, 0x90, 0x90, 0xB0, 0xF0, // Q
+    0xF0, 0x90, 0xF0, 0xA0, 0x90, // R
+    0x70, 0x80, 0x60, 0x10, 0xE0, // S
+    0xE0, 0x40, 0x40, 0x40, 0x40, // T
+    0x90, 0x90, 0x90, 0x90, 0xF0, // U
+    0x90, 0x90, 0x90, 0xA0, 0x40, // V
+    0x90, 0x90, 0xF0, 0xF0, 0x90, // W
+    0x90, 0x90, 0x60, 0x90, 0x90, // X
+    0xA0, 0xA0, 0x40, 0x40, 0x40, // Y
+    0xF0, 0x10, 0x60, 0x80, 0xF0, // Z
+    0x00, 0x00, 0x00, 0x00, 0x00, // ' '
+    0x00, 0x00, 0x00, 0x00, 0x40, // .
+    0x00, 0x00, 0xF0, 0x00, 0x00, // -
+    0x00, 0x00, 0x00, 0x00, 0xF0, // _
+    0xE0, 0x10, 0x60, 0x00, 0x40, // ?
+];
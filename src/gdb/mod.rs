@@ -0,0 +1,548 @@
+//! GDB remote serial protocol stub.
+//!
+//! Listens on localhost for a single GDB-protocol client, and services it
+//! without blocking through poll(), which the host calls from its main loop.
+//! Stop replies are sent through notify_stop() when Core::run() returns a
+//! stop reason.
+//!
+//! Registers are numbered V0-VF (0-15, 8-bit), I (16, 16-bit), PC (17, 16-bit)
+//! and SP (18, 8-bit stack depth, read-only), described by the target.xml
+//! feature document. 16-bit registers are sent little endian, as expected by
+//! clients that do not know the CHIP8 architecture. Memory is sent as is.
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use Core;
+use common::constants::memory::MEMORY_SIZE;
+use common::types::primative::*;
+use debugger::{StopReason, WatchKind};
+
+/// Target description, served through qXfer:features:read.
+const TARGET_XML: &'static str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Register numbers (see module documentation).
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_COUNT: usize = 19;
+
+/// Size of all registers in a 'g' packet (bytes).
+const REGISTERS_SIZE: usize = 16 + 2 + 2 + 1;
+
+/// Signal numbers reported in stop replies.
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+struct Connection {
+    stream: TcpStream,
+
+    /// Received data not yet parsed into packets.
+    buffer: Vec<u8>,
+
+    /// Set once the client sent QStartNoAckMode.
+    no_ack: bool,
+
+    /// Set while the client waits for a stop reply (after 'c' or 's').
+    running: bool,
+}
+
+pub struct GdbStub {
+    listener: TcpListener,
+    connection: Option<Connection>,
+}
+
+impl GdbStub {
+    /// Starts listening on localhost at the given port.
+    pub fn bind(port: u16) -> Result<GdbStub, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("Could not listen on port {}: {}", port, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        info!("Gdb stub listening on 127.0.0.1:{}", port);
+
+        Ok(GdbStub {
+            listener,
+            connection: None,
+        })
+    }
+
+    /// Accepts a client if none is connected, and handles any packets received.
+    /// Never blocks; call from the host main loop.
+    pub fn poll(&mut self, core: &mut Core) -> Result<(), String> {
+        if self.connection.is_none() {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    info!("Gdb client connected from {}", address);
+                    stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+                    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+                    self.connection = Some(Connection {
+                        stream,
+                        buffer: Vec::new(),
+                        no_ack: false,
+                        running: false,
+                    });
+
+                    // Clients expect the target to be stopped once attached.
+                    core.debugger().interrupt();
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+
+        let closed = self.receive()?;
+
+        while let Some(packet) = self.next_packet()? {
+            if !self.handle_packet(core, &packet)? {
+                self.connection = None;
+                return Ok(());
+            }
+        }
+
+        if closed {
+            info!("Gdb client disconnected");
+            self.connection = None;
+            core.debugger().resume();
+        }
+
+        Ok(())
+    }
+
+    /// Sends a stop reply to the client if it is waiting for one.
+    pub fn notify_stop(&mut self, reason: &StopReason) -> Result<(), String> {
+        let waiting = match self.connection {
+            Some(ref conn) => conn.running,
+            None => false,
+        };
+
+        if waiting {
+            self.connection.as_mut().unwrap().running = false;
+            let reply = GdbStub::stop_reply(Some(reason));
+            self.send_packet(&reply)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads all available data into the connection buffer. Returns true if the connection was closed.
+    fn receive(&mut self) -> Result<bool, String> {
+        let conn = self.connection.as_mut().unwrap();
+        let mut data = [0u8; 1024];
+        loop {
+            match conn.stream.read(&mut data) {
+                Ok(0) => return Ok(true),
+                Ok(count) => conn.buffer.extend_from_slice(&data[..count]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(ref e) if e.kind() == ErrorKind::ConnectionReset => return Ok(true),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    /// Extracts the next complete packet from the buffer, acknowledging it.
+    /// Packets with a bad checksum are dropped and NAKed, so the client sends them again.
+    /// An interrupt request (0x03) is returned as the packet "\x03".
+    fn next_packet(&mut self) -> Result<Option<String>, String> {
+        loop {
+            let (packet, valid) = match self.take_packet() {
+                Some(packet) => packet,
+                None => return Ok(None),
+            };
+
+            if !self.connection.as_ref().unwrap().no_ack {
+                self.send_raw(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(packet));
+            }
+            warn!("Gdb stub: dropped packet with bad checksum '{}'", packet);
+        }
+    }
+
+    /// Removes the next complete packet from the buffer, returning it and whether its checksum is valid.
+    fn take_packet(&mut self) -> Option<(String, bool)> {
+        let conn = match self.connection {
+            Some(ref mut conn) => conn,
+            None => return None,
+        };
+
+        // Skip acknowledgements and anything before the start of a packet.
+        let start = conn.buffer.iter().position(|&b| b == b'$' || b == 0x03).unwrap_or(conn.buffer.len());
+        conn.buffer.drain(..start);
+
+        if conn.buffer.is_empty() {
+            return None;
+        }
+
+        if conn.buffer[0] == 0x03 {
+            conn.buffer.drain(..1);
+            return Some(("\x03".to_owned(), true));
+        }
+
+        // Packet format: $<data>#<2 digit checksum>, the checksum being the sum of the data bytes.
+        let end = match conn.buffer.iter().position(|&b| b == b'#') {
+            Some(end) if conn.buffer.len() >= end + 3 => end,
+            _ => return None,
+        };
+
+        let data: Vec<u8> = conn.buffer[1..end].to_vec();
+        let checksum = String::from_utf8_lossy(&conn.buffer[(end + 1)..(end + 3)]).into_owned();
+        conn.buffer.drain(..end + 3);
+
+        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let valid = u8::from_str_radix(&checksum, 16).ok() == Some(sum);
+        Some((String::from_utf8_lossy(&data).into_owned(), valid))
+    }
+
+    /// Handles a packet, sending the reply. Returns false if the connection should be closed.
+    fn handle_packet(&mut self, core: &mut Core, packet: &str) -> Result<bool, String> {
+        debug!("Gdb stub: received packet '{}'", packet);
+
+        // Packets are parsed by byte offset, and no supported packet carries binary data.
+        if !packet.bytes().all(|b| b < 0x80) {
+            self.send_packet("E01")?;
+            return Ok(true);
+        }
+
+        let reply = match packet.chars().next().unwrap_or(' ') {
+            '\x03' => {
+                core.debugger().interrupt();
+                return Ok(true);
+            },
+            '?' => {
+                GdbStub::stop_reply(core.debugger().stop_reason())
+            },
+            'g' => {
+                let registers = GdbStub::read_registers(core)?;
+                to_hex(&registers)
+            },
+            'G' => {
+                match from_hex(&packet[1..]) {
+                    Some(ref registers) if registers.len() == REGISTERS_SIZE => {
+                        GdbStub::write_registers_reply(core, registers)
+                    },
+                    _ => "E01".to_owned(),
+                }
+            },
+            'p' => {
+                match usize::from_str_radix(&packet[1..], 16) {
+                    Ok(number) if number < REG_COUNT => {
+                        let registers = GdbStub::read_registers(core)?;
+                        let (offset, size) = GdbStub::register_range(number);
+                        to_hex(&registers[offset..offset + size])
+                    },
+                    _ => "E01".to_owned(),
+                }
+            },
+            'P' => {
+                let mut parts = packet[1..].splitn(2, '=');
+                let number = parts.next().and_then(|n| usize::from_str_radix(n, 16).ok());
+                let value = parts.next().and_then(from_hex);
+                match (number, value) {
+                    (Some(number), Some(value)) if number < REG_COUNT => {
+                        let mut registers = GdbStub::read_registers(core)?;
+                        let (offset, size) = GdbStub::register_range(number);
+                        if value.len() == size {
+                            registers[offset..offset + size].copy_from_slice(&value);
+                            GdbStub::write_registers_reply(core, &registers)
+                        } else {
+                            "E01".to_owned()
+                        }
+                    },
+                    _ => "E01".to_owned(),
+                }
+            },
+            'm' => {
+                match parse_address_length(&packet[1..]) {
+                    Some((address, length)) => {
+                        let mut values = vec![0; length.min(MEMORY_SIZE)];
+                        match core.read_memory(address, &mut values) {
+                            Ok(_) => to_hex(&values),
+                            Err(_) => "E01".to_owned(),
+                        }
+                    },
+                    None => "E01".to_owned(),
+                }
+            },
+            'M' => {
+                let mut parts = packet[1..].splitn(2, ':');
+                let range = parts.next().and_then(parse_address_length);
+                let values = parts.next().and_then(from_hex);
+                match (range, values) {
+                    (Some((address, length)), Some(values)) if values.len() == length => {
+                        match core.write_memory(address, &values) {
+                            Ok(_) => "OK".to_owned(),
+                            Err(_) => "E01".to_owned(),
+                        }
+                    },
+                    _ => "E01".to_owned(),
+                }
+            },
+            'Z' | 'z' => {
+                GdbStub::handle_breakpoint(core, packet)
+            },
+            'c' | 's' => {
+                if packet.len() > 1 {
+                    let result = u16::from_str_radix(&packet[1..], 16).map_err(|e| e.to_string())
+                        .and_then(|address| core.set_pc(address));
+                    if let Err(e) = result {
+                        warn!("Gdb stub: can't resume at '{}': {}", &packet[1..], e);
+                        self.send_packet("E01")?;
+                        return Ok(true);
+                    }
+                }
+
+                if packet.starts_with('c') {
+                    core.debugger().resume();
+                } else {
+                    core.debugger().step_instruction();
+                }
+
+                // Reply is sent on stop, see notify_stop().
+                self.connection.as_mut().unwrap().running = true;
+                return Ok(true);
+            },
+            'D' => {
+                self.send_packet("OK")?;
+                core.debugger().resume();
+                info!("Gdb client detached");
+                return Ok(false);
+            },
+            'k' => {
+                // The emulator keeps running, as on detach.
+                core.debugger().resume();
+                info!("Gdb client killed session");
+                return Ok(false);
+            },
+            'H' => {
+                "OK".to_owned()
+            },
+            'q' => {
+                GdbStub::handle_query(packet)
+            },
+            'Q' => {
+                if packet == "QStartNoAckMode" {
+                    self.send_packet("OK")?;
+                    self.connection.as_mut().unwrap().no_ack = true;
+                    return Ok(true);
+                }
+                String::new()
+            },
+            _ => {
+                // Unsupported packet.
+                String::new()
+            },
+        };
+
+        self.send_packet(&reply)?;
+        Ok(true)
+    }
+
+    /// Handles the query packets needed to attach.
+    fn handle_query(packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_owned();
+        }
+
+        if packet.starts_with("qXfer:features:read:target.xml:") {
+            let range = &packet["qXfer:features:read:target.xml:".len()..];
+            return match parse_address_length(range) {
+                Some((offset, length)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    if offset >= xml.len() {
+                        "l".to_owned()
+                    } else {
+                        let end = (offset + length).min(xml.len());
+                        let prefix = if end == xml.len() { "l" } else { "m" };
+                        format!("{}{}", prefix, String::from_utf8_lossy(&xml[offset..end]))
+                    }
+                },
+                None => "E01".to_owned(),
+            };
+        }
+
+        match packet {
+            "qAttached" => "1".to_owned(),
+            "qC" => "QC1".to_owned(),
+            "qfThreadInfo" => "m1".to_owned(),
+            "qsThreadInfo" => "l".to_owned(),
+            _ => String::new(),
+        }
+    }
+
+    /// Handles Z/z (insert/remove) breakpoint and watchpoint packets: "Z<type>,<addr>,<kind>".
+    fn handle_breakpoint(core: &mut Core, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+        let fields: Vec<&str> = packet[1..].split(',').collect();
+        if fields.len() < 3 {
+            return "E01".to_owned();
+        }
+
+        let address = match u16::from_str_radix(fields[1], 16) {
+            Ok(address) => address,
+            Err(_) => return "E01".to_owned(),
+        };
+        let length = usize::from_str_radix(fields[2], 16).unwrap_or(1).max(1);
+
+        let kind = match fields[0] {
+            "0" | "1" => None,
+            "2" => Some(WatchKind::Write),
+            "3" => Some(WatchKind::Read),
+            "4" => Some(WatchKind::Access),
+            _ => return String::new(),
+        };
+
+        let debugger = core.debugger();
+        match (kind, insert) {
+            (None, true) => debugger.set_breakpoint(address, None),
+            (None, false) => { debugger.remove_breakpoint(address); },
+            (Some(kind), true) => debugger.add_watchpoint(address, length, kind),
+            (Some(kind), false) => { debugger.remove_watchpoint_of_kind(address, kind); },
+        }
+
+        "OK".to_owned()
+    }
+
+    /// Returns the stop reply packet for a stop reason.
+    fn stop_reply(reason: Option<&StopReason>) -> String {
+        match reason {
+            Some(&StopReason::Watchpoint { address, kind, .. }) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, address)
+            },
+            Some(&StopReason::Interrupted(_)) => format!("S{:02x}", SIGINT),
+            Some(&StopReason::Sanitizer(_)) | Some(&StopReason::StackUnderflow(_)) => format!("S{:02x}", SIGSEGV),
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    /// Returns the (offset, size) of a register within the 'g' packet data.
+    fn register_range(number: usize) -> (usize, usize) {
+        match number {
+            REG_I => (16, 2),
+            REG_PC => (18, 2),
+            REG_SP => (20, 1),
+            _ => (number, 1),
+        }
+    }
+
+    fn read_registers(core: &Core) -> Result<Vec<u8>, String> {
+        let mut registers = Vec::with_capacity(REGISTERS_SIZE);
+        for index in 0..16 {
+            registers.push(core.gpr(index)?);
+        }
+
+        let i = core.i()?;
+        registers.push(i as u8);
+        registers.push((i >> 8) as u8);
+
+        let pc: uptr = core.pc()?;
+        registers.push(pc as u8);
+        registers.push((pc >> 8) as u8);
+
+        registers.push(core.stack()?.len() as u8);
+
+        Ok(registers)
+    }
+
+    /// Writes registers from 'g' packet data. SP is read-only and ignored.
+    /// The values are checked first (I takes any 16 bit value, the PC must be within memory),
+    /// so nothing is written if any is invalid.
+    fn write_registers(core: &mut Core, registers: &[u8]) -> Result<(), String> {
+        let i = (registers[16] as udword) | ((registers[17] as udword) << 8);
+        let pc = (registers[18] as uptr) | ((registers[19] as uptr) << 8);
+        if pc as usize >= MEMORY_SIZE {
+            return Err(format!("Program counter 0x{:X} not within valid range", pc));
+        }
+
+        for index in 0..16 {
+            core.set_gpr(index, registers[index])?;
+        }
+        core.set_i(i)?;
+        core.set_pc(pc)?;
+
+        Ok(())
+    }
+
+    /// Writes registers (see write_registers()), returning the reply.
+    fn write_registers_reply(core: &mut Core, registers: &[u8]) -> String {
+        match GdbStub::write_registers(core, registers) {
+            Ok(()) => "OK".to_owned(),
+            Err(e) => {
+                warn!("Gdb stub: can't write registers: {}", e);
+                "E01".to_owned()
+            },
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> Result<(), String> {
+        debug!("Gdb stub: sending packet '{}'", data);
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.send_raw(packet.as_bytes())
+    }
+
+    fn send_raw(&mut self, data: &[u8]) -> Result<(), String> {
+        let conn = self.connection.as_mut().unwrap();
+        conn.stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+        let result = conn.stream.write_all(data).map_err(|e| e.to_string());
+        conn.stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+        result
+    }
+}
+
+/// Parses "<addr>,<length>" (both hex).
+fn parse_address_length(text: &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let address = parts.next().and_then(|a| usize::from_str_radix(a, 16).ok());
+    let length = parts.next().and_then(|l| usize::from_str_radix(l, 16).ok());
+    match (address, length) {
+        (Some(address), Some(length)) => Some((address, length)),
+        _ => None,
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len() / 2)
+        .map(|index| u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok())
+        .collect()
+}
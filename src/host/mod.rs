@@ -0,0 +1,49 @@
+//! Host interface.
+//!
+//! The core talks to the frontend through a Host (see Config::host): it
+//! passes on display updates, beeper changes and synthesised audio from the
+//! controllers, and collects key changes at the start of each run. Hosts are
+//! plain objects, so they can own whatever state the frontend needs
+//! (textures, audio devices, recorders). To share a host with the rest of the frontend, give the core
+//! an Rc<RefCell<...>> of it.
+//!
+//! All calls are made from the thread calling Core::run(), never from the
+//! controller threads.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+
+pub trait Host {
+    /// Called when the display changed (possibly several times per run).
+    fn video(&mut self, _framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES]) {}
+
+    /// Called when the beeper is turned on or off.
+    fn audio(&mut self, _on: bool) {}
+
+    /// Called after each run with the audio synthesised during it, if enabled (see audio).
+    fn audio_samples(&mut self, _samples: &[f32]) {}
+
+    /// Returns the key changes since the last call, as (key, pressed), applied in order before the run.
+    fn input(&mut self) -> Vec<(usize, bool)> {
+        Vec::new()
+    }
+}
+
+impl<T: Host> Host for Rc<RefCell<T>> {
+    fn video(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES]) {
+        self.borrow_mut().video(framebuffer)
+    }
+
+    fn audio(&mut self, on: bool) {
+        self.borrow_mut().audio(on)
+    }
+
+    fn audio_samples(&mut self, samples: &[f32]) {
+        self.borrow_mut().audio_samples(samples)
+    }
+
+    fn input(&mut self) -> Vec<(usize, bool)> {
+        self.borrow_mut().input()
+    }
+}
@@ -0,0 +1,298 @@
+//! Keymaps.
+//!
+//! Maps host inputs (keyboard keys, game controller buttons and axes) to the
+//! Chip8 keys, independently of the frontend library. Each Chip8 key can be
+//! bound to any number of host inputs, and stays pressed while any of them
+//! is held.
+//!
+//! Bindings are written as strings: a key name (eg: "Q", "Left"), a
+//! controller button ("button:a", "button:dpup") or a controller axis
+//! direction ("axis:leftx+", "axis:lefty-"). Names follow SDL's and are case
+//! insensitive. In config files a Chip8 key takes one binding or a list, eg:
+//!
+//! ```yaml
+//! keys:
+//!   "5": ["W", "Up", "button:dpup", "axis:lefty-"]
+//!   "6": "E"
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use common::constants::cpu::KEYS_COUNT;
+
+/// Axis values beyond this (either direction) count as pressed.
+pub const AXIS_THRESHOLD: i16 = 16384;
+
+/// Default key names by Chip8 key: the 1234/QWER/ASDF/ZXCV block, laid out like the COSMAC VIP keypad.
+pub const DEFAULT_KEYS: [&'static str; KEYS_COUNT] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+/// A host input. Names are kept in lowercase.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HostInput {
+    /// Keyboard key, by name.
+    Key(String),
+
+    /// Game controller button, by name.
+    Button(String),
+
+    /// Game controller axis, by name, pushed in the positive (true) or negative direction.
+    Axis(String, bool),
+}
+
+impl HostInput {
+    pub fn key(name: &str) -> HostInput {
+        HostInput::Key(name.to_lowercase())
+    }
+
+    pub fn button(name: &str) -> HostInput {
+        HostInput::Button(name.to_lowercase())
+    }
+
+    pub fn axis(name: &str, positive: bool) -> HostInput {
+        HostInput::Axis(name.to_lowercase(), positive)
+    }
+
+    /// Parses a binding, eg: "Q", "button:a" or "axis:leftx+".
+    pub fn parse(binding: &str) -> Result<HostInput, String> {
+        let binding = binding.trim();
+        if binding.starts_with("button:") {
+            let name = &binding["button:".len()..];
+            if !name.is_empty() {
+                return Ok(HostInput::button(name));
+            }
+        } else if binding.starts_with("axis:") {
+            let name = &binding["axis:".len()..];
+            if name.len() > 1 && name.ends_with('+') {
+                return Ok(HostInput::axis(&name[..(name.len() - 1)], true));
+            }
+            if name.len() > 1 && name.ends_with('-') {
+                return Ok(HostInput::axis(&name[..(name.len() - 1)], false));
+            }
+        } else if !binding.is_empty() {
+            return Ok(HostInput::key(binding));
+        }
+        Err(format!("'{}' is not a binding (expected a key name, button:<name> or axis:<name>+/-)", binding))
+    }
+}
+
+impl fmt::Display for HostInput {
+    /// Formats the input as a binding.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HostInput::Key(ref name) => write!(f, "{}", name),
+            HostInput::Button(ref name) => write!(f, "button:{}", name),
+            HostInput::Axis(ref name, positive) => write!(f, "axis:{}{}", name, if positive { '+' } else { '-' }),
+        }
+    }
+}
+
+/// Bindings of a Chip8 key, as written in config files: one binding or a list.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum KeyBindings {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyBindings {
+    pub fn bindings(&self) -> Vec<&str> {
+        match *self {
+            KeyBindings::One(ref binding) => vec![binding.as_str()],
+            KeyBindings::Many(ref bindings) => bindings.iter().map(|binding| binding.as_str()).collect(),
+        }
+    }
+}
+
+/// Parses a Chip8 key name (a hex digit).
+pub fn parse_key(key: &str) -> Result<usize, String> {
+    match usize::from_str_radix(key, 16) {
+        Ok(key) if key < KEYS_COUNT => Ok(key),
+        _ => Err(format!("'{}' is not a Chip8 key (expected 0-F)", key)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<HostInput, usize>,
+
+    /// Host inputs currently held.
+    held: HashMap<HostInput, usize>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut keymap = Keymap::new();
+        for (key, name) in DEFAULT_KEYS.iter().enumerate() {
+            keymap.bind(key, HostInput::key(name));
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Creates a keymap without any bindings.
+    pub fn new() -> Keymap {
+        Keymap {
+            bindings: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+
+    /// Binds a host input to the Chip8 key, replacing any previous binding of the input.
+    pub fn bind(&mut self, key: usize, input: HostInput) {
+        self.bindings.insert(input, key);
+    }
+
+    /// Removes all bindings of the Chip8 key.
+    pub fn unbind(&mut self, key: usize) {
+        self.bindings.retain(|_, &mut bound_key| bound_key != key);
+    }
+
+    /// Replaces the bindings of each Chip8 key listed (keys not listed are kept).
+    /// Errors name the field within the section, eg: "keys.4: ...".
+    pub fn apply(&mut self, section: &str, bindings: &BTreeMap<String, KeyBindings>) -> Result<(), String> {
+        for (key_name, key_bindings) in bindings.iter() {
+            let key = parse_key(key_name).map_err(|e| format!("{}.{}: {}", section, key_name, e))?;
+            let inputs = key_bindings.bindings().iter()
+                .map(|binding| HostInput::parse(binding))
+                .collect::<Result<Vec<HostInput>, String>>()
+                .map_err(|e| format!("{}.{}: {}", section, key_name, e))?;
+
+            self.unbind(key);
+            for input in inputs {
+                self.bind(key, input);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the input names with the frontend, returning an error naming the first unknown one.
+    pub fn validate<F: Fn(&HostInput) -> bool>(&self, section: &str, is_known: F) -> Result<(), String> {
+        let mut bindings: Vec<(&HostInput, &usize)> = self.bindings.iter().collect();
+        bindings.sort();
+        for (input, key) in bindings {
+            if !is_known(input) {
+                return Err(format!("{}.{:X}: unknown input '{}'", section, key, input));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the Chip8 key bound to the host input.
+    pub fn key(&self, input: &HostInput) -> Option<usize> {
+        self.bindings.get(input).cloned()
+    }
+
+    /// Returns the host inputs bound to the Chip8 key.
+    pub fn inputs(&self, key: usize) -> Vec<&HostInput> {
+        let mut inputs: Vec<&HostInput> = self.bindings.iter()
+            .filter(|&(_, &bound_key)| bound_key == key)
+            .map(|(input, _)| input)
+            .collect();
+        inputs.sort();
+        inputs
+    }
+
+    fn is_key_held(&self, key: usize) -> bool {
+        self.held.values().any(|&held_key| held_key == key)
+    }
+
+    /// Updates the state of a host input, returning the Chip8 key and its new state if it changed.
+    pub fn update(&mut self, input: HostInput, pressed: bool) -> Option<(usize, bool)> {
+        let was_held = self.held.contains_key(&input);
+        if pressed == was_held {
+            return None;
+        }
+
+        let key = if pressed {
+            match self.key(&input) {
+                Some(key) => {
+                    let changed = !self.is_key_held(key);
+                    self.held.insert(input, key);
+                    if changed { Some(key) } else { None }
+                },
+                None => None,
+            }
+        } else {
+            let key = self.held.remove(&input).unwrap();
+            if self.is_key_held(key) { None } else { Some(key) }
+        };
+        key.map(|key| (key, pressed))
+    }
+
+    /// Updates the state of a controller axis (both directions), returning the Chip8 key changes.
+    pub fn update_axis(&mut self, axis: &str, value: i16) -> Vec<(usize, bool)> {
+        let mut changes = Vec::new();
+        let positive = self.update(HostInput::axis(axis, true), value > AXIS_THRESHOLD);
+        let negative = self.update(HostInput::axis(axis, false), value < -AXIS_THRESHOLD);
+        changes.extend(positive);
+        changes.extend(negative);
+        changes
+    }
+
+    /// Releases all held host inputs, returning the Chip8 keys released.
+    pub fn release_all(&mut self) -> Vec<usize> {
+        let mut keys: Vec<usize> = self.held.drain().map(|(_, key)| key).collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_stays_pressed_while_any_binding_is_held() {
+        let mut keymap = Keymap::new();
+        keymap.bind(5, HostInput::key("W"));
+        keymap.bind(5, HostInput::key("Up"));
+
+        assert_eq!(keymap.update(HostInput::key("w"), true), Some((5, true)));
+        assert_eq!(keymap.update(HostInput::key("up"), true), None);
+        assert_eq!(keymap.update(HostInput::key("w"), false), None);
+        assert_eq!(keymap.update(HostInput::key("up"), false), Some((5, false)));
+
+        // Repeated and unbound inputs don't change anything.
+        assert_eq!(keymap.update(HostInput::key("up"), false), None);
+        assert_eq!(keymap.update(HostInput::key("q"), true), None);
+    }
+
+    #[test]
+    fn axis_crosses_threshold() {
+        let mut keymap = Keymap::new();
+        keymap.bind(4, HostInput::axis("leftx", false));
+        keymap.bind(6, HostInput::axis("leftx", true));
+
+        assert_eq!(keymap.update_axis("leftx", AXIS_THRESHOLD), Vec::new());
+        assert_eq!(keymap.update_axis("leftx", AXIS_THRESHOLD + 1), vec![(6, true)]);
+        assert_eq!(keymap.update_axis("leftx", i16::max_value()), Vec::new());
+
+        // Swinging straight across releases one direction and presses the other.
+        assert_eq!(keymap.update_axis("leftx", -AXIS_THRESHOLD - 1), vec![(6, false), (4, true)]);
+        assert_eq!(keymap.update_axis("leftx", -AXIS_THRESHOLD), vec![(4, false)]);
+        assert_eq!(keymap.update_axis("leftx", 0), Vec::new());
+    }
+
+    #[test]
+    fn apply_replaces_listed_keys_only() {
+        let mut keymap = Keymap::default();
+        let mut bindings = BTreeMap::new();
+        bindings.insert("5".to_owned(), KeyBindings::Many(vec!["Up".to_owned(), "button:dpup".to_owned()]));
+        bindings.insert("6".to_owned(), KeyBindings::One("axis:leftx+".to_owned()));
+        keymap.apply("keys", &bindings).unwrap();
+
+        assert_eq!(keymap.inputs(5), vec![&HostInput::key("up"), &HostInput::button("dpup")]);
+        assert_eq!(keymap.inputs(6), vec![&HostInput::axis("leftx", true)]);
+        assert_eq!(keymap.key(&HostInput::key("w")), None);
+        assert_eq!(keymap.inputs(4), vec![&HostInput::key("q")]);
+        assert_eq!(keymap.inputs(0xF), vec![&HostInput::key("v")]);
+
+        let mut invalid = BTreeMap::new();
+        invalid.insert("G".to_owned(), KeyBindings::One("Q".to_owned()));
+        assert!(keymap.apply("keys", &invalid).unwrap_err().starts_with("keys.G: "));
+    }
+}
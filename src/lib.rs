@@ -5,9 +5,14 @@ extern crate num;
 extern crate rand;
 #[macro_use]
 extern crate log;
-//#[macro_use] 
-//extern crate serde_derive; // Waiting for const generics... RFC 2000.
-//extern crate bincode;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+//extern crate bincode; // Waiting for const generics... RFC 2000.
+extern crate gif;
+extern crate png;
+extern crate sha1;
 extern crate futures;
 extern crate futures_cpupool;
 extern crate parking_lot;
@@ -15,31 +20,114 @@ extern crate parking_lot;
 pub mod common;
 pub mod resources;
 pub mod controller;
+pub mod debugger;
+pub mod gdb;
+pub mod trace;
+pub mod profiler;
+pub mod coverage;
+pub mod sanitizer;
+pub mod font;
+pub mod rom;
+pub mod romdb;
+pub mod quirks;
+pub mod keymap;
+pub mod config;
+pub mod browser;
+pub mod video;
+pub mod host;
+pub mod capture;
+pub mod audio;
+pub mod pacing;
 
 use std::cell::UnsafeCell;
+use std::ops::Range;
+use std::path::Path;
 use std::sync::mpsc::*;
 use futures::Future;
 use futures_cpupool::CpuPool;
 use futures_cpupool::CpuFuture;
+use common::types::primative::*;
 use common::types::storage::*;
 use resources::Resources;
+use resources::bus::{Device, Region};
 use controller::Controller;
 use controller::ControllerEvent;
 use controller::cpu::Cpu;
 use controller::spu::Spu;
 use controller::timer::Timer;
 use common::constants::cpu::{VERTICAL_RES, HORIZONTAL_RES};
+use common::constants::memory::{MEMORY_SIZE, PROGRAM_START};
+use common::types::storage::register::*;
+use resources::cpu::KEYS;
+use debugger::Debugger;
+use debugger::StopReason;
+use trace::{TraceFormat, TraceRecorder};
+use profiler::Profiler;
+use sanitizer::{Sanitizer, SanitizerMode};
+use font::{FontSet, BigFontSet, big_font_address};
+use resources::layout::MemoryLayout;
+use rom::Rom;
+use rom::octo::OctoOptions;
+use quirks::{Quirks, QuirkOverrides};
+use host::Host;
+use capture::GifRecorder;
+use capture::wav::WavWriter;
+use audio::{AudioSettings, Synth};
+use video::palette::Palette;
+use romdb::{RomDatabase, RomEntry, DATABASE_PATH, USER_DATABASE_PATH, rom_hash};
 
 pub struct Config {
     pub workspace_path: String,
     pub time_delta_us: f64,
     pub multithreaded_pool: Option<CpuPool>, 
-    pub cpu_bias: f64,
+
+    /// Cpu speed multiplier set by the user. If None, the one recommended for the rom is used (see
+    /// Core::cpu_bias()), otherwise 1.
+    pub cpu_bias: Option<f64>,
     pub spu_bias: f64,
     pub timer_bias: f64,
 
-    pub video_callback: Option<fn(&[bool; HORIZONTAL_RES * VERTICAL_RES])>,
-    pub audio_callback: Option<fn(bool)>,
+    /// Small font (FX29) and big font (FX30) sets, loaded at font_address (the big font directly after).
+    pub font_set: FontSet,
+    pub big_font_set: BigFontSet,
+    pub font_address: usize,
+
+    /// Where the stack, registers and display are kept (see resources::layout).
+    pub memory_layout: MemoryLayout,
+
+    /// Applies the recommended settings of known roms on reset (see romdb).
+    /// Entries in the user database (workspace/config/romdb.user.json) override the defaults.
+    pub rom_database: bool,
+
+    /// Interpreter quirks set by the user, overriding those recommended for the rom (see quirks).
+    pub quirks: QuirkOverrides,
+
+    /// Frontend receiving the video and audio events and providing the input (see host).
+    pub host: Option<Box<Host>>,
+
+    /// Synthesises the beep into audio samples for the host (see audio), if set.
+    pub audio: Option<AudioSettings>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            workspace_path: "./workspace/".to_owned(),
+            time_delta_us: 20000.0,
+            multithreaded_pool: None,
+            cpu_bias: None,
+            spu_bias: 1.0,
+            timer_bias: 1.0,
+            font_set: FontSet::Default,
+            big_font_set: BigFontSet::SchipHex,
+            font_address: 0x0,
+            memory_layout: MemoryLayout::Default,
+            rom_database: true,
+            quirks: QuirkOverrides::default(),
+            host: None,
+            audio: None,
+        }
+    }
 }
 
 /// Events that are communicated from the controllers to the core,
@@ -52,6 +140,13 @@ enum CoreEvent {
     Audio(bool),
 }
 
+/// The emulator core.
+///
+/// Machine state (memory, registers, stack, keys, timers and the framebuffer) 
+/// can be inspected and modified between calls to run(), through the state 
+/// functions such as read_memory(), gpr() and set_pc(). Functions modifying 
+/// state take &mut self, so they can never race with a run in progress.
+/// Modifications take effect from the next run.
 pub struct Core {
     config: Config,
     resources: Option<Box<UnsafeCell<Resources>>>,
@@ -59,56 +154,88 @@ pub struct Core {
     multithreaded_futures: Vec<CpuFuture<(), String>>,
     event_queue_rx: Receiver<CoreEvent>,
     event_queue_tx: SyncSender<CoreEvent>,
+    debugger: UnsafeCell<Debugger>,
+    tracer: UnsafeCell<Option<TraceRecorder>>,
+    profiler: UnsafeCell<Option<Profiler>>,
+    sanitizer: UnsafeCell<Option<Sanitizer>>,
+    recorder: Option<GifRecorder>,
+    synth: UnsafeCell<Option<Synth>>,
+    audio_recorder: Option<WavWriter>,
+    font_size: usize,
+    rom_size: usize,
+    rom_options: Option<OctoOptions>,
+    rom_hash: String,
+    rom_name: String,
+    rom_database: Option<RomDatabase>,
+    rom_entry: Option<RomEntry>,
+    quirks: Quirks,
 }
 
 impl Core {
-    /// Creates a new core.
+    /// Creates a new core, with the default config (see Config::default()) if none given.
     /// You must call reset() afterwards to create the resources and controllers.
     /// This is to prevent moves when constructing, causing the controllers
     /// core references pointing to invalid locations.
     pub fn new(config: Option<Config>) -> Core {
         let (event_queue_tx, event_queue_rx) = sync_channel::<CoreEvent>(128);
-        match config {
-            Some(config) => {
-                Core {
-                    config: config,
-                    resources: None,
-                    controllers: Vec::new(),
-                    multithreaded_futures: Vec::new(),
-                    event_queue_rx,
-                    event_queue_tx,
-                }
-            },
-            None => { 
-                Core {
-                    config: Config {
-                        workspace_path: "./workspace/".to_owned(),
-                        time_delta_us: 20000.0,
-                        multithreaded_pool: None,
-                        cpu_bias: 1.0, 
-                        spu_bias: 1.0,
-                        timer_bias: 1.0,
-                        video_callback: None,
-                        audio_callback: None,
-                    },
-                    resources: None,
-                    controllers: Vec::new(),
-                    multithreaded_futures: Vec::new(),
-                    event_queue_rx,
-                    event_queue_tx,
-                }
-            },
+        let config = config.unwrap_or_default();
+        let mut quirks = Quirks::default();
+        config.quirks.apply(&mut quirks);
+        let synth = config.audio.map(Synth::new);
+        Core {
+            config: config,
+            resources: None,
+            controllers: Vec::new(),
+            multithreaded_futures: Vec::new(),
+            event_queue_rx,
+            event_queue_tx,
+            debugger: UnsafeCell::new(Debugger::new()),
+            tracer: UnsafeCell::new(None),
+            profiler: UnsafeCell::new(None),
+            sanitizer: UnsafeCell::new(None),
+            recorder: None,
+            synth: UnsafeCell::new(synth),
+            audio_recorder: None,
+            font_size: 0,
+            rom_size: 0,
+            rom_options: None,
+            rom_hash: String::new(),
+            rom_name: String::new(),
+            rom_database: None,
+            rom_entry: None,
+            quirks,
         }
     }
 
     /// Resets the core, initialising the Core state.
     /// Performs the following:
-    ///  - Allocates resources.
+    ///  - Allocates resources, keeping the bus regions and devices (regions are created
+    ///    from the memory layout on the first reset).
     ///  - Resets all controllers.
+    ///  - Resets the debugger execution state (breakpoints are kept).
+    ///  - Resets the profiler call stack (collected counts are kept).
+    ///  - Carries over the coverage recorded so far, if enabled.
     ///  - Loads the default font set.
     ///  - Loads the rom from the path given.
+    ///  - Looks up the rom in the rom database, if enabled (loaded on the first reset),
+    ///    applying its quirks.
+    ///  - Resets the sanitizer memory state (reports are kept).
+    ///  - Keeps any display and audio recording in progress.
     pub fn reset(&mut self, rom_path: &str) -> Result<(), String> {
-        self.resources = Some(Box::new(UnsafeCell::new(Resources::new())));
+        // The font sets are read first, as the bus regions follow them.
+        let (small_font, big_font) = self.font_sets()?;
+        let font_range = self.font_range();
+
+        let bus = self.resources.take().map(|res| {
+            let mut bus = (*res).into_inner().bus;
+            bus.reset();
+            bus.set_font_range(font_range.clone());
+            bus
+        });
+        self.resources = Some(Box::new(UnsafeCell::new(match bus {
+            Some(bus) => Resources::with_bus(bus, self.config.memory_layout),
+            None => Resources::with_bus(self.config.memory_layout.bus(MEMORY_SIZE, font_range), self.config.memory_layout),
+        })));
 
         self.controllers.clear();
         unsafe {
@@ -118,17 +245,42 @@ impl Core {
             self.controllers.push(Box::new(Timer::new(&*self_ptr)));
         }
 
-        self.load_font_set()?;
+        self.debugger().reset();
+        if let Some(ref mut profiler) = *self.profiler_state() {
+            profiler.reset_stack();
+        }
+
+        self.load_font_sets(&small_font, &big_font)?;
         self.load_rom(rom_path)?;
+        self.lookup_rom();
+
+        let initialised = self.initialised_ranges();
+        if let Some(ref mut sanitizer) = *self.sanitizer_state() {
+            sanitizer.reset(&initialised, self.font_range());
+        }
 
         Ok(())
     }
 
     /// Runs through each of the controllers that update the machine state.
     /// Each run will update the state for the time step defined at initialisation.
-    /// Also handles any events received from the controllers.
-    pub fn run(&mut self) -> Result<(), String> {
-        if cfg!(build = "debug") {
+    /// Key changes from the host are applied first, and events received from the controllers are
+    /// passed on to the host after.
+    /// While the debugger has emulation stopped, no time is emulated (only input is processed).
+    /// Returns the stop reason if the debugger stopped emulation during this run.
+    pub fn run(&mut self) -> Result<Option<StopReason>, String> {
+        let stopped = self.debugger().is_stopped();
+
+        // Apply the key changes from the host, even while stopped.
+        let key_changes = match self.config.host {
+            Some(ref mut host) => host.input(),
+            None => Vec::new(),
+        };
+        for (key, pressed) in key_changes {
+            self.set_key(key, pressed)?;
+        }
+
+        if cfg!(build = "debug") && !stopped {
             unsafe {
                 static mut TIME_US: f64 = 0.0;
                 info!("Emulated time elapsed (s) = {:.6}", TIME_US / 1e6);
@@ -137,8 +289,10 @@ impl Core {
         }
 
         // Generate the clock tick event for each controller, using the time slice set.
-        for ref cont in self.controllers.iter() {
-            cont.gen_tick_event(self.config.time_delta_us)?;
+        if !stopped {
+            for ref cont in self.controllers.iter() {
+                cont.gen_tick_event(self.config.time_delta_us)?;
+            }
         }
 
         // Run the controllers, either in multi-threaded or single-threaded mode.
@@ -170,24 +324,291 @@ impl Core {
         for event in self.event_queue_rx.try_iter() {
             match event {
                 CoreEvent::Video => {
-                    if let Some(ref f) = self.config.video_callback {
-                        f(&self.resources()?.cpu.framebuffer);
+                    let framebuffer = self.resources()?.framebuffer();
+                    if let Some(ref mut host) = self.config.host {
+                        host.video(&framebuffer);
                     }
                 },
                 CoreEvent::Audio(play) => {
-                    if let Some(ref f) = self.config.audio_callback {
-                        f(play);
+                    if let Some(ref mut host) = self.config.host {
+                        host.audio(play);
                     }
                 },
             }
         }
 
+        // Pass on the audio synthesised by the Spu, also recording it if requested.
+        let samples = match *self.synth_state() {
+            Some(ref mut synth) => synth.take_samples(),
+            None => Vec::new(),
+        };
+        if !samples.is_empty() {
+            if let Some(ref mut host) = self.config.host {
+                host.audio_samples(&samples);
+            }
+            if let Some(ref mut audio_recorder) = self.audio_recorder {
+                audio_recorder.add_samples(&samples)?;
+            }
+        }
+
+        // Sample the display for the recording, if any (only emulated time is recorded).
+        if !stopped && self.recorder.is_some() {
+            let framebuffer = self.resources()?.framebuffer();
+            self.recorder.as_mut().unwrap().add_frame(&framebuffer, self.config.time_delta_us)?;
+        }
+
+        // Deliver any debugger break requested while no instructions were executed.
+        let pc: uptr = self.resources()?.cpu.pc.read(BusContext::Raw, 0);
+        let debugger = self.debugger_state();
+        debugger.poll_interrupt(pc);
+
+        Ok(debugger.take_stop_event())
+    }
+
+    /// Returns the debugger, used to set breakpoints, watchpoints and control execution.
+    pub fn debugger(&mut self) -> &mut Debugger {
+        self.debugger_state()
+    }
+
+    /// Starts recording an instruction trace to the file at the given path, replacing any trace in progress.
+    /// Only instructions with a pc within range are recorded, if given.
+    pub fn start_trace(&mut self, path: &str, format: TraceFormat, range: Option<Range<uptr>>) -> Result<(), String> {
+        let recorder = TraceRecorder::new(path, format, range)?;
+        *self.tracer_state() = Some(recorder);
+        Ok(())
+    }
+
+    /// Stops recording the instruction trace, flushing it to the file.
+    pub fn stop_trace(&mut self) -> Result<(), String> {
+        match self.tracer_state().take() {
+            Some(mut recorder) => recorder.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns if an instruction trace is being recorded.
+    pub fn is_tracing(&self) -> bool {
+        self.tracer_state().is_some()
+    }
+
+    /// Saves a PNG screenshot of the display, in the palette and integer scale given, to the path
+    /// given or else the captures folder (see capture). Returns the path written.
+    pub fn screenshot(&self, path: Option<&str>, palette: &Palette, scale: usize) -> Result<String, String> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => capture::capture_path(&self.config.workspace_path, &self.rom_name, "png")?,
+        };
+        capture::screenshot(&path, &self.framebuffer()?, palette, scale)?;
+        Ok(path)
+    }
+
+    /// Starts recording the display to an animated GIF, in the palette and integer scale given, at
+    /// the path given or else in the captures folder (see capture). Any recording in progress is
+    /// finished first. Returns the path of the new recording.
+    pub fn start_recording(&mut self, path: Option<&str>, palette: &Palette, scale: usize) -> Result<String, String> {
+        self.stop_recording()?;
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => capture::capture_path(&self.config.workspace_path, &self.rom_name, "gif")?,
+        };
+        self.recorder = Some(GifRecorder::new(&path, palette, scale)?);
+        Ok(path)
+    }
+
+    /// Stops recording, finishing the GIF. Returns the path and number of frames written, if recording.
+    pub fn stop_recording(&mut self) -> Result<Option<(String, usize)>, String> {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let path = recorder.path().to_owned();
+                Ok(Some((path, recorder.finish()?)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Returns if the display is being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Starts recording the synthesised audio to a WAV file at the path, or a new file in the workspace
+    /// captures folder if none given (see capture). Replaces any audio recording in progress.
+    /// Requires audio synthesis (see Config::audio). Returns the path.
+    pub fn start_audio_recording(&mut self, path: Option<&str>) -> Result<String, String> {
+        let sample_rate = match *self.synth_state() {
+            Some(ref synth) => synth.settings().sample_rate,
+            None => return Err("Audio synthesis is disabled".to_owned()),
+        };
+        self.stop_audio_recording()?;
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => capture::capture_path(&self.config.workspace_path, &self.rom_name, "wav")?,
+        };
+        self.audio_recorder = Some(WavWriter::new(&path, sample_rate)?);
+        Ok(path)
+    }
+
+    /// Stops the audio recording, if any, finishing the file. Returns its path and the number of samples.
+    pub fn stop_audio_recording(&mut self) -> Result<Option<(String, u32)>, String> {
+        match self.audio_recorder.take() {
+            Some(audio_recorder) => {
+                let path = audio_recorder.path().to_owned();
+                Ok(Some((path, audio_recorder.finish()?)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Returns true if the audio is being recorded.
+    pub fn is_recording_audio(&self) -> bool {
+        self.audio_recorder.is_some()
+    }
+
+    /// Starts profiling execution, discarding any previous profile.
+    pub fn start_profiling(&mut self) {
+        *self.profiler_state() = Some(Profiler::new());
+    }
+
+    /// Stops profiling execution, returning the collected profile.
+    pub fn stop_profiling(&mut self) -> Option<Profiler> {
+        self.profiler_state().take()
+    }
+
+    /// Returns the profile being collected, if profiling.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler_state().as_ref()
+    }
+
+    /// Enables the memory access sanitizer, reporting violations as warnings or stopping
+    /// emulation (see StopReason::Sanitizer). Memory written before enabling (other than
+    /// the font sets, rom and Cpu state of the memory layout) is treated as never written.
+    pub fn enable_sanitizer(&mut self, mode: SanitizerMode) {
+        let mut sanitizer = Sanitizer::new(mode);
+        sanitizer.reset(&self.initialised_ranges(), self.font_range());
+        *self.sanitizer_state() = Some(sanitizer);
+    }
+
+    /// Disables the sanitizer, returning it with the violations reported.
+    pub fn disable_sanitizer(&mut self) -> Option<Sanitizer> {
+        self.sanitizer_state().take()
+    }
+
+    /// Returns the sanitizer, if enabled.
+    pub fn sanitizer(&self) -> Option<&Sanitizer> {
+        self.sanitizer_state().as_ref()
+    }
+
+    /// Returns the regions mapped on the memory bus.
+    pub fn bus_regions(&self) -> Result<&[Region], String> {
+        Ok(self.resources()?.bus.regions())
+    }
+
+    /// Sets the write protection of a bus region (see REGION_* in resources::bus).
+    /// Write protection blocks writes made by instructions; the debugger can still write.
+    pub fn set_write_protect(&mut self, region: &str, enabled: bool) -> Result<(), String> {
+        self.resources()?.bus.set_write_protect(region, enabled)
+    }
+
+    /// Maps a device on the memory bus over the given range. Devices are kept across resets.
+    pub fn map_device(&mut self, name: &str, range: Range<usize>, device: Box<Device>) -> Result<(), String> {
+        self.resources()?.bus.map_device(name, range, device)
+    }
+
+    /// Removes a device from the memory bus, returning it.
+    pub fn unmap_device(&mut self, name: &str) -> Result<Option<Box<Device>>, String> {
+        Ok(self.resources()?.bus.unmap_device(name))
+    }
+
+    /// Returns the memory range the font sets were loaded into.
+    pub fn font_range(&self) -> Range<usize> {
+        self.config.font_address..(self.config.font_address + self.font_size)
+    }
+
+    /// Returns the memory range the rom was loaded into.
+    pub fn rom_range(&self) -> Range<usize> {
+        PROGRAM_START..(PROGRAM_START + self.rom_size)
+    }
+
+    /// Returns the SHA-1 of the loaded rom image, as used by the rom database.
+    pub fn rom_hash(&self) -> &str {
+        &self.rom_hash
+    }
+
+    /// Returns the file name of the loaded rom, without the extension.
+    pub fn rom_name(&self) -> &str {
+        &self.rom_name
+    }
+
+    /// Returns the rom database entry of the loaded rom, if known (and the database is enabled).
+    pub fn rom_entry(&self) -> Option<&RomEntry> {
+        self.rom_entry.as_ref()
+    }
+
+    /// Returns the emulated time of each run (us).
+    pub fn time_delta_us(&self) -> f64 {
+        self.config.time_delta_us
+    }
+
+    /// Returns the Cpu speed multiplier in effect: Config::cpu_bias if set, otherwise the tickrate of an
+    /// Octo cartridge or the rom database recommendation, if any, otherwise 1.
+    pub fn cpu_bias(&self) -> f64 {
+        self.config.cpu_bias
+            .or_else(|| self.rom_options.as_ref().and_then(|options| options.cpu_bias()))
+            .or_else(|| self.rom_entry.as_ref().and_then(|entry| entry.cpu_bias))
+            .unwrap_or(1.0)
+    }
+
+    /// Returns the interpreter quirks in effect: the defaults, overridden by the rom database entry if any,
+    /// then by the options of an Octo cartridge, then by Config::quirks.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Returns the options embedded in the rom (Octo cartridges only).
+    /// The tickrate and quirks are applied by the core (see cpu_bias() and quirks()), the palette by the
+    /// frontend (see rom_palette()).
+    pub fn rom_options(&self) -> Option<&OctoOptions> {
+        self.rom_options.as_ref()
+    }
+
+    /// Returns the palette recommended for the rom, if any: the colours of an Octo cartridge, otherwise
+    /// the rom database entry palette.
+    pub fn rom_palette(&self) -> Result<Option<Palette>, String> {
+        if let Some(palette) = self.rom_options.as_ref().and_then(|options| options.palette()) {
+            return palette.map(Some);
+        }
+
+        match self.rom_entry {
+            Some(ref entry) if !entry.palette.is_empty() => {
+                Palette::parse(&entry.palette).map(Some).map_err(|e| format!("Invalid rom database palette: {}", e))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Enables recording the kinds of access (see ACCESS_* in word_memory) made to each byte of memory.
+    /// Coverage is kept across resets until disabled.
+    pub fn enable_coverage(&mut self) -> Result<(), String> {
+        let memory = self.resources()?.bus.memory_mut();
+        if memory.access_map().is_none() {
+            memory.set_access_map(Some(Vec::new()));
+        }
         Ok(())
     }
 
+    /// Disables coverage recording, returning the access kinds recorded per byte.
+    pub fn disable_coverage(&mut self) -> Result<Option<Vec<uword>>, String> {
+        Ok(self.resources()?.bus.memory_mut().take_access_map())
+    }
+
+    /// Returns the access kinds recorded per byte, if coverage is enabled.
+    pub fn coverage(&self) -> Result<Option<&[uword]>, String> {
+        Ok(self.resources()?.bus.memory().access_map())
+    }
+
     /// Dumps all resources memory to workspace/dumps/file.bin.
     pub fn debug_dump_all(&self, postfix_tag: &str) -> Result<(), String> {
-        if let Err(_) = self.resources()?.memory.dump_file(&self.workspace_path(&format!("dumps/memory{}.bin", postfix_tag))) {
+        if let Err(_) = self.resources()?.bus.memory().dump_file(&self.workspace_path(&format!("dumps/memory{}.bin", postfix_tag))) {
             return Err("Something went wrong writing the memory dump file.".to_owned());
         }
 
@@ -195,7 +616,7 @@ impl Core {
     }
 
     /// Generates an Controller::Event::Input event upon host telling us 
-    /// of a key change.
+    /// of a key change. The key state is updated on the next run.
     pub fn set_key(&self, key: usize, pressed: bool) -> Result<(), String> {
         if key > 0xF {
             return Err("Key not within valid range".to_owned());
@@ -208,6 +629,143 @@ impl Core {
         Ok(())
     }
 
+    /// Reads memory starting at the given address into values.
+    pub fn read_memory(&self, address: usize, values: &mut [uword]) -> Result<(), String> {
+        let res = self.resources()?;
+        if address.checked_add(values.len()).map_or(true, |end| end > MEMORY_SIZE) {
+            return Err(format!("Memory range 0x{:X} (+0x{:X}) not within valid range", address, values.len()));
+        }
+
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = res.bus.read(BusContext::Debugger, address + index);
+        }
+
+        Ok(())
+    }
+
+    /// Writes values into memory starting at the given address.
+    pub fn write_memory(&mut self, address: usize, values: &[uword]) -> Result<(), String> {
+        let res = self.resources()?;
+        if address.checked_add(values.len()).map_or(true, |end| end > MEMORY_SIZE) {
+            return Err(format!("Memory range 0x{:X} (+0x{:X}) not within valid range", address, values.len()));
+        }
+
+        res.bus.write_slice(BusContext::Debugger, address, values);
+        if let Some(ref mut sanitizer) = *self.sanitizer_state() {
+            for offset in 0..values.len() {
+                sanitizer.on_host_write(address + offset);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the value of general purpose register V[index].
+    pub fn gpr(&self, index: usize) -> Result<uword, String> {
+        if index > 0xF {
+            return Err("Register not within valid range".to_owned());
+        }
+
+        Ok(self.resources()?.gpr(index))
+    }
+
+    /// Sets the value of general purpose register V[index].
+    pub fn set_gpr(&mut self, index: usize, value: uword) -> Result<(), String> {
+        if index > 0xF {
+            return Err("Register not within valid range".to_owned());
+        }
+
+        self.resources()?.set_gpr(index, value);
+        Ok(())
+    }
+
+    /// Returns the value of the index register I.
+    pub fn i(&self) -> Result<udword, String> {
+        Ok(self.resources()?.cpu.i.read(BusContext::Raw, 0))
+    }
+
+    /// Sets the value of the index register I.
+    pub fn set_i(&mut self, value: udword) -> Result<(), String> {
+        self.resources()?.cpu.i.write(BusContext::Raw, 0, value);
+        Ok(())
+    }
+
+    /// Returns the program counter.
+    pub fn pc(&self) -> Result<uptr, String> {
+        Ok(self.resources()?.cpu.pc.read(BusContext::Raw, 0))
+    }
+
+    /// Sets the program counter.
+    pub fn set_pc(&mut self, value: uptr) -> Result<(), String> {
+        if value as usize >= MEMORY_SIZE {
+            return Err("Program counter not within valid range".to_owned());
+        }
+
+        self.resources()?.cpu.pc.write(BusContext::Raw, 0, value);
+        Ok(())
+    }
+
+    /// Returns the call stack (return addresses), from the bottom up.
+    pub fn stack(&self) -> Result<Vec<uptr>, String> {
+        Ok(self.resources()?.stack())
+    }
+
+    /// Replaces the call stack (return addresses), from the bottom up.
+    pub fn set_stack(&mut self, stack: &[uptr]) -> Result<(), String> {
+        if stack.iter().any(|&address| address as usize >= MEMORY_SIZE) {
+            return Err("Stack address not within valid range".to_owned());
+        }
+
+        self.resources()?.set_stack(stack);
+        Ok(())
+    }
+
+    /// Returns the pressed state of a key.
+    pub fn key(&self, key: usize) -> Result<bool, String> {
+        if key > 0xF {
+            return Err("Key not within valid range".to_owned());
+        }
+
+        let value: udword = self.resources()?.cpu.keys.read_bitfield(BusContext::Raw, 0, KEYS[key]);
+        Ok(value == 1)
+    }
+
+    /// Returns the pressed state of all keys, as a bitmask (bit n = key n).
+    pub fn keys(&self) -> Result<udword, String> {
+        Ok(self.resources()?.cpu.keys.read(BusContext::Raw, 0))
+    }
+
+    /// Returns the delay timer counter.
+    pub fn delay_timer(&self) -> Result<uword, String> {
+        Ok(self.resources()?.timer.counter.read(BusContext::Raw, 0))
+    }
+
+    /// Sets the delay timer counter.
+    pub fn set_delay_timer(&mut self, value: uword) -> Result<(), String> {
+        self.resources()?.timer.counter.write(BusContext::Raw, 0, value);
+        Ok(())
+    }
+
+    /// Returns the sound timer counter.
+    pub fn sound_timer(&self) -> Result<uword, String> {
+        Ok(self.resources()?.spu.counter.read(BusContext::Raw, 0))
+    }
+
+    /// Sets the sound timer counter. The beep starts or stops on the next run.
+    pub fn set_sound_timer(&mut self, value: uword) -> Result<(), String> {
+        self.resources()?.spu.counter.write(BusContext::Raw, 0, value);
+        Ok(())
+    }
+
+    /// Returns if the Cpu is halted waiting for a key press (see instruction 'keyr').
+    pub fn is_halted(&self) -> Result<bool, String> {
+        Ok(self.resources()?.cpu.halted)
+    }
+
+    /// Returns a copy of the framebuffer.
+    pub fn framebuffer(&self) -> Result<[bool; HORIZONTAL_RES * VERTICAL_RES], String> {
+        Ok(self.resources()?.framebuffer())
+    }
+
     /// Returns a relative path within the workspace.
     /// Workspace contains config files, save files, log files, etc.
     fn workspace_path(&self, rel_path: &str) -> String {
@@ -228,64 +786,213 @@ impl Core {
         }
     }
 
+    /// Returns a reference to the mutable debugger state, used by controllers.
+    fn debugger_state(&self) -> &mut Debugger {
+        unsafe { &mut *self.debugger.get() }
+    }
+
+    /// Returns a reference to the mutable sanitizer, used by controllers.
+    fn sanitizer_state(&self) -> &mut Option<Sanitizer> {
+        unsafe { &mut *self.sanitizer.get() }
+    }
+
+    /// Returns a reference to the mutable audio synthesiser, used by controllers.
+    fn synth_state(&self) -> &mut Option<Synth> {
+        unsafe { &mut *self.synth.get() }
+    }
+
+    /// Returns a reference to the mutable profiler, used by controllers.
+    fn profiler_state(&self) -> &mut Option<Profiler> {
+        unsafe { &mut *self.profiler.get() }
+    }
+
+    /// Returns a reference to the mutable trace recorder, used by controllers.
+    fn tracer_state(&self) -> &mut Option<TraceRecorder> {
+        unsafe { &mut *self.tracer.get() }
+    }
+
     /// Returns a reference to the shared config. 
     fn config(&self) -> &Config {
         &self.config
     }
 
-    /// Initialises the default Chip8 font set and loads it into memory starting at offset 0x0. 
-    /// See http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#font.
-    fn load_font_set(&self) -> Result<(), String> {
-        let char_0: [u8; 5] = [0xF0, 0x90, 0x90, 0x90, 0xF0];
-        let char_1: [u8; 5] = [0x20, 0x60, 0x20, 0x20, 0x70];
-        let char_2: [u8; 5] = [0xF0, 0x10, 0xF0, 0x80, 0xF0];
-        let char_3: [u8; 5] = [0xF0, 0x10, 0xF0, 0x10, 0xF0];
-        let char_4: [u8; 5] = [0x90, 0x90, 0xF0, 0x10, 0x10];
-        let char_5: [u8; 5] = [0xF0, 0x80, 0xF0, 0x10, 0xF0];
-        let char_6: [u8; 5] = [0xF0, 0x80, 0xF0, 0x90, 0xF0];
-        let char_7: [u8; 5] = [0xF0, 0x10, 0x20, 0x40, 0x40];
-        let char_8: [u8; 5] = [0xF0, 0x90, 0xF0, 0x90, 0xF0];
-        let char_9: [u8; 5] = [0xF0, 0x90, 0xF0, 0x10, 0xF0];
-        let char_a: [u8; 5] = [0xF0, 0x90, 0xF0, 0x90, 0x90];
-        let char_b: [u8; 5] = [0xE0, 0x90, 0xE0, 0x90, 0xE0];
-        let char_c: [u8; 5] = [0xF0, 0x80, 0x80, 0x80, 0xF0];
-        let char_d: [u8; 5] = [0xE0, 0x90, 0x90, 0x90, 0xE0];
-        let char_e: [u8; 5] = [0xF0, 0x80, 0xF0, 0x80, 0xF0];
-        let char_f: [u8; 5] = [0xF0, 0x80, 0xF0, 0x80, 0x80];
+    /// Returns the memory ranges initialised by a reset, for the sanitizer.
+    fn initialised_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = vec![self.font_range(), self.rom_range()];
+        ranges.extend(self.config.memory_layout.state_range(MEMORY_SIZE));
+        ranges
+    }
 
-        let res = self.resources()?;
+    /// Reads the configured font sets (the small font and the big font), checking they fit below the
+    /// program area at the configured font address. Sets the size of Core::font_range().
+    fn font_sets(&mut self) -> Result<(Vec<uword>, Vec<uword>), String> {
+        let small_font = self.config.font_set.data(&self.config.workspace_path)?;
+        let big_font = self.config.big_font_set.data(&self.config.workspace_path)?;
 
-        res.memory.write_slice(BusContext::Raw, 0x0, &char_0);
-        res.memory.write_slice(BusContext::Raw, 0x5, &char_1);
-        res.memory.write_slice(BusContext::Raw, 0xA, &char_2);
-        res.memory.write_slice(BusContext::Raw, 0xF, &char_3);
-        res.memory.write_slice(BusContext::Raw, 0x14, &char_4);
-        res.memory.write_slice(BusContext::Raw, 0x19, &char_5);
-        res.memory.write_slice(BusContext::Raw, 0x1E, &char_6);
-        res.memory.write_slice(BusContext::Raw, 0x23, &char_7);
-        res.memory.write_slice(BusContext::Raw, 0x28, &char_8);
-        res.memory.write_slice(BusContext::Raw, 0x2D, &char_9);
-        res.memory.write_slice(BusContext::Raw, 0x32, &char_a);
-        res.memory.write_slice(BusContext::Raw, 0x37, &char_b);
-        res.memory.write_slice(BusContext::Raw, 0x3C, &char_c);
-        res.memory.write_slice(BusContext::Raw, 0x41, &char_d);
-        res.memory.write_slice(BusContext::Raw, 0x46, &char_e);
-        res.memory.write_slice(BusContext::Raw, 0x4B, &char_f);
+        let address = self.config.font_address;
+        if address + small_font.len() + big_font.len() > PROGRAM_START {
+            return Err(format!("Font sets at 0x{:X} (0x{:X} bytes) overlap the program area", address, small_font.len() + big_font.len()));
+        }
+        self.font_size = small_font.len() + big_font.len();
+
+        Ok((small_font, big_font))
+    }
 
+    /// Loads the font sets into memory, the small font starting at the configured font address and
+    /// the big font directly after it.
+    fn load_font_sets(&self, small_font: &[uword], big_font: &[uword]) -> Result<(), String> {
+        let address = self.config.font_address;
+        let res = self.resources()?;
+        res.bus.write_slice(BusContext::Dma, address, small_font);
+        res.bus.write_slice(BusContext::Dma, big_font_address(address), big_font);
         Ok(())
     }
 
-    /// Loads in a Chip8 rom at 0x200.
-    fn load_rom(&self, rom_path: &str) -> Result<(), String> {
-        if let Err(_) = self.resources()?.memory.read_file(0x200, rom_path) {
-            return Err("Something went wrong loading rom file.".to_owned());
-        }
+    /// Loads the rom (in any supported format, see rom::RomFormat) at the program start address.
+    fn load_rom(&mut self, rom_path: &str) -> Result<(), String> {
+        let rom = Rom::open(rom_path)?;
+        let program_range = self.config.memory_layout.program_range(MEMORY_SIZE);
+        rom.check_size(program_range.end - program_range.start).map_err(|e| format!("Could not load rom file {}: {}", rom_path, e))?;
+        info!("Loaded rom {} ({}, 0x{:X} bytes)", rom_path, rom.format, rom.data.len());
+
+        self.resources()?.bus.write_slice(BusContext::Dma, PROGRAM_START, &rom.data);
+        self.rom_size = rom.data.len();
+        self.rom_options = rom.options;
+        self.rom_hash = rom_hash(&rom.data);
+        self.rom_name = Path::new(rom_path).file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
         Ok(())
     }
 
+    /// Finds the database entry of the loaded rom, loading the database if needed, and applies the quirks
+    /// of the entry and of the Octo cartridge options.
+    /// A database that fails to load is reported and treated as empty.
+    fn lookup_rom(&mut self) {
+        self.rom_entry = None;
+        self.quirks = Quirks::default();
+        if self.config.rom_database {
+            self.lookup_rom_database();
+        }
+
+        // Cartridge options come with the rom itself, so they take priority over the database.
+        if let Some(ref options) = self.rom_options {
+            for (name, enabled) in options.quirks() {
+                self.quirks.set(name, enabled).unwrap();
+            }
+        }
+
+        // Quirks the user set explicitly take priority over any recommendation.
+        self.config.quirks.apply(&mut self.quirks);
+    }
+
+    fn lookup_rom_database(&mut self) {
+        if self.rom_database.is_none() {
+            let mut database = RomDatabase::new();
+            for path in [DATABASE_PATH, USER_DATABASE_PATH].iter() {
+                if let Err(e) = database.load_overrides(&self.workspace_path(path)) {
+                    warn!("{}", e);
+                }
+            }
+            self.rom_database = Some(database);
+        }
+
+        self.rom_entry = self.rom_database.as_ref().unwrap().lookup(&self.rom_hash).cloned();
+        match self.rom_entry {
+            Some(ref entry) => {
+                info!("Rom {} found in database: {}", self.rom_hash, entry.title.as_ref().map(|title| title.as_str()).unwrap_or("(untitled)"));
+                for (name, &enabled) in entry.quirks.iter() {
+                    if let Err(e) = self.quirks.set(name, enabled) {
+                        warn!("Rom database entry {}: {}", self.rom_hash, e);
+                    }
+                }
+            },
+            None => info!("Rom {} not found in database", self.rom_hash),
+        }
+    }
+
     /// Sends an event to the back of the event queue attached to the core.
-    /// Used from controllers to do callbacks from the main thread.
+    /// Used from controllers to pass events on to the host from the main thread.
     fn send_event(&self, event: CoreEvent) {
         self.event_queue_tx.send(event).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    /// Returns a core reset with the rom given, written to a temporary file.
+    fn core_with_rom(name: &str, rom: &[uword]) -> Core {
+        let path = env::temp_dir().join(format!("chip8-rs-test-{}.ch8", name));
+        File::create(&path).and_then(|mut file| file.write_all(rom)).unwrap();
+
+        let mut config = Config::default();
+        config.rom_database = false;
+        let mut core = Core::new(Some(config));
+        core.reset(path.to_str().unwrap()).unwrap();
+        core
+    }
+
+    #[test]
+    fn state_needs_reset() {
+        let core = Core::new(None);
+        assert!(core.pc().is_err());
+        assert!(core.read_memory(0x200, &mut [0; 2]).is_err());
+    }
+
+    #[test]
+    fn memory_ranges() {
+        let mut core = core_with_rom("memory", &[0x12, 0x00]);
+        let mut values = [0; 2];
+        core.read_memory(0x200, &mut values).unwrap();
+        assert_eq!(values, [0x12, 0x00]);
+
+        core.write_memory(0xFFE, &[0xAB, 0xCD]).unwrap();
+        core.read_memory(0xFFE, &mut values).unwrap();
+        assert_eq!(values, [0xAB, 0xCD]);
+
+        assert!(core.read_memory(0xFFF, &mut values).is_err());
+        assert!(core.write_memory(0x1000, &[0]).is_err());
+        assert!(core.write_memory(usize::max_value(), &[0, 0]).is_err());
+        assert!(core.read_memory(0x1000, &mut []).is_ok());
+    }
+
+    #[test]
+    fn register_ranges() {
+        let mut core = core_with_rom("registers", &[0x12, 0x00]);
+        core.set_gpr(0xF, 0x42).unwrap();
+        assert_eq!(core.gpr(0xF), Ok(0x42));
+        assert!(core.gpr(0x10).is_err());
+        assert!(core.set_gpr(0x10, 0).is_err());
+
+        core.set_i(0xFFFF).unwrap();
+        assert_eq!(core.i(), Ok(0xFFFF));
+
+        core.set_pc(0xFFE).unwrap();
+        assert_eq!(core.pc(), Ok(0xFFE));
+        assert!(core.set_pc(0x1000).is_err());
+        assert_eq!(core.pc(), Ok(0xFFE));
+
+        core.set_stack(&[0x202, 0x306]).unwrap();
+        assert_eq!(core.stack(), Ok(vec![0x202, 0x306]));
+        assert!(core.set_stack(&[0x202, 0x1000]).is_err());
+        assert_eq!(core.stack(), Ok(vec![0x202, 0x306]));
+    }
+
+    #[test]
+    fn keys_and_timers() {
+        let mut core = core_with_rom("keys", &[0x12, 0x00]);
+        assert_eq!(core.key(0xF), Ok(false));
+        assert_eq!(core.keys(), Ok(0));
+        assert!(core.key(0x10).is_err());
+        assert!(core.set_key(0x10, true).is_err());
+
+        core.set_delay_timer(0x30).unwrap();
+        core.set_sound_timer(0x20).unwrap();
+        assert_eq!(core.delay_timer(), Ok(0x30));
+        assert_eq!(core.sound_timer(), Ok(0x20));
+        assert_eq!(core.is_halted(), Ok(false));
+    }
+}
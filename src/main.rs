@@ -1,16 +1,13 @@
-#![feature(drop_types_in_const)]
-
 #[macro_use]
 extern crate log;
 extern crate log4rs;
-extern crate futures_cpupool;
 extern crate sdl2;
 
 extern crate chip8_rs as chip8;
 
-use sdl2::Sdl;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::controller::{Axis, Button, GameController};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::VideoSubsystem;
 use sdl2::render::WindowCanvas;
@@ -18,55 +15,155 @@ use sdl2::render::TextureCreator;
 use sdl2::video::WindowContext;
 use sdl2::render::Texture;
 use sdl2::AudioSubsystem;
-use sdl2::audio::AudioDevice;
-use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioQueue;
 use sdl2::audio::AudioSpecDesired;
-use futures_cpupool::CpuPool;
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::Instant;
 use chip8::Core;
 use chip8::Config;
+use chip8::config::{ConfigFile, CONFIG_PATH};
+use chip8::host::Host;
+use chip8::pacing::{Pacer, PacingSettings, SpeedMode};
+use chip8::video;
+use chip8::video::palette::Palette;
+use chip8::video::phosphor::Phosphor;
+use chip8::video::filter::{PostProcess, rgba};
+use chip8::keymap::{Keymap, HostInput};
+use chip8::browser::{RomBrowser, ROMS_PATH};
+use chip8::gdb::GdbStub;
+use chip8::trace::TraceFormat;
+use chip8::coverage;
+use chip8::sanitizer::SanitizerMode;
+use chip8::common::constants::memory::MEMORY_SIZE;
 use chip8::common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32
+/// Rom loaded when the config file does not name one.
+const DEFAULT_ROM_PATH: &'static str = "./workspace/roms/BLINKY";
+
+/// Most audio kept queued for playback (s). Samples arriving while the queue is fuller are dropped,
+/// so the sound doesn't lag behind when emulation runs faster than real time.
+const MAX_AUDIO_QUEUED_S: f32 = 0.1;
+
+/// The core's host: keeps the framebuffer for the next present, queues the audio and passes on the key changes.
+/// Shared between the core and the main loop.
+struct SdlHost {
+    /// Last framebuffer received, shown on the next present.
+    framebuffer: [bool; HORIZONTAL_RES * VERTICAL_RES],
+
+    /// Playback of the samples synthesised by the core. None when audio is disabled.
+    audio_queue: Option<AudioQueue<f32>>,
+
+    /// Key changes from the keymap, applied on the next run.
+    key_changes: Vec<(usize, bool)>,
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
+impl Host for SdlHost {
+    fn video(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES]) {
+        self.framebuffer = *framebuffer;
+    }
 
-    fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = if (self.phase >= 0.0) && (self.phase < 0.5) { self.volume }
-                 else { -self.volume };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+    fn audio_samples(&mut self, samples: &[f32]) {
+        if let Some(ref audio_queue) = self.audio_queue {
+            let spec = audio_queue.spec();
+            let max_queued = (spec.freq as f32 * MAX_AUDIO_QUEUED_S) as u32 * 4;
+            if audio_queue.size() < max_queued {
+                audio_queue.queue(samples);
+            }
         }
     }
+
+    fn input(&mut self) -> Vec<(usize, bool)> {
+        self.key_changes.drain(..).collect()
+    }
 }
 
-struct SdlContext {
-    sdl_context: Option<Sdl>,
-    _video_subsystem: Option<VideoSubsystem>,
-    window_canvas: Option<WindowCanvas>,
-    texture_creator: Option<TextureCreator<WindowContext>>,
-    texture: Option<Texture<'static>>,
-    audio_subsystem: Option<AudioSubsystem>,
-    audio_device: Option<AudioDevice<SquareWave>>
+/// The window, along with the display settings.
+struct Display {
+    canvas: WindowCanvas,
+    palette: Palette,
+    phosphor: Option<Phosphor>,
+    post_process: PostProcess,
 }
 
-static mut SDL_CONTEXT: SdlContext = SdlContext {
-    sdl_context: None,
-    _video_subsystem: None,
-    window_canvas: None,
-    texture_creator: None,
-    texture: None,
-    audio_subsystem: None,
-    audio_device: None,
-};
+impl Display {
+    fn new(video_subsystem: &VideoSubsystem, config_file: &ConfigFile) -> Result<Display, String> {
+        let window = video_subsystem.window("chip8-rs", HORIZONTAL_RES as u32 * config_file.video.scale, VERTICAL_RES as u32 * config_file.video.scale)
+            .position_centered()
+            .resizable()
+            .opengl()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Display {
+            canvas,
+            palette: config_file.palette()?,
+            phosphor: if config_file.video.phosphor > 0.0 { Some(Phosphor::new(config_file.video.phosphor)) } else { None },
+            post_process: config_file.post_process()?,
+        })
+    }
+
+    /// Returns the scale of a display pixel in the window (as seen, for captures).
+    fn scale(&self) -> usize {
+        let (width, height) = self.canvas.output_size().unwrap();
+        self.post_process.pixel_scale(HORIZONTAL_RES, VERTICAL_RES, Some((width as usize, height as usize)))
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.post_process.border = rgba(palette.background());
+    }
+
+    /// Advances the phosphor persistence by the emulated time given (us), ending with the framebuffer.
+    fn advance_phosphor(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], elapsed_us: f64) {
+        if let Some(ref mut phosphor) = self.phosphor {
+            phosphor.update(framebuffer, elapsed_us);
+        }
+    }
+
+    /// Clears the phosphor persistence, so a previous rom's afterglow doesn't show.
+    fn clear_phosphor(&mut self) {
+        if let Some(ref mut phosphor) = self.phosphor {
+            phosphor.clear();
+        }
+    }
+
+    /// Shows the framebuffer, through the phosphor persistence if it's emulated (rather than eg the rom browser).
+    /// The image is post-processed to the window size, recreating the texture when the size changes.
+    fn present<'a>(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], emulated: bool, texture_creator: &'a TextureCreator<WindowContext>, texture: &mut Option<Texture<'a>>) {
+        let (width, height) = self.canvas.output_size().unwrap();
+        let phosphor = if emulated { self.phosphor.as_ref() } else { None };
+        let display = video::image(framebuffer, &self.palette, phosphor);
+        let image = self.post_process.apply(&display, Some((width as usize, height as usize)));
+
+        let resized = match *texture {
+            Some(ref texture) => texture.query().width != width || texture.query().height != height,
+            None => true,
+        };
+        if resized {
+            *texture = Some(texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, width, height).unwrap());
+        }
+
+        let texture = texture.as_mut().unwrap();
+        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            image.write_rgb24(buffer, pitch);
+        }).unwrap();
+        self.canvas.clear();
+        self.canvas.copy(texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
 
 fn main() {
-    init_sdl2();
     log4rs::init_file("./workspace/config/log.yml", Default::default()).unwrap();
     if cfg!(build = "debug") {
         info!("Started (debug)");
@@ -74,224 +171,538 @@ fn main() {
         info!("Started (release)")
     }
 
-    let config = Config {
-        workspace_path: "./workspace/".to_owned(),
-        time_delta_us: 20000.0,
-        multithreaded_pool: Some(CpuPool::new_num_cpus()),
-        cpu_bias: 1.0, 
-        spu_bias: 1.0,
-        timer_bias: 1.0,
-        video_callback: Some(render),
-        audio_callback: Some(play_beep),
+    // Settings come from the config file ('--config <path>', or workspace/config/config.yml if present),
+    // overridden by '--set section.field=value' and the flags below.
+    let config_file = match load_config_file() {
+        Ok(config_file) => config_file,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
     };
-    let mut core = Core::new(Some(config));
-    core.reset("./workspace/roms/BLINKY").unwrap();
-
-    unsafe {
-        let event_pump = &mut SDL_CONTEXT.sdl_context.as_mut().unwrap().event_pump().unwrap();
-        'running: loop {
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+    let mut base_settings = match load_settings(&config_file) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Invalid config: {}", e);
+            process::exit(1);
+        },
+    };
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let game_controller_subsystem = sdl_context.game_controller().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let mut display = Display::new(&video_subsystem, &config_file).unwrap();
+    let texture_creator = display.canvas.texture_creator();
+    let mut texture = None;
+
+    let host = Rc::new(RefCell::new(SdlHost {
+        framebuffer: [false; HORIZONTAL_RES * VERTICAL_RES],
+        audio_queue: if config_file.audio.enabled { Some(open_audio(&audio_subsystem, &config_file).unwrap()) } else { None },
+        key_changes: Vec::new(),
+    }));
+
+    let mut rom_path = config_file.rom.clone().unwrap_or_else(|| DEFAULT_ROM_PATH.to_owned());
+    let mut core = Core::new(Some(emulator_config(&config_file, &host).unwrap()));
+    core.reset(&rom_path).unwrap();
+
+    // The keymap and palette are the configured ones, with any recommended for the rom applied (see romdb).
+    let mut settings = rom_settings(&base_settings, &core);
+    display.set_palette(settings.palette);
+
+    // Emulated time follows wall time, at the configured speed (see pacing). F7 pauses and resumes, F8
+    // advances a frame (pausing first), Tab fast-forwards while held and F9 toggles slow motion.
+    let mut pacer = Pacer::new(core.time_delta_us(), base_settings.pacing);
+    let mut slow_motion = false;
+
+    // Game controllers are opened as they are connected (including those connected on start).
+    let mut game_controllers: Vec<GameController> = Vec::new();
+
+    // Rom browser, shown in place of the display while open (F1). Roms can also be dropped onto the window.
+    // F5 soft resets (restarts the rom), F6 hard resets (recreates the core from the config file).
+    // F12 saves a screenshot, F11 starts or stops a GIF recording and F10 a WAV recording of the audio,
+    // all in workspace/captures/.
+    let mut browser: Option<RomBrowser> = None;
+
+    // Optional gdb stub, enabled with '--gdb <port>'.
+    let mut gdb_stub = match env::args().skip_while(|arg| arg != "--gdb").nth(1) {
+        Some(port) => {
+            let stub = port.parse::<u16>()
+                .map_err(|_| format!("Invalid gdb port '{}'", port))
+                .and_then(GdbStub::bind);
+            match stub {
+                Ok(stub) => Some(stub),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                },
+            }
+        },
+        None => None,
+    };
+
+    // Optional instruction trace, enabled with '--trace <path>' (binary format if the path ends in '.bin').
+    if let Some(path) = env::args().skip_while(|arg| arg != "--trace").nth(1) {
+        let format = if path.ends_with(".bin") { TraceFormat::Binary } else { TraceFormat::Text };
+        if let Err(e) = core.start_trace(&path, format, None) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    // Optional profiling, enabled with '--profile <path>'. The callgrind profile is written on exit.
+    let profile_path = env::args().skip_while(|arg| arg != "--profile").nth(1);
+    if profile_path.is_some() {
+        core.start_profiling();
+    }
+
+    // Optional memory access sanitizer, enabled with '--sanitize <warn|stop>'.
+    match env::args().skip_while(|arg| arg != "--sanitize").nth(1).as_ref().map(|mode| mode.as_str()) {
+        Some("warn") => core.enable_sanitizer(SanitizerMode::Warn),
+        Some("stop") => core.enable_sanitizer(SanitizerMode::Stop),
+        Some(mode) => {
+            eprintln!("Invalid sanitizer mode '{}' (expected warn or stop)", mode);
+            process::exit(1);
+        },
+        None => {},
+    }
+
+    // Optional coverage, enabled with '--coverage <path>'. The annotated listing and heatmap are written on exit.
+    let coverage_path = env::args().skip_while(|arg| arg != "--coverage").nth(1);
+    if coverage_path.is_some() {
+        if let Err(e) = core.enable_coverage() {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    'running: loop {
+        let mut rom_changed = false;
+        for event in event_pump.poll_iter() {
+            if let Event::Quit {..} = event {
+                break 'running;
+            }
+
+            if let Event::DropFile { filename, .. } = event {
+                browser = None;
+                rom_changed = switch_rom(&mut core, &mut rom_path, &filename);
+                continue;
+            }
+
+            if let Some(mut rom_browser) = browser.take() {
+                match browser_event(&mut rom_browser, &event) {
+                    BrowserAction::None => browser = Some(rom_browser),
+                    BrowserAction::Close => {},
+                    BrowserAction::Load(path) => rom_changed = switch_rom(&mut core, &mut rom_path, &path),
+                }
+                continue;
+            }
+
+            match event {
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    break 'running;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                    match RomBrowser::open(&format!("./workspace/{}", ROMS_PATH)) {
+                        Ok(mut rom_browser) => {
+                            rom_browser.select_path(&rom_path);
+                            release_keys(&mut host.borrow_mut(), &mut settings.keymap);
+                            browser = Some(rom_browser);
+                        },
+                        Err(e) => error!("{}", e),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => {
+                    info!("Soft reset");
+                    if let Err(e) = core.reset(&rom_path) {
+                        error!("Encountered error (exiting): {}", e);
                         break 'running;
-                    },
-                    Event::KeyUp {..} | Event::KeyDown {..} => {
-                        if let Err(e) = send_key_event(&core, event) {
+                    }
+                    display.clear_phosphor();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F6), repeat: false, .. } => {
+                    info!("Hard reset");
+                    match hard_reset(&mut core, &rom_path, &host) {
+                        Ok(new_settings) => {
+                            base_settings = new_settings;
+                            pacer = Pacer::new(core.time_delta_us(), base_settings.pacing);
+                            slow_motion = false;
+                            rom_changed = true;
+                        },
+                        Err(e) => {
                             error!("Encountered error (exiting): {}", e);
+                            break 'running;
+                        },
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), repeat: false, .. } => {
+                    let paused = !pacer.is_paused();
+                    pacer.set_paused(paused);
+                    info!("{}", if paused { "Paused" } else { "Resumed" });
+                },
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
+                    pacer.advance();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    pacer.set_mode(SpeedMode::FastForward);
+                },
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => {
+                    pacer.set_mode(if slow_motion { SpeedMode::SlowMotion } else { SpeedMode::Normal });
+                },
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    slow_motion = !slow_motion;
+                    if pacer.mode() != SpeedMode::FastForward {
+                        pacer.set_mode(if slow_motion { SpeedMode::SlowMotion } else { SpeedMode::Normal });
+                    }
+                    info!("Slow motion {}", if slow_motion { "on" } else { "off" });
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    match core.screenshot(None, &display.palette, display.scale()) {
+                        Ok(path) => info!("Screenshot saved to {}", path),
+                        Err(e) => error!("Could not save screenshot: {}", e),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    if core.is_recording() {
+                        stop_gif_recording(&mut core);
+                    } else {
+                        match core.start_recording(None, &display.palette, display.scale()) {
+                            Ok(path) => info!("Recording to {}", path),
+                            Err(e) => error!("Could not start recording: {}", e),
                         }
-                    },
-                    _ => {}
-                }
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F10), repeat: false, .. } => {
+                    if core.is_recording_audio() {
+                        stop_audio_recording(&mut core);
+                    } else {
+                        match core.start_audio_recording(None) {
+                            Ok(path) => info!("Recording audio to {}", path),
+                            Err(e) => error!("Could not start audio recording: {}", e),
+                        }
+                    }
+                },
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match game_controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            info!("Game controller connected: {}", controller.name());
+                            game_controllers.push(controller);
+                        },
+                        Err(e) => error!("Could not open game controller {}: {}", which, e),
+                    }
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    game_controllers.retain(|controller| controller.instance_id() != which);
+                },
+                _ => send_input_event(&mut host.borrow_mut(), &mut settings.keymap, &event),
             }
+        }
 
-            if let Err(e) = core.run() {
-                error!("Encountered error (exiting): {}", e);
-                break 'running;
+        if rom_changed {
+            release_keys(&mut host.borrow_mut(), &mut settings.keymap);
+            settings = rom_settings(&base_settings, &core);
+            display.set_palette(settings.palette);
+            display.clear_phosphor();
+            if let Ok(framebuffer) = core.framebuffer() {
+                host.borrow_mut().framebuffer = framebuffer;
+            }
+        }
+
+        // Emulation is paused while browsing.
+        if let Some(ref rom_browser) = browser {
+            let mut framebuffer = [false; HORIZONTAL_RES * VERTICAL_RES];
+            rom_browser.render(&mut framebuffer);
+            display.present(&framebuffer, false, &texture_creator, &mut texture);
+            pacer.reset_clock();
+            continue;
+        }
+
+        // Run the emulated time due since the last frame, skipping the frames in between.
+        let runs = pacer.runs(Instant::now());
+        for _ in 0..runs {
+            let result = core.run();
+            display.advance_phosphor(&host.borrow().framebuffer, core.time_delta_us());
+            match result {
+                Ok(Some(reason)) => {
+                    info!("Emulation stopped by debugger: {}", reason);
+                    if let Some(ref mut stub) = gdb_stub {
+                        if let Err(e) = stub.notify_stop(&reason) {
+                            error!("Gdb stub error: {}", e);
+                        }
+                    }
+                    break;
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    error!("Encountered error (exiting): {}", e);
+                    break 'running;
+                },
+            }
+        }
+
+        if let Some(ref mut stub) = gdb_stub {
+            if let Err(e) = stub.poll(&mut core) {
+                error!("Gdb stub error: {}", e);
+            }
+        }
+
+        // While the Cpu waits for a key the display doesn't change, so sleep until the next run is due
+        // rather than presenting the same frame again.
+        if runs == 0 && core.is_halted().unwrap_or(false) {
+            if let Some(wait) = pacer.time_until_run(Instant::now()) {
+                thread::sleep(wait);
+                continue;
             }
         }
+
+        let framebuffer = host.borrow().framebuffer;
+        display.present(&framebuffer, true, &texture_creator, &mut texture);
     }
     
+    stop_recording(&mut core);
+
+    if let Err(e) = core.stop_trace() {
+        error!("Could not finish trace: {}", e);
+    }
+
+    if let (Some(path), Some(profiler)) = (profile_path, core.stop_profiling()) {
+        info!("Profile:\n{}", profiler.report());
+        if let Err(e) = profiler.write_callgrind(&path) {
+            error!("Could not write profile: {}", e);
+        }
+    }
+
+    if let Some(path) = coverage_path {
+        if let Err(e) = write_coverage(&mut core, &path) {
+            error!("Could not write coverage: {}", e);
+        }
+    }
+
     if cfg!(build = "debug") {
         debug!("Memory dumped to workspace/dumps folder");
         core.debug_dump_all("_exit").unwrap();
     }
 }
 
-fn init_sdl2() {
-    unsafe {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-        let audio_subsystem = sdl_context.audio().unwrap();
-        let window = video_subsystem.window("chip8-rs", 800, 600)
-            .position_centered()
-            .opengl()
-            .build()
-            .unwrap();
-        let window_canvas = window.into_canvas()
-            .present_vsync()
-            .build()
-            .unwrap();
-        let texture_creator = window_canvas.texture_creator();
-
-        SDL_CONTEXT = SdlContext {
-            sdl_context: Some(sdl_context),
-            _video_subsystem: Some(video_subsystem),
-            window_canvas: Some(window_canvas),
-            texture_creator: Some(texture_creator),
-            texture: None,
-            audio_subsystem: Some(audio_subsystem),
-            audio_device: None,
-        };
+/// Returns the emulator config for the SDL frontend (multithreaded unless set otherwise).
+/// The audio is synthesised at the rate of the audio queue, which is opened on start (so enabling audio
+/// or changing its sample rate needs a restart).
+fn emulator_config(config_file: &ConfigFile, host: &Rc<RefCell<SdlHost>>) -> Result<Config, String> {
+    let mut config = config_file.config()?;
+    config.multithreaded_pool = config_file.multithreaded_pool(true);
+    match host.borrow().audio_queue {
+        Some(ref audio_queue) => if let Some(ref mut audio) = config.audio {
+            audio.sample_rate = audio_queue.spec().freq as u32;
+        },
+        None => config.audio = None,
+    }
+    config.host = Some(Box::new(host.clone()));
+    Ok(config)
+}
 
-        SDL_CONTEXT.texture = Some((&mut SDL_CONTEXT).texture_creator.as_mut().unwrap()
-            .create_texture_streaming(PixelFormatEnum::RGB24, HORIZONTAL_RES as u32, VERTICAL_RES as u32).unwrap());
+/// Resets the core with another rom, going back to the current rom if it can't be loaded.
+/// Returns true if the rom was changed.
+fn switch_rom(core: &mut Core, rom_path: &mut String, path: &str) -> bool {
+    match core.reset(path) {
+        Ok(()) => {
+            info!("Loaded rom {}", path);
+            *rom_path = path.to_owned();
+            true
+        },
+        Err(e) => {
+            error!("Could not load rom {}: {}", path, e);
+            if let Err(e) = core.reset(rom_path) {
+                error!("Could not reload rom {}: {}", rom_path, e);
+            }
+            false
+        },
+    }
+}
 
-        let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1),  // mono
-            samples: None       // default sample size
-        };
-        SDL_CONTEXT.audio_device = Some((&mut SDL_CONTEXT).audio_subsystem.as_mut().unwrap().open_playback(None, &desired_spec, 
-            |spec| {
-                // initialize the audio callback
-                SquareWave {
-                    phase_inc: 440.0 / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.25
-                }
-            }).unwrap());
+/// Replaces the core with a new one, created from the config file as it is now, and resets it with the rom.
+/// Unlike a soft reset (Core::reset()), this discards the debugger, profiler, coverage and sanitizer state,
+/// and finishes any recording. Returns the frontend settings from the config file.
+fn hard_reset(core: &mut Core, rom_path: &str, host: &Rc<RefCell<SdlHost>>) -> Result<FrontendSettings, String> {
+    let config_file = load_config_file()?;
+    let settings = load_settings(&config_file)?;
+    stop_recording(core);
+    *core = Core::new(Some(emulator_config(&config_file, host)?));
+    core.reset(rom_path)?;
+    Ok(settings)
+}
+
+/// Finishes the GIF and WAV recordings, if any.
+fn stop_recording(core: &mut Core) {
+    stop_gif_recording(core);
+    stop_audio_recording(core);
+}
+
+/// Finishes the GIF recording, if any.
+fn stop_gif_recording(core: &mut Core) {
+    match core.stop_recording() {
+        Ok(Some((path, frames))) => info!("Recorded {} frames to {}", frames, path),
+        Ok(None) => {},
+        Err(e) => error!("Could not finish recording: {}", e),
     }
 }
 
-fn render(framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES]) { 
-    unsafe {
-        let sdl_context = &mut SDL_CONTEXT;
-        sdl_context.window_canvas.as_mut().unwrap().clear();
-        sdl_context.texture.as_mut().unwrap().with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for y in 0..VERTICAL_RES {
-                for x in 0..HORIZONTAL_RES {
-                    let fb_index = y * HORIZONTAL_RES + x;
-                    let tex_offset = (y * pitch) + (x * 3);
-                    buffer[tex_offset] = (framebuffer[fb_index] as u8) * 255;
-                    buffer[tex_offset + 1] = (framebuffer[fb_index] as u8) * 255;
-                    buffer[tex_offset + 2] = (framebuffer[fb_index] as u8) * 255;
-                }
+/// Finishes the WAV recording, if any.
+fn stop_audio_recording(core: &mut Core) {
+    match core.stop_audio_recording() {
+        Ok(Some((path, samples))) => info!("Recorded {} audio samples to {}", samples, path),
+        Ok(None) => {},
+        Err(e) => error!("Could not finish audio recording: {}", e),
+    }
+}
+
+/// Releases all held keys, so keys held when the emulation is paused or the keymap changes don't stay pressed.
+fn release_keys(host: &mut SdlHost, keymap: &mut Keymap) {
+    for key in keymap.release_all() {
+        host.key_changes.push((key, false));
+    }
+}
+
+enum BrowserAction {
+    None,
+    Close,
+    Load(String),
+}
+
+/// Handles a key press while the rom browser is open.
+fn browser_event(browser: &mut RomBrowser, event: &Event) -> BrowserAction {
+    if let Event::KeyDown { keycode: Some(keycode), .. } = *event {
+        match keycode {
+            Keycode::Escape | Keycode::F1 => return BrowserAction::Close,
+            Keycode::Return => {
+                return match browser.selected_path() {
+                    Some(path) => BrowserAction::Load(path),
+                    None => BrowserAction::Close,
+                };
+            },
+            Keycode::Up => browser.move_selection(-1),
+            Keycode::Down => browser.move_selection(1),
+            Keycode::PageUp => browser.move_page(-1),
+            Keycode::PageDown => browser.move_page(1),
+            Keycode::Home => browser.move_selection(isize::min_value()),
+            Keycode::End => browser.move_selection(isize::max_value()),
+            _ => {},
+        }
+    }
+    BrowserAction::None
+}
+
+/// Loads the config file, applying '--set section.field=value' and the shorthand flags as overrides.
+fn load_config_file() -> Result<ConfigFile, String> {
+    let args: Vec<String> = env::args().collect();
+    let mut config_path = None;
+    let mut overrides = Vec::new();
+
+    for (index, arg) in args.iter().enumerate() {
+        // Strings are quoted, so values like '1' are not read as numbers.
+        if let Some(value) = args.get(index + 1) {
+            match arg.as_str() {
+                "--config" => config_path = Some(value.clone()),
+                "--set" => overrides.push(value.clone()),
+                "--rom" => overrides.push(format!("rom={:?}", value)),
+                "--font" => overrides.push(format!("emulator.font={:?}", value)),
+                "--big-font" => overrides.push(format!("emulator.big_font={:?}", value)),
+                "--layout" => overrides.push(format!("emulator.memory_layout={:?}", value)),
+                _ => {},
             }
-        }).unwrap(); 
-        sdl_context.window_canvas.as_mut().unwrap().copy(&sdl_context.texture.as_mut().unwrap(), None, None).unwrap();
-        sdl_context.window_canvas.as_mut().unwrap().present();
+        }
+        if arg == "--no-rom-database" {
+            overrides.push("emulator.rom_database=false".to_owned());
+        }
+    }
+
+    match config_path {
+        Some(path) => ConfigFile::load(&path, true, &overrides),
+        None => ConfigFile::load(&format!("./workspace/{}", CONFIG_PATH), false, &overrides),
     }
 }
 
-fn play_beep(on: bool) {
-    unsafe {
-        let sdl_context = &mut SDL_CONTEXT;
-        if on {
-            sdl_context.audio_device.as_mut().unwrap().resume();
-        } else {
-            sdl_context.audio_device.as_mut().unwrap().pause();
+/// Frontend settings from the config file, the keymap and palette of which rom database entries can override.
+#[derive(Clone)]
+struct FrontendSettings {
+    keymap: Keymap,
+    palette: Palette,
+    pacing: PacingSettings,
+}
+
+/// Returns the frontend settings from the config file, checking the key names against SDL's.
+fn load_settings(config_file: &ConfigFile) -> Result<FrontendSettings, String> {
+    let keymap = config_file.keymap()?;
+    keymap.validate("keys", is_known_input)?;
+    Ok(FrontendSettings {
+        keymap,
+        palette: config_file.palette()?,
+        pacing: config_file.pacing_settings()?,
+    })
+}
+
+/// Returns the settings with the keymap of the loaded rom's database entry and the palette recommended
+/// for the rom (see Core::rom_palette()) applied, if any.
+fn rom_settings(base_settings: &FrontendSettings, core: &Core) -> FrontendSettings {
+    let mut settings = base_settings.clone();
+    if let Some(entry) = core.rom_entry() {
+        let result = settings.keymap.apply("keymap", &entry.keymap).and_then(|_| settings.keymap.validate("keymap", is_known_input));
+        if let Err(e) = result {
+            warn!("Ignoring rom database keymap: {}", e);
+            settings.keymap = base_settings.keymap.clone();
         }
     }
+
+    match core.rom_palette() {
+        Ok(Some(palette)) => settings.palette = palette,
+        Ok(None) => {},
+        Err(e) => warn!("Ignoring rom palette: {}", e),
+    }
+    settings
 }
 
-fn send_key_event(core: &Core, event: Event) -> Result<(), String> {
-    match event {
-        Event::KeyDown { keycode: Some(Keycode::Num1), .. } => {
-            core.set_key(0x1, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::Num1), .. } => {
-            core.set_key(0x1, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::Num2), .. } => {
-            core.set_key(0x2, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::Num2), .. } => {
-            core.set_key(0x2, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::Num3), .. } => {
-            core.set_key(0x3, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::Num3), .. } => {
-            core.set_key(0x3, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::Num4), .. } => {
-            core.set_key(0xC, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::Num4), .. } => {
-            core.set_key(0xC, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::Q), .. } => {
-            core.set_key(0x4, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::Q), .. } => {
-            core.set_key(0x4, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::W), .. } => {
-            core.set_key(0x5, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::W), .. } => {
-            core.set_key(0x5, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::E), .. } => {
-            core.set_key(0x6, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::E), .. } => {
-            core.set_key(0x6, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::R), .. } => {
-            core.set_key(0xD, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::R), .. } => {
-            core.set_key(0xD, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::A), .. } => {
-            core.set_key(0x7, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::A), .. } => {
-            core.set_key(0x7, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-            core.set_key(0x8, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::S), .. } => {
-            core.set_key(0x8, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::D), .. } => {
-            core.set_key(0x9, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::D), .. } => {
-            core.set_key(0x9, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::F), .. } => {
-            core.set_key(0xE, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::F), .. } => {
-            core.set_key(0xE, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::Z), .. } => {
-            core.set_key(0xA, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::Z), .. } => {
-            core.set_key(0xA, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::X), .. } => {
-            core.set_key(0x0, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::X), .. } => {
-            core.set_key(0x0, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::C), .. } => {
-            core.set_key(0xB, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::C), .. } => {
-            core.set_key(0xB, false)
-        },
-        Event::KeyDown { keycode: Some(Keycode::V), .. } => {
-            core.set_key(0xF, true)
-        },
-        Event::KeyUp { keycode: Some(Keycode::V), .. } => {
-            core.set_key(0xF, false)
-        },
-        _ => { Ok(()) }
+fn is_known_input(input: &HostInput) -> bool {
+    match *input {
+        HostInput::Key(ref name) => Keycode::from_name(name).is_some(),
+        HostInput::Button(ref name) => Button::from_string(name).is_some(),
+        HostInput::Axis(ref name, _) => Axis::from_string(name).is_some(),
     }
-}
\ No newline at end of file
+}
+
+/// Writes the annotated listing and heatmap of the rom coverage to the file at path.
+fn write_coverage(core: &mut Core, path: &str) -> Result<(), String> {
+    let mut memory = vec![0; MEMORY_SIZE];
+    core.read_memory(0, &mut memory)?;
+    let access_map = core.disable_coverage()?.ok_or("Coverage not enabled")?;
+    let range = core.rom_range();
+
+    let mut file = File::create(path).map_err(|e| e.to_string())?;
+    write!(file, "{}\n{}", coverage::listing(&memory, &access_map, range.clone()), coverage::heatmap(&access_map, range)).map_err(|e| e.to_string())
+}
+
+/// Opens the audio queue at the configured sample rate, playing as samples are queued.
+fn open_audio(audio_subsystem: &AudioSubsystem, config_file: &ConfigFile) -> Result<AudioQueue<f32>, String> {
+    let desired_spec = AudioSpecDesired {
+        freq: Some(config_file.audio.sample_rate as i32),
+        channels: Some(1),  // mono
+        samples: None       // default sample size
+    };
+    let audio_queue = audio_subsystem.open_queue(None, &desired_spec)?;
+    audio_queue.resume();
+    Ok(audio_queue)
+}
+
+/// Passes keyboard and game controller input through the keymap to the core (on its next run).
+fn send_input_event(host: &mut SdlHost, keymap: &mut Keymap, event: &Event) {
+    let changes = match *event {
+        Event::KeyDown { keycode: Some(keycode), .. } => keymap.update(HostInput::key(&keycode.name()), true).into_iter().collect(),
+        Event::KeyUp { keycode: Some(keycode), .. } => keymap.update(HostInput::key(&keycode.name()), false).into_iter().collect(),
+        Event::ControllerButtonDown { button, .. } => keymap.update(HostInput::button(&button.string()), true).into_iter().collect(),
+        Event::ControllerButtonUp { button, .. } => keymap.update(HostInput::button(&button.string()), false).into_iter().collect(),
+        Event::ControllerAxisMotion { axis, value, .. } => keymap.update_axis(&axis.string(), value),
+        _ => Vec::new(),
+    };
+
+    host.key_changes.extend(changes);
+}
@@ -0,0 +1,178 @@
+//! Real-time pacing.
+//!
+//! Each Core::run() emulates a fixed time step (Config::time_delta_us), so a
+//! frontend calling it once per displayed frame runs at a speed set by the
+//! display refresh rate. A Pacer maps host wall time to emulated time
+//! instead: each frame, runs() returns the number of runs due for the wall
+//! time elapsed, scaled by the speed, carrying the remainder over to the next
+//! frame.
+//!
+//! On hosts too slow to keep up, several runs are made before presenting,
+//! skipping the frames in between. At most max_frame_skip frames are skipped
+//! (at normal speed); time beyond that is dropped, so the emulation slows
+//! down rather than falling further and further behind.
+//!
+//! While paused, no time is emulated, except one run per frame advance.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PacingSettings {
+    /// Speeds, as multiples of real time: normally, while fast-forwarding and in slow motion (see SpeedMode).
+    pub speed: f64,
+    pub fast_forward: f64,
+    pub slow_motion: f64,
+
+    /// Most frames skipped when the host falls behind.
+    pub max_frame_skip: u32,
+}
+
+impl Default for PacingSettings {
+    fn default() -> PacingSettings {
+        PacingSettings {
+            speed: 1.0,
+            fast_forward: 4.0,
+            slow_motion: 0.25,
+            max_frame_skip: 4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpeedMode {
+    Normal,
+    FastForward,
+    SlowMotion,
+}
+
+pub struct Pacer {
+    settings: PacingSettings,
+    time_delta_us: f64,
+    mode: SpeedMode,
+    paused: bool,
+
+    /// Frame advances requested while paused, each one run.
+    advances: usize,
+
+    /// Wall time of the last update, None after a pause or a clock reset.
+    last_update: Option<Instant>,
+
+    /// Emulated time due but not yet run (us).
+    owed_us: f64,
+
+    /// Wall time dropped because the host fell behind (us).
+    dropped_us: f64,
+}
+
+fn duration_us(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e6 + duration.subsec_nanos() as f64 / 1e3
+}
+
+impl Pacer {
+    /// Creates a pacer for runs of the time step given (us).
+    pub fn new(time_delta_us: f64, settings: PacingSettings) -> Pacer {
+        Pacer {
+            settings,
+            time_delta_us,
+            mode: SpeedMode::Normal,
+            paused: false,
+            advances: 0,
+            last_update: None,
+            owed_us: 0.0,
+            dropped_us: 0.0,
+        }
+    }
+
+    pub fn settings(&self) -> &PacingSettings {
+        &self.settings
+    }
+
+    pub fn mode(&self) -> SpeedMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SpeedMode) {
+        self.mode = mode;
+    }
+
+    /// Returns the speed of the current mode, as a multiple of real time.
+    pub fn speed(&self) -> f64 {
+        match self.mode {
+            SpeedMode::Normal => self.settings.speed,
+            SpeedMode::FastForward => self.settings.fast_forward,
+            SpeedMode::SlowMotion => self.settings.slow_motion,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes. Resuming starts timing afresh, so the paused time isn't caught up on.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.advances = 0;
+        self.reset_clock();
+    }
+
+    /// Pauses if running, and requests a single run.
+    pub fn advance(&mut self) {
+        if !self.paused {
+            self.set_paused(true);
+        }
+        self.advances += 1;
+    }
+
+    /// Forgets the wall time elapsed since the last update, for frames where the frontend holds the
+    /// emulation (eg: while a menu is open).
+    pub fn reset_clock(&mut self) {
+        self.last_update = None;
+        self.owed_us = 0.0;
+    }
+
+    /// Returns the wall time dropped so far because the host fell behind (us).
+    pub fn dropped_us(&self) -> f64 {
+        self.dropped_us
+    }
+
+    /// Returns the number of runs due for the wall time elapsed from the last update to the time given.
+    pub fn runs(&mut self, now: Instant) -> usize {
+        if self.paused {
+            let advances = self.advances;
+            self.advances = 0;
+            return advances;
+        }
+
+        let mut elapsed_us = match self.last_update {
+            Some(last_update) if now > last_update => duration_us(now - last_update),
+            _ => 0.0,
+        };
+        self.last_update = Some(now);
+
+        let max_elapsed_us = (self.settings.max_frame_skip + 1) as f64 * self.time_delta_us;
+        if elapsed_us > max_elapsed_us {
+            self.dropped_us += elapsed_us - max_elapsed_us;
+            elapsed_us = max_elapsed_us;
+        }
+
+        self.owed_us += elapsed_us * self.speed();
+        let runs = (self.owed_us / self.time_delta_us).floor();
+        self.owed_us -= runs * self.time_delta_us;
+        runs as usize
+    }
+
+    /// Returns the wall time from the time given until the next run is due, or None while paused
+    /// with no frame advance requested.
+    pub fn time_until_run(&self, now: Instant) -> Option<Duration> {
+        if self.paused {
+            return if self.advances > 0 { Some(Duration::new(0, 0)) } else { None };
+        }
+
+        let elapsed_us = match self.last_update {
+            Some(last_update) if now > last_update => duration_us(now - last_update),
+            _ => 0.0,
+        };
+        let wait_us = ((self.time_delta_us - self.owed_us) / self.speed() - elapsed_us).max(0.0);
+        Some(Duration::new((wait_us / 1e6) as u64, ((wait_us % 1e6) * 1000.0) as u32))
+    }
+}
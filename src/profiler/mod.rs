@@ -0,0 +1,253 @@
+//! Execution profiling.
+//!
+//! Counts executions per address and per opcode class, and attributes cycles
+//! to subroutines by following call and ret instructions. Results can be
+//! reported as text, or written in the callgrind format for use with tools
+//! such as KCachegrind.
+//!
+//! Every instruction costs one cycle, as emulated by the Cpu controller.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use common::constants::cpu::INSTRUCTION_COUNT;
+use common::constants::memory::{MEMORY_SIZE, PROGRAM_START};
+use common::types::primative::*;
+use resources::cpu::instruction::Instruction;
+use resources::cpu::instruction_lookup::MNEMONICS;
+
+/// Amount of entries listed in each section of the text report.
+const REPORT_ENTRIES: usize = 20;
+
+/// Call edge cost, from a call site to a subroutine.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CallCost {
+    /// Amount of times the call was made.
+    pub count: u64,
+
+    /// Cycles spent within the subroutine (and its callees) for completed calls.
+    pub inclusive_cycles: u64,
+}
+
+/// Cycles attributed to a subroutine (or the program entry point).
+#[derive(Debug, Clone, Default)]
+pub struct Function {
+    /// Cycles per instruction address executed within this function (excluding callees).
+    pub lines: BTreeMap<uptr, u64>,
+
+    /// Calls made from this function, keyed by (call site, subroutine address).
+    pub calls: BTreeMap<(uptr, uptr), CallCost>,
+}
+
+impl Function {
+    /// Returns the cycles spent within this function, excluding callees.
+    pub fn self_cycles(&self) -> u64 {
+        self.lines.values().sum()
+    }
+}
+
+/// An active subroutine call.
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    function: uptr,
+    call_site: uptr,
+    start_cycle: u64,
+}
+
+pub struct Profiler {
+    /// Total cycles profiled.
+    cycles: u64,
+
+    /// Executions and last opcode seen per address.
+    address_counts: Vec<(u64, udword)>,
+
+    /// Executions per decoded opcode class (see instruction_lookup).
+    class_counts: [u64; INSTRUCTION_COUNT],
+
+    /// Executions of undecodable instructions.
+    unknown_count: u64,
+
+    /// Functions, keyed by entry address.
+    functions: BTreeMap<uptr, Function>,
+
+    /// Call stack, with the entry point at the bottom.
+    frames: Vec<Frame>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        let mut profiler = Profiler {
+            cycles: 0,
+            address_counts: vec![(0, 0); MEMORY_SIZE],
+            class_counts: [0; INSTRUCTION_COUNT],
+            unknown_count: 0,
+            functions: BTreeMap::new(),
+            frames: Vec::new(),
+        };
+        profiler.reset_stack();
+        profiler
+    }
+
+    /// Clears the call stack, used when the machine is reset. Collected counts are kept.
+    pub fn reset_stack(&mut self) {
+        self.frames.clear();
+        self.frames.push(Frame {
+            function: PROGRAM_START as uptr,
+            call_site: 0,
+            start_cycle: self.cycles,
+        });
+    }
+
+    /// Called by the Cpu controller before executing an instruction.
+    pub fn before_execute(&mut self, pc: uptr, inst: &Instruction) {
+        self.cycles += 1;
+
+        let address = &mut self.address_counts[pc as usize % MEMORY_SIZE];
+        address.0 += 1;
+        address.1 = inst.raw().value;
+
+        match inst.index() {
+            Some(index) => self.class_counts[index] += 1,
+            None => self.unknown_count += 1,
+        }
+
+        let function = self.frames.last().unwrap().function;
+        *self.functions.entry(function).or_insert_with(Function::default).lines.entry(pc).or_insert(0) += 1;
+
+        match inst.mnemonic() {
+            Some("call") => {
+                self.frames.push(Frame {
+                    function: inst.raw().address(),
+                    call_site: pc,
+                    start_cycle: self.cycles,
+                });
+            },
+            Some("ret") => {
+                // A ret without a matching call (eg: the stack was modified) is ignored.
+                if self.frames.len() > 1 {
+                    let frame = self.frames.pop().unwrap();
+                    let caller = self.frames.last().unwrap().function;
+                    let cycles = self.cycles - frame.start_cycle;
+                    let cost = self.call_cost(caller, frame.call_site, frame.function);
+                    cost.count += 1;
+                    cost.inclusive_cycles += cycles;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the amount of executions (and last opcode executed) at an address.
+    pub fn address_count(&self, address: uptr) -> (u64, udword) {
+        self.address_counts[address as usize % MEMORY_SIZE]
+    }
+
+    /// Returns the functions profiled, with calls still in progress accounted for.
+    pub fn functions(&self) -> BTreeMap<uptr, Function> {
+        let mut functions = self.functions.clone();
+        for (depth, frame) in self.frames.iter().enumerate().skip(1) {
+            let caller = self.frames[depth - 1].function;
+            let cost = functions.entry(caller).or_insert_with(Function::default)
+                .calls.entry((frame.call_site, frame.function)).or_insert_with(CallCost::default);
+            cost.count += 1;
+            cost.inclusive_cycles += self.cycles - frame.start_cycle;
+        }
+        functions
+    }
+
+    /// Returns a human readable report of hot spots, opcode classes and subroutines.
+    pub fn report(&self) -> String {
+        let mut report = format!("Total cycles: {}\n", self.cycles);
+
+        report.push_str("\nHot spots:\n");
+        let mut addresses: Vec<(usize, (u64, udword))> = self.address_counts.iter().cloned().enumerate().filter(|&(_, (count, _))| count > 0).collect();
+        addresses.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then(a.0.cmp(&b.0)));
+        for &(address, (count, opcode)) in addresses.iter().take(REPORT_ENTRIES) {
+            report.push_str(&format!("  0x{:03X} {:>12} {:>6.2}%  {}\n", address, count, self.percentage(count), Instruction::new(opcode)));
+        }
+
+        report.push_str("\nOpcode classes:\n");
+        let mut classes: Vec<(&str, u64)> = MNEMONICS.iter().cloned().zip(self.class_counts.iter().cloned()).collect();
+        classes.push(("unknown", self.unknown_count));
+        classes.retain(|&(_, count)| count > 0);
+        classes.sort_by(|a, b| b.1.cmp(&a.1));
+        for &(mnemonic, count) in classes.iter() {
+            report.push_str(&format!("  {:<12} {:>12} {:>6.2}%\n", mnemonic, count, self.percentage(count)));
+        }
+
+        report.push_str("\nSubroutines (inclusive, self, calls):\n");
+        let functions = self.functions();
+        let mut subroutines: Vec<(uptr, u64, u64, u64)> = functions.iter().map(|(&address, function)| {
+            let (inclusive, calls) = if address == PROGRAM_START as uptr {
+                (self.cycles, 0)
+            } else {
+                functions.values()
+                    .flat_map(|caller| caller.calls.iter())
+                    .filter(|&(&(_, callee), _)| callee == address)
+                    .fold((0, 0), |(cycles, count), (_, cost)| (cycles + cost.inclusive_cycles, count + cost.count))
+            };
+            (address, inclusive, function.self_cycles(), calls)
+        }).collect();
+        subroutines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for &(address, inclusive, self_cycles, calls) in subroutines.iter().take(REPORT_ENTRIES) {
+            report.push_str(&format!("  {:<10} {:>12} {:>6.2}% {:>12} {:>6.2}% {:>8}\n", function_name(address),
+                inclusive, self.percentage(inclusive), self_cycles, self.percentage(self_cycles), calls));
+        }
+
+        report
+    }
+
+    /// Writes the profile in the callgrind format to the file at the given path.
+    pub fn write_callgrind(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Could not create profile file {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_callgrind_to(&mut writer).map_err(|e| format!("Could not write profile: {}", e))
+    }
+
+    fn write_callgrind_to<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writeln!(writer, "# callgrind format")?;
+        writeln!(writer, "version: 1")?;
+        writeln!(writer, "creator: chip8-rs")?;
+        writeln!(writer, "positions: instr")?;
+        writeln!(writer, "events: Cycles")?;
+        writeln!(writer, "summary: {}", self.cycles)?;
+        writeln!(writer, "ob=chip8")?;
+
+        for (&address, function) in self.functions().iter() {
+            writeln!(writer, "\nfn={}", function_name(address))?;
+            for (&pc, &cycles) in function.lines.iter() {
+                writeln!(writer, "0x{:X} {}", pc, cycles)?;
+            }
+            for (&(call_site, callee), cost) in function.calls.iter() {
+                writeln!(writer, "cfn={}", function_name(callee))?;
+                writeln!(writer, "calls={} 0x{:X}", cost.count, callee)?;
+                writeln!(writer, "0x{:X} {}", call_site, cost.inclusive_cycles)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    fn call_cost(&mut self, caller: uptr, call_site: uptr, callee: uptr) -> &mut CallCost {
+        self.functions.entry(caller).or_insert_with(Function::default)
+            .calls.entry((call_site, callee)).or_insert_with(CallCost::default)
+    }
+
+    fn percentage(&self, count: u64) -> f64 {
+        if self.cycles == 0 { 0.0 } else { (count as f64) * 100.0 / (self.cycles as f64) }
+    }
+}
+
+/// Returns the display name of the function at an address.
+fn function_name(address: uptr) -> String {
+    if address == PROGRAM_START as uptr {
+        format!("entry_{:03X}", address)
+    } else {
+        format!("sub_{:03X}", address)
+    }
+}
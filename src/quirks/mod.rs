@@ -0,0 +1,87 @@
+//! Interpreter quirks.
+//!
+//! Chip8 interpreters differ in the behaviour of a few instructions, and roms
+//! tend to depend on the interpreter they were written for. The quirk names
+//! and meanings follow Octo's. The defaults match this emulator's original
+//! behaviour.
+//!
+//! The quirks in effect start from the defaults, then take those recommended
+//! for the rom (rom database entry, then Octo cartridge options), then those
+//! set explicitly by the user (see QuirkOverrides).
+
+/// Names of the quirks, as used in config files and the rom database.
+pub const QUIRK_NAMES: [&'static str; 5] = ["shift", "load_store", "jump", "logic", "clip"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place, rather than shifting VY into VX.
+    pub shift: bool,
+
+    /// FX55/FX65 leave I unchanged, rather than incrementing it past the last register.
+    pub load_store: bool,
+
+    /// BNNN jumps to NNN + VX (X being the high nibble of NNN), rather than NNN + V0.
+    pub jump: bool,
+
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic: bool,
+
+    /// Sprites are clipped at the edges of the screen, rather than wrapping around.
+    pub clip: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: false,
+            jump: false,
+            logic: false,
+            clip: false,
+        }
+    }
+}
+
+impl Quirks {
+    /// Sets a quirk by name (see QUIRK_NAMES).
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        match name {
+            "shift" => self.shift = enabled,
+            "load_store" => self.load_store = enabled,
+            "jump" => self.jump = enabled,
+            "logic" => self.logic = enabled,
+            "clip" => self.clip = enabled,
+            _ => return Err(format!("Unknown quirk '{}' (expected one of {})", name, QUIRK_NAMES.join(", "))),
+        }
+        Ok(())
+    }
+}
+
+/// Quirks set explicitly in the config; absent quirks are None, leaving those recommended for the rom.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkOverrides {
+    pub shift: Option<bool>,
+    pub load_store: Option<bool>,
+    pub jump: Option<bool>,
+    pub logic: Option<bool>,
+    pub clip: Option<bool>,
+}
+
+impl QuirkOverrides {
+    /// Sets the quirks given.
+    pub fn apply(&self, quirks: &mut Quirks) {
+        let overrides = [
+            ("shift", self.shift),
+            ("load_store", self.load_store),
+            ("jump", self.jump),
+            ("logic", self.logic),
+            ("clip", self.clip),
+        ];
+        for &(name, enabled) in overrides.iter() {
+            if let Some(enabled) = enabled {
+                quirks.set(name, enabled).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Memory bus.
+//!
+//! Maps the address space onto regions: the font sets (wherever they are
+//! loaded, see Core::font_range()), the rest of the interpreter area, program
+//! RAM and optionally device I/O. Regions backed by memory can be
+//! write-protected, which blocks writes made in the CpuData context. Device
+//! regions forward accesses to the device, with the offset relative to the
+//! start of the region.
+//!
+//! Addresses wrap around at the end of the address space.
+
+use std::ops::Range;
+use common::constants::memory::PROGRAM_START;
+use common::types::primative::*;
+use common::types::storage::*;
+use common::types::storage::memory::word_memory::WordMemory;
+
+/// Names of the default regions.
+pub const REGION_FONT: &'static str = "font";
+pub const REGION_INTERPRETER: &'static str = "interpreter";
+pub const REGION_PROGRAM: &'static str = "program";
+
+/// Returns the regions below the program: the font sets over the given range, and the interpreter
+/// area around them.
+pub fn low_regions(font: Range<usize>) -> Vec<(&'static str, Range<usize>)> {
+    let end = font.end.min(PROGRAM_START);
+    let mut regions = Vec::new();
+    if font.start > 0 {
+        regions.push((REGION_INTERPRETER, 0..font.start));
+    }
+    if font.start < end {
+        regions.push((REGION_FONT, font.start..end));
+    }
+    if end < PROGRAM_START {
+        regions.push((REGION_INTERPRETER, end..PROGRAM_START));
+    }
+    regions
+}
+
+/// Memory-mapped device, attached to the bus with Bus::map_device().
+/// The context is passed through, so devices can avoid side effects for debugger accesses.
+pub trait Device {
+    fn read(&self, ctx: BusContext, offset: usize) -> uword;
+
+    fn write(&self, ctx: BusContext, offset: usize, value: uword);
+
+    /// Called when the machine is reset.
+    fn reset(&self) {}
+}
+
+pub struct Region {
+    pub name: String,
+    pub range: Range<usize>,
+
+    /// Blocks writes in the CpuData context.
+    pub write_protect: bool,
+
+    /// Device handling accesses, or None if backed by memory.
+    device: Option<Box<Device>>,
+}
+
+impl Region {
+    pub fn is_device(&self) -> bool {
+        self.device.is_some()
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        address >= self.range.start && address < self.range.end
+    }
+}
+
+pub struct Bus {
+    memory: WordMemory,
+
+    /// Regions, searched in order (devices are placed first).
+    regions: Vec<Region>,
+}
+
+impl Bus {
+    /// Creates a bus with the default regions (the font sets over the given range), backed by zeroed
+    /// memory of the given size.
+    pub fn new(size: usize, font: Range<usize>) -> Bus {
+        let mut regions = low_regions(font);
+        regions.push((REGION_PROGRAM, PROGRAM_START..size));
+        Bus::with_regions(size, &regions)
+    }
+
+    /// Creates a bus with the given (name, range) memory regions, backed by zeroed memory of the given size.
+    pub fn with_regions(size: usize, regions: &[(&str, Range<usize>)]) -> Bus {
+        Bus {
+            memory: WordMemory::new(size),
+            regions: regions.iter().map(|&(name, ref range)| Region {
+                name: name.to_owned(),
+                range: range.clone(),
+                write_protect: false,
+                device: None,
+            }).collect(),
+        }
+    }
+
+    /// Returns the size of the address space.
+    pub fn size(&self) -> usize {
+        self.memory.size()
+    }
+
+    /// Returns the backing memory (bypassing regions and devices).
+    pub fn memory(&self) -> &WordMemory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut WordMemory {
+        &mut self.memory
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the region containing the address.
+    pub fn region(&self, address: usize) -> Option<&Region> {
+        let address = address % self.size();
+        self.regions.iter().find(|region| region.contains(address))
+    }
+
+    /// Sets the write protection of the named region (of all its parts, eg the interpreter area on
+    /// both sides of the font sets).
+    pub fn set_write_protect(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let mut found = false;
+        for region in self.regions.iter_mut().filter(|region| region.name == name) {
+            region.write_protect = enabled;
+            found = true;
+        }
+        if !found {
+            return Err(format!("Unknown bus region '{}'", name));
+        }
+        Ok(())
+    }
+
+    /// Moves the font region to the given range, the interpreter region covering the rest of the
+    /// area below the program (see low_regions()). The write protection of both is kept.
+    pub fn set_font_range(&mut self, font: Range<usize>) {
+        let is_low = |region: &Region| !region.is_device() && (region.name == REGION_FONT || region.name == REGION_INTERPRETER);
+        let index = self.regions.iter().position(|region| is_low(region)).unwrap_or(self.regions.len());
+        let protected: Vec<String> = self.regions.iter()
+            .filter(|region| is_low(region) && region.write_protect)
+            .map(|region| region.name.clone())
+            .collect();
+
+        self.regions.retain(|region| !is_low(region));
+        for (offset, (name, range)) in low_regions(font).into_iter().enumerate() {
+            self.regions.insert(index + offset, Region {
+                name: name.to_owned(),
+                range,
+                write_protect: protected.iter().any(|protected| protected == name),
+                device: None,
+            });
+        }
+    }
+
+    /// Maps a device over the given address range, taking priority over memory.
+    pub fn map_device(&mut self, name: &str, range: Range<usize>, device: Box<Device>) -> Result<(), String> {
+        if range.start >= range.end || range.end > self.size() {
+            return Err(format!("Device range 0x{:X}-0x{:X} not within valid range", range.start, range.end));
+        }
+        if self.regions.iter().any(|region| region.name == name) {
+            return Err(format!("Bus region '{}' already exists", name));
+        }
+        if self.regions.iter().any(|region| region.is_device() && region.range.start < range.end && range.start < region.range.end) {
+            return Err(format!("Device range 0x{:X}-0x{:X} overlaps another device", range.start, range.end));
+        }
+
+        self.regions.insert(0, Region {
+            name: name.to_owned(),
+            range,
+            write_protect: false,
+            device: Some(device),
+        });
+        Ok(())
+    }
+
+    /// Removes the named device, returning it.
+    pub fn unmap_device(&mut self, name: &str) -> Option<Box<Device>> {
+        match self.regions.iter().position(|region| region.is_device() && region.name == name) {
+            Some(index) => self.regions.remove(index).device,
+            None => None,
+        }
+    }
+
+    /// Clears memory and resets devices, used when the machine is reset.
+    /// Regions and memory access tracking are kept.
+    pub fn reset(&mut self) {
+        self.memory.clear();
+        for region in self.regions.iter() {
+            if let Some(ref device) = region.device {
+                device.reset();
+            }
+        }
+    }
+
+    pub fn read(&self, ctx: BusContext, address: usize) -> uword {
+        let address = address % self.size();
+        match self.region(address) {
+            Some(&Region { device: Some(ref device), ref range, .. }) => device.read(ctx, address - range.start),
+            _ => self.memory.read(ctx, address),
+        }
+    }
+
+    /// Writes a word, returning false if blocked by write protection.
+    pub fn write(&self, ctx: BusContext, address: usize, value: uword) -> bool {
+        let address = address % self.size();
+        match self.region(address) {
+            Some(&Region { write_protect: true, .. }) if ctx == BusContext::CpuData => false,
+            Some(&Region { device: Some(ref device), ref range, .. }) => {
+                device.write(ctx, address - range.start, value);
+                true
+            },
+            _ => {
+                self.memory.write(ctx, address, value);
+                true
+            },
+        }
+    }
+
+    /// Reads a big endian dword (eg: an instruction).
+    pub fn read_dword(&self, ctx: BusContext, address: usize) -> udword {
+        ((self.read(ctx, address) as udword) << 8) | (self.read(ctx, address + 1) as udword)
+    }
+
+    pub fn read_slice(&self, ctx: BusContext, address: usize, values: &mut [uword]) {
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = self.read(ctx, address + index);
+        }
+    }
+
+    pub fn write_slice(&self, ctx: BusContext, address: usize, values: &[uword]) {
+        for (index, &value) in values.iter().enumerate() {
+            self.write(ctx, address + index, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Device reading back the offset accessed, and recording the last write.
+    struct TestDevice {
+        written: Rc<Cell<Option<(usize, uword)>>>,
+    }
+
+    impl Device for TestDevice {
+        fn read(&self, _ctx: BusContext, offset: usize) -> uword {
+            offset as uword
+        }
+
+        fn write(&self, _ctx: BusContext, offset: usize, value: uword) {
+            self.written.set(Some((offset, value)));
+        }
+    }
+
+    fn region_layout(bus: &Bus) -> Vec<(&str, Range<usize>, bool)> {
+        bus.regions().iter().map(|region| (region.name.as_str(), region.range.clone(), region.write_protect)).collect()
+    }
+
+    #[test]
+    fn low_region_layout() {
+        assert_eq!(low_regions(0x50..0xA0), vec![
+            (REGION_INTERPRETER, 0x0..0x50),
+            (REGION_FONT, 0x50..0xA0),
+            (REGION_INTERPRETER, 0xA0..0x200),
+        ]);
+        assert_eq!(low_regions(0..0x50), vec![(REGION_FONT, 0..0x50), (REGION_INTERPRETER, 0x50..0x200)]);
+        assert_eq!(low_regions(0x1C0..0x240), vec![(REGION_INTERPRETER, 0..0x1C0), (REGION_FONT, 0x1C0..0x200)]);
+    }
+
+    #[test]
+    fn write_protection() {
+        let mut bus = Bus::new(0x1000, 0x50..0xA0);
+        bus.set_write_protect(REGION_FONT, true).unwrap();
+        assert!(bus.set_write_protect("rom", true).is_err());
+
+        assert!(!bus.write(BusContext::CpuData, 0x60, 0x12));
+        assert_eq!(bus.read(BusContext::Raw, 0x60), 0);
+
+        // Only writes by executing instructions are blocked.
+        assert!(bus.write(BusContext::Debugger, 0x60, 0x12));
+        assert!(bus.write(BusContext::Raw, 0x61, 0x34));
+        assert_eq!(bus.read_dword(BusContext::Raw, 0x60), 0x1234);
+        assert!(bus.write(BusContext::CpuData, 0xA0, 0x56));
+    }
+
+    #[test]
+    fn font_range_keeps_protection() {
+        let mut bus = Bus::new(0x1000, 0x50..0xA0);
+        bus.set_write_protect(REGION_INTERPRETER, true).unwrap();
+        bus.set_font_range(0..0x50);
+
+        assert_eq!(region_layout(&bus), vec![
+            (REGION_FONT, 0..0x50, false),
+            (REGION_INTERPRETER, 0x50..0x200, true),
+            (REGION_PROGRAM, 0x200..0x1000, false),
+        ]);
+        assert_eq!(bus.region(0x1040).map(|region| region.name.as_str()), Some(REGION_FONT));
+    }
+
+    #[test]
+    fn devices() {
+        let written = Rc::new(Cell::new(None));
+        let mut bus = Bus::new(0x1000, 0x50..0xA0);
+        bus.map_device("io", 0xF00..0xF10, Box::new(TestDevice { written: written.clone() })).unwrap();
+
+        // Accesses are routed with the offset into the region, wrapping at the end of memory.
+        assert_eq!(bus.read(BusContext::Raw, 0xF04), 0x04);
+        assert_eq!(bus.read(BusContext::Raw, 0x1F0F), 0x0F);
+        assert!(bus.write(BusContext::CpuData, 0xF08, 0x42));
+        assert_eq!(written.get(), Some((0x08, 0x42)));
+        let value: uword = bus.memory().read(BusContext::Raw, 0xF08);
+        assert_eq!(value, 0);
+
+        assert!(bus.map_device("io", 0xE00..0xE10, Box::new(TestDevice { written: written.clone() })).is_err());
+        assert!(bus.map_device(REGION_PROGRAM, 0xE00..0xE10, Box::new(TestDevice { written: written.clone() })).is_err());
+        assert!(bus.map_device("overlap", 0xEF8..0xF01, Box::new(TestDevice { written: written.clone() })).is_err());
+        assert!(bus.map_device("empty", 0xE00..0xE00, Box::new(TestDevice { written: written.clone() })).is_err());
+        assert!(bus.map_device("past_end", 0xFF0..0x1010, Box::new(TestDevice { written: written.clone() })).is_err());
+
+        assert!(bus.unmap_device("io").is_some());
+        assert!(bus.unmap_device(REGION_PROGRAM).is_none());
+        assert_eq!(bus.read(BusContext::Raw, 0xF04), 0);
+    }
+}
@@ -1,5 +1,6 @@
+use std::fmt;
 use common::types::primative::*;
-use resources::cpu::instruction_lookup::lookup;
+use resources::cpu::instruction_lookup::{lookup, MNEMONICS};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Instruction {
@@ -27,11 +28,47 @@ impl Instruction {
         self.index
     }
 
+    /// Returns the instruction mnemonic, or None if the instruction is unknown.
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        self.index.map(|index| MNEMONICS[index])
+    }
+
     pub fn raw(&self) -> RawInstruction {
         self.raw_inst
     }
 }
 
+impl fmt::Display for Instruction {
+    /// Formats the instruction as disassembly, eg: "draw V1, V2, 5".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inst = &self.raw_inst;
+        match self.mnemonic() {
+            Some(m @ "cls") | Some(m @ "ret") | Some(m @ "audio_I") => {
+                write!(f, "{}", m)
+            },
+            Some(m @ "call_rca1802") | Some(m @ "jump") | Some(m @ "call") | Some(m @ "mov_I") | Some(m @ "call_I") => {
+                write!(f, "{} 0x{:03X}", m, inst.address())
+            },
+            Some(m @ "sifeqi") | Some(m @ "sifnei") | Some(m @ "movi") | Some(m @ "addi") | Some(m @ "rand") => {
+                write!(f, "{} V{:X}, 0x{:02X}", m, inst.x_register(), inst.immediate())
+            },
+            Some(m @ "draw") => {
+                write!(f, "{} V{:X}, V{:X}, {}", m, inst.x_register(), inst.y_register(), inst.low_nibble())
+            },
+            Some(m @ "sifeq") | Some(m @ "mov") | Some(m @ "or") | Some(m @ "and") | Some(m @ "xor") | 
+            Some(m @ "add") | Some(m @ "sub") | Some(m @ "rsub") | Some(m @ "sifne") => {
+                write!(f, "{} V{:X}, V{:X}", m, inst.x_register(), inst.y_register())
+            },
+            Some(m) => {
+                write!(f, "{} V{:X}", m, inst.x_register())
+            },
+            None => {
+                write!(f, "unknown 0x{:04X}", inst.value)
+            },
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct RawInstruction {
     /// Raw instruction value.
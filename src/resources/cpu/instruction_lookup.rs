@@ -1,5 +1,19 @@
+use common::constants::cpu::INSTRUCTION_COUNT;
 use resources::cpu::instruction::RawInstruction;
 
+/// Instruction mnemonics, indexed by the unique instruction index returned from lookup().
+pub static MNEMONICS: [&'static str; INSTRUCTION_COUNT] = [
+    "cls", "ret", "call_rca1802", "jump", "call", "sifeqi", "sifnei", "sifeq", "movi", "addi", 
+    "mov", "or", "and", "xor", "add", "sub", "shr1", "rsub", "shl1", "sifne", 
+    "mov_I", "call_I", "rand", "draw", "sifkeq", "sifkne", "timerr", "keyr", "timerw", "soundw", 
+    "add_I", "sprite_I", "bcd", "save", "load", "bigsprite_I", "audio_I", "pitch",
+];
+
+/// Returns the unique instruction index for the given mnemonic (case insensitive).
+pub fn lookup_mnemonic(mnemonic: &str) -> Option<usize> {
+    MNEMONICS.iter().position(|m| m.eq_ignore_ascii_case(mnemonic))
+}
+
 /// CPU instruction lookup, which returns a unique index for use in a function call array.
 /// For documentation, see here: https://en.wikipedia.org/wiki/CHIP-8
 pub fn lookup(inst: RawInstruction) -> Option<usize> {
@@ -73,13 +87,16 @@ pub fn lookup(inst: RawInstruction) -> Option<usize> {
         },
         0xF => {
             match inst.immediate() {
+                0x02 if inst.x_register() == 0 => Some(36), // audio_I
                 0x07 => Some(26), // timerr
                 0x0A => Some(27), // keyr
                 0x15 => Some(28), // timerw
                 0x18 => Some(29), // soundw
                 0x1E => Some(30), // add_I
                 0x29 => Some(31), // sprite_I
+                0x30 => Some(35), // bigsprite_I
                 0x33 => Some(32), // bcd
+                0x3A => Some(37), // pitch
                 0x55 => Some(33), // save
                 0x65 => Some(34), // load
                 _ => None,
@@ -89,4 +106,25 @@ pub fn lookup(inst: RawInstruction) -> Option<usize> {
     };
 
     index
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::primative::*;
+
+    fn mnemonic(value: udword) -> Option<&'static str> {
+        lookup(RawInstruction::new(value)).map(|index| MNEMONICS[index])
+    }
+
+    #[test]
+    fn xo_chip_audio() {
+        assert_eq!(mnemonic(0xF002), Some("audio_I"));
+        assert_eq!(mnemonic(0xF33A), Some("pitch"));
+        assert_eq!(mnemonic(0xFF3A), Some("pitch"));
+
+        // F002 takes no register.
+        assert_eq!(mnemonic(0xF102), None);
+        assert_eq!(mnemonic(0xF03B), None);
+        assert_eq!(lookup_mnemonic("AUDIO_I"), Some(36));
+    }
+}
@@ -28,9 +28,14 @@ pub static KEYS: [&'static BitfieldParam; KEYS_COUNT] = [&KEY_0, &KEY_1, &KEY_2,
 
 pub struct Cpu {
     pub clock_state: ClockState,
+    /// Amount of instructions executed since reset.
+    pub cycles: u64,
     pub pc: DwordRegister,
+    /// Variable registers and display, used by the default memory layout only (see Resources::gpr()
+    /// and Resources::pixel(), which also handle the VIP layout).
     pub gpr: [WordRegister; 16],
     pub i: DwordRegister,
+    /// Call stack, with the entries in memory for the VIP layout read from there (see Resources::stack()).
     pub stack: Vec<uptr>,
     pub keys: DwordRegister,
     pub framebuffer: [bool; HORIZONTAL_RES * VERTICAL_RES],
@@ -42,6 +47,7 @@ impl Cpu {
     pub fn new() -> Cpu {
         Cpu {
             clock_state: ClockState::new(),
+            cycles: 0,
             pc: DwordRegister::from(0x200),
             gpr: [WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), 
                   WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new(), WordRegister::new() ],
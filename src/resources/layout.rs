@@ -0,0 +1,134 @@
+//! Memory layouts.
+//!
+//! By default the stack, variable registers and display buffer are kept by the
+//! Cpu, outside of memory. With the VIP layout they are kept in memory instead,
+//! where the COSMAC VIP interpreter keeps them, so programs reading or writing
+//! these areas directly behave as on the original machine:
+//!
+//!  - 0xEA0-0xECF: stack, growing down from 0xECF (2 bytes per entry, big endian).
+//!  - 0xED0-0xEEF: interpreter work area (unused).
+//!  - 0xEF0-0xEFF: variable registers V0-VF.
+//!  - 0xF00-0xFFF: display buffer (1 bit per pixel, rows of 8 bytes, MSB leftmost).
+//!
+//! The state is read and written through the bus at these addresses (see the
+//! Resources accessors, eg: Resources::gpr()), so instructions and direct
+//! memory accesses always see the same values.
+
+use std::ops::Range;
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+use common::constants::memory::PROGRAM_START;
+use resources::bus::{Bus, REGION_PROGRAM, low_regions};
+
+/// Names of the regions added by the VIP layout.
+pub const REGION_VIP_STACK: &'static str = "vip_stack";
+pub const REGION_VIP_WORK: &'static str = "vip_work";
+pub const REGION_VIP_REGISTERS: &'static str = "vip_registers";
+pub const REGION_VIP_DISPLAY: &'static str = "vip_display";
+
+pub const VIP_STACK_START: usize = 0xEA0;
+pub const VIP_WORK_START: usize = 0xED0;
+pub const VIP_REGISTERS_START: usize = 0xEF0;
+pub const VIP_DISPLAY_START: usize = 0xF00;
+pub const VIP_DISPLAY_SIZE: usize = HORIZONTAL_RES * VERTICAL_RES / 8;
+
+/// Maximum stack depth kept in memory. Deeper entries are only kept by the Cpu, as is the depth
+/// (the VIP keeps it in a 1802 register).
+pub const VIP_STACK_DEPTH: usize = (VIP_WORK_START - VIP_STACK_START) / 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MemoryLayout {
+    /// Stack, registers and display kept outside of memory.
+    Default,
+
+    /// Stack, registers and display kept in memory as on the COSMAC VIP.
+    Vip,
+}
+
+impl MemoryLayout {
+    /// Parses a layout name ('default' or 'vip').
+    pub fn parse(name: &str) -> Result<MemoryLayout, String> {
+        match name {
+            "default" => Ok(MemoryLayout::Default),
+            "vip" => Ok(MemoryLayout::Vip),
+            _ => Err(format!("Unknown memory layout '{}' (expected default or vip)", name)),
+        }
+    }
+
+    /// Returns the memory available to the program.
+    pub fn program_range(&self, memory_size: usize) -> Range<usize> {
+        match *self {
+            MemoryLayout::Default => PROGRAM_START..memory_size,
+            MemoryLayout::Vip => PROGRAM_START..VIP_STACK_START,
+        }
+    }
+
+    /// Returns the memory holding the Cpu state (always initialised), if any.
+    pub fn state_range(&self, memory_size: usize) -> Option<Range<usize>> {
+        match *self {
+            MemoryLayout::Default => None,
+            MemoryLayout::Vip => Some(VIP_STACK_START..memory_size),
+        }
+    }
+
+    /// Creates a bus with the regions of the layout, the font sets being loaded over the given range.
+    pub fn bus(&self, memory_size: usize, font: Range<usize>) -> Bus {
+        match *self {
+            MemoryLayout::Default => Bus::new(memory_size, font),
+            MemoryLayout::Vip => {
+                let mut regions = low_regions(font);
+                regions.extend_from_slice(&[
+                    (REGION_PROGRAM, PROGRAM_START..VIP_STACK_START),
+                    (REGION_VIP_STACK, VIP_STACK_START..VIP_WORK_START),
+                    (REGION_VIP_WORK, VIP_WORK_START..VIP_REGISTERS_START),
+                    (REGION_VIP_REGISTERS, VIP_REGISTERS_START..VIP_DISPLAY_START),
+                    (REGION_VIP_DISPLAY, VIP_DISPLAY_START..memory_size),
+                ]);
+                Bus::with_regions(memory_size, &regions)
+            },
+        }
+    }
+}
+
+/// Address of the stack entry at the given depth (from the bottom).
+pub fn stack_address(depth: usize) -> usize {
+    VIP_STACK_START + (VIP_STACK_DEPTH - 1 - depth) * 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::constants::memory::MEMORY_SIZE;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(MemoryLayout::parse("vip"), Ok(MemoryLayout::Vip));
+        assert!(MemoryLayout::parse("eti660").is_err());
+
+        assert_eq!(MemoryLayout::Default.program_range(MEMORY_SIZE), 0x200..0x1000);
+        assert_eq!(MemoryLayout::Default.state_range(MEMORY_SIZE), None);
+        assert_eq!(MemoryLayout::Vip.program_range(MEMORY_SIZE), 0x200..0xEA0);
+        assert_eq!(MemoryLayout::Vip.state_range(MEMORY_SIZE), Some(0xEA0..0x1000));
+        assert_eq!(VIP_DISPLAY_SIZE, 0x100);
+    }
+
+    #[test]
+    fn stack_addresses() {
+        // The stack grows down from 0xECF.
+        assert_eq!(VIP_STACK_DEPTH, 24);
+        assert_eq!(stack_address(0), 0xECE);
+        assert_eq!(stack_address(1), 0xECC);
+        assert_eq!(stack_address(VIP_STACK_DEPTH - 1), VIP_STACK_START);
+    }
+
+    #[test]
+    fn vip_regions() {
+        let bus = MemoryLayout::Vip.bus(MEMORY_SIZE, 0x50..0xA0);
+        let name = |address| bus.region(address).map(|region| region.name.clone());
+        assert_eq!(name(0x60), Some("font".to_owned()));
+        assert_eq!(name(0xE9F), Some(REGION_PROGRAM.to_owned()));
+        assert_eq!(name(0xEA0), Some(REGION_VIP_STACK.to_owned()));
+        assert_eq!(name(0xEEF), Some(REGION_VIP_WORK.to_owned()));
+        assert_eq!(name(0xEFF), Some(REGION_VIP_REGISTERS.to_owned()));
+        assert_eq!(name(0xFFF), Some(REGION_VIP_DISPLAY.to_owned()));
+    }
+}
@@ -1,28 +1,214 @@
+pub mod bus;
 pub mod cpu;
+pub mod layout;
 pub mod spu;
 pub mod timer;
 
-use common::types::storage::memory::word_memory::WordMemory;
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+use common::types::primative::*;
+use common::types::storage::*;
+use resources::bus::Bus;
 use resources::cpu::Cpu;
+use resources::layout::{MemoryLayout, VIP_DISPLAY_SIZE, VIP_DISPLAY_START, VIP_REGISTERS_START, VIP_STACK_DEPTH, stack_address};
 use resources::spu::Spu;
 use resources::timer::Timer;
 
 pub struct Resources {
-    pub memory: WordMemory,
+    pub bus: Bus,
     pub cpu: Cpu,
     pub spu: Spu,
     pub timer: Timer,
+
+    /// Where the stack, registers and display are kept. Access them with the methods below, which
+    /// go through the bus when the layout keeps them in memory.
+    pub layout: MemoryLayout,
 }
 
 unsafe impl Sync for Resources { }
 
 impl Resources {
-    pub fn new() -> Resources {
+    /// Creates resources attached to an existing bus (keeping its regions and devices).
+    pub fn with_bus(bus: Bus, layout: MemoryLayout) -> Resources {
         Resources {
-            memory: WordMemory::new(0x1000),
+            bus,
             cpu: Cpu::new(),
             spu: Spu::new(),
             timer: Timer::new(),
+            layout,
+        }
+    }
+
+    /// Returns the value of general purpose register V[index].
+    pub fn gpr(&self, index: usize) -> uword {
+        match self.layout {
+            MemoryLayout::Default => self.cpu.gpr[index].read(BusContext::Raw, 0),
+            MemoryLayout::Vip => self.bus.read(BusContext::Raw, VIP_REGISTERS_START + index),
+        }
+    }
+
+    pub fn set_gpr(&mut self, index: usize, value: uword) {
+        match self.layout {
+            MemoryLayout::Default => self.cpu.gpr[index].write(BusContext::Raw, 0, value),
+            MemoryLayout::Vip => { self.bus.write(BusContext::Raw, VIP_REGISTERS_START + index, value); },
         }
     }
-}
\ No newline at end of file
+
+    /// Returns the call stack depth.
+    pub fn stack_depth(&self) -> usize {
+        self.cpu.stack.len()
+    }
+
+    /// Returns the return address at the given depth (from the bottom of the stack).
+    pub fn stack_entry(&self, depth: usize) -> uptr {
+        match self.layout {
+            MemoryLayout::Vip if depth < VIP_STACK_DEPTH => {
+                let address = stack_address(depth);
+                ((self.bus.read(BusContext::Raw, address) as uptr) << 8) | (self.bus.read(BusContext::Raw, address + 1) as uptr)
+            },
+            _ => self.cpu.stack[depth],
+        }
+    }
+
+    /// Returns the call stack (return addresses), from the bottom up.
+    pub fn stack(&self) -> Vec<uptr> {
+        (0..self.stack_depth()).map(|depth| self.stack_entry(depth)).collect()
+    }
+
+    pub fn push_stack(&mut self, address: uptr) {
+        let depth = self.stack_depth();
+        if self.layout == MemoryLayout::Vip && depth < VIP_STACK_DEPTH {
+            let entry_address = stack_address(depth);
+            self.bus.write(BusContext::Raw, entry_address, (address >> 8) as uword);
+            self.bus.write(BusContext::Raw, entry_address + 1, address as uword);
+        }
+        self.cpu.stack.push(address);
+    }
+
+    /// Pops the return address at the top of the stack, or returns None if the stack is empty.
+    pub fn pop_stack(&mut self) -> Option<uptr> {
+        match self.stack_depth() {
+            0 => None,
+            depth => {
+                let address = self.stack_entry(depth - 1);
+                self.cpu.stack.pop();
+                Some(address)
+            },
+        }
+    }
+
+    /// Replaces the call stack (return addresses), from the bottom up.
+    pub fn set_stack(&mut self, stack: &[uptr]) {
+        self.cpu.stack.clear();
+        for &address in stack {
+            self.push_stack(address);
+        }
+    }
+
+    /// Returns the pixel at the given index (rows of HORIZONTAL_RES pixels).
+    pub fn pixel(&self, index: usize) -> bool {
+        match self.layout {
+            MemoryLayout::Default => self.cpu.framebuffer[index],
+            MemoryLayout::Vip => self.bus.read(BusContext::Raw, VIP_DISPLAY_START + index / 8) & (0x80 >> (index % 8)) != 0,
+        }
+    }
+
+    pub fn set_pixel(&mut self, index: usize, value: bool) {
+        match self.layout {
+            MemoryLayout::Default => self.cpu.framebuffer[index] = value,
+            MemoryLayout::Vip => {
+                let address = VIP_DISPLAY_START + index / 8;
+                let mask = 0x80 >> (index % 8);
+                let byte = self.bus.read(BusContext::Raw, address);
+                self.bus.write(BusContext::Raw, address, if value { byte | mask } else { byte & !mask });
+            },
+        }
+    }
+
+    /// Returns a copy of the display.
+    pub fn framebuffer(&self) -> [bool; HORIZONTAL_RES * VERTICAL_RES] {
+        match self.layout {
+            MemoryLayout::Default => self.cpu.framebuffer,
+            MemoryLayout::Vip => {
+                let mut framebuffer = [false; HORIZONTAL_RES * VERTICAL_RES];
+                for (index, pixel) in framebuffer.iter_mut().enumerate() {
+                    *pixel = self.pixel(index);
+                }
+                framebuffer
+            },
+        }
+    }
+
+    pub fn clear_framebuffer(&mut self) {
+        match self.layout {
+            MemoryLayout::Default => self.cpu.framebuffer = [false; HORIZONTAL_RES * VERTICAL_RES],
+            MemoryLayout::Vip => {
+                for index in 0..VIP_DISPLAY_SIZE {
+                    self.bus.write(BusContext::Raw, VIP_DISPLAY_START + index, 0);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::constants::memory::MEMORY_SIZE;
+
+    fn vip_resources() -> Resources {
+        let layout = MemoryLayout::Vip;
+        Resources::with_bus(layout.bus(MEMORY_SIZE, 0x50..0xA0), layout)
+    }
+
+    #[test]
+    fn vip_registers() {
+        let mut res = vip_resources();
+        res.set_gpr(0x3, 0x42);
+        assert_eq!(res.bus.read(BusContext::Raw, 0xEF3), 0x42);
+
+        res.bus.write(BusContext::CpuData, 0xEFF, 0x12);
+        assert_eq!(res.gpr(0xF), 0x12);
+        assert_eq!(res.cpu.gpr[0xF].read(BusContext::Raw, 0), 0);
+    }
+
+    #[test]
+    fn vip_stack() {
+        let mut res = vip_resources();
+        res.push_stack(0x2A4);
+        assert_eq!(res.bus.read_dword(BusContext::Raw, 0xECE), 0x2A4);
+
+        // Entries are read back from memory.
+        res.bus.write(BusContext::CpuData, 0xECF, 0xA6);
+        assert_eq!(res.stack(), vec![0x2A6]);
+
+        // Entries past the depth kept in memory are only kept by the Cpu.
+        let stack: Vec<uptr> = (0..VIP_STACK_DEPTH as uptr + 2).map(|depth| 0x200 + depth * 2).collect();
+        res.set_stack(&stack);
+        assert_eq!(res.stack(), stack);
+        assert_eq!(res.bus.read_dword(BusContext::Raw, 0xEA0), 0x200 + (VIP_STACK_DEPTH as udword - 1) * 2);
+
+        assert_eq!(res.pop_stack(), stack.last().cloned());
+        res.set_stack(&[]);
+        assert_eq!(res.pop_stack(), None);
+    }
+
+    #[test]
+    fn vip_display() {
+        let mut res = vip_resources();
+
+        // Rows of 8 bytes, the most significant bit leftmost.
+        res.set_pixel(0, true);
+        res.set_pixel(HORIZONTAL_RES + 9, true);
+        assert_eq!(res.bus.read(BusContext::Raw, 0xF00), 0x80);
+        assert_eq!(res.bus.read(BusContext::Raw, 0xF09), 0x40);
+
+        res.bus.write(BusContext::CpuData, 0xFFF, 0x01);
+        assert!(res.pixel(HORIZONTAL_RES * VERTICAL_RES - 1));
+        assert_eq!(res.framebuffer().iter().filter(|&&pixel| pixel).count(), 3);
+
+        res.set_pixel(0, false);
+        assert_eq!(res.bus.read(BusContext::Raw, 0xF00), 0);
+        res.clear_framebuffer();
+        assert!(res.framebuffer().iter().all(|&pixel| !pixel));
+    }
+}
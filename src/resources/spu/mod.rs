@@ -1,19 +1,32 @@
 pub mod register;
 
+use common::constants::spu::DEFAULT_PITCH;
 use common::types::clock_state::ClockState;
-use resources::spu::register::CountRegister;
+use common::types::storage::*;
+use common::types::storage::register::word_register::WordSyncRegister;
+use resources::spu::register::{CountRegister, PatternRegister};
 
 #[derive(Debug)]
 pub struct Spu {
     pub clock_state: ClockState,
     pub counter: CountRegister,
+
+    /// XO-CHIP audio pattern (F002), played in place of the configured waveform once loaded.
+    pub pattern: PatternRegister,
+
+    /// XO-CHIP pitch register (FX3A), setting the pattern playback rate (see audio::pattern_rate()).
+    pub pitch: WordSyncRegister,
 }
 
 impl Spu {
     pub fn new() -> Spu {
+        let pitch = WordSyncRegister::new();
+        pitch.write(BusContext::Raw, 0, DEFAULT_PITCH);
         Spu { 
             clock_state: ClockState::new(),
             counter: CountRegister::new(),
+            pattern: PatternRegister::new(),
+            pitch,
         }
     }
 }
\ No newline at end of file
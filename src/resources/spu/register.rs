@@ -5,6 +5,7 @@ use parking_lot::ReentrantMutexGuard;
 use common::types::primative::*;
 use common::types::storage::*;
 use common::types::storage::register::*;
+use common::constants::spu::AUDIO_PATTERN_SIZE;
 
 /// Spu count register.
 /// Holds a flag to indicate if the register changed from a zero to non-zero
@@ -63,4 +64,39 @@ impl SyncRegister for CountRegister {
     fn scope_guard(&self) -> ReentrantMutexGuard<()> {
         unsafe { (*self.scope_mutex.get()).lock() }
     }
-}
\ No newline at end of file
+}
+
+/// XO-CHIP audio pattern register (see F002).
+/// Written by the Cpu and read by the Spu controller, possibly on different threads, so every
+/// access copies the pattern under the scope mutex.
+#[derive(Debug)]
+pub struct PatternRegister {
+    value: UnsafeCell<Option<[uword; AUDIO_PATTERN_SIZE]>>,
+    scope_mutex: ReentrantMutex<()>,
+}
+
+impl PatternRegister {
+    pub fn new() -> PatternRegister {
+        PatternRegister {
+            value: UnsafeCell::new(None),
+            scope_mutex: ReentrantMutex::new(()),
+        }
+    }
+
+    /// Returns the pattern, or None if none was loaded.
+    pub fn pattern(&self) -> Option<[uword; AUDIO_PATTERN_SIZE]> {
+        let _guard = self.scope_guard();
+        unsafe { *self.value.get() }
+    }
+
+    pub fn load(&self, pattern: [uword; AUDIO_PATTERN_SIZE]) {
+        let _guard = self.scope_guard();
+        unsafe { *self.value.get() = Some(pattern); }
+    }
+}
+
+impl SyncRegister for PatternRegister {
+    fn scope_guard(&self) -> ReentrantMutexGuard<()> {
+        self.scope_mutex.lock()
+    }
+}
@@ -0,0 +1,1067 @@
+//! Octo assembler.
+//!
+//! Compiles Octo source (as stored in Octo cartridges) into a binary loaded at
+//! the program start address, following the Octo compiler: the program starts
+//! with a jump to the 'main' label, dropped when main comes first.
+//!
+//! Supported:
+//! - the CHIP-8, SUPER-CHIP and XO-CHIP statements, numbers (emitted as bytes)
+//!   and labels (bare label names are subroutine calls, forward references
+//!   are allowed wherever an address is expected)
+//! - if ... then, if ... begin ... else ... end and loop ... while ... again,
+//!   with comparisons (<, >, <=, >=) using the compare-temp register (vf)
+//! - :next, :org, :const, :alias, :unpack, :byte, :pointer, :call, :macro,
+//!   :calc and :assert (:breakpoint and :monitor are ignored)
+//!
+//! :stringmode is not supported.
+
+use std::collections::HashMap;
+use std::f64::consts;
+use common::constants::memory::PROGRAM_START;
+use common::types::primative::*;
+
+/// Size of the XO-CHIP address space, the largest a program can be assembled into.
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// Most macro expansions in a program, stopping recursive macros.
+const MAX_EXPANSIONS: usize = 100000;
+
+const BINARY_OPERATORS: [&'static str; 19] = [
+    "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=", ">=", ">",
+];
+
+const UNARY_OPERATORS: [&'static str; 13] = [
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor",
+];
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How a reference to a label is filled in once the label is defined.
+#[derive(Copy, Clone)]
+enum ReferenceKind {
+    /// The 12 bit address of an instruction.
+    Address,
+    /// A 16 bit address (i := long, :pointer).
+    Long,
+    /// The low nibble of a byte, set to the top 4 bits of a 12 bit address (:unpack).
+    HighNibble,
+    /// A byte, set to the top or bottom 8 bits of the address.
+    HighByte,
+    LowByte,
+}
+
+struct Reference {
+    name: String,
+    kind: ReferenceKind,
+    at: usize,
+    line: usize,
+}
+
+struct Loop {
+    start: usize,
+    line: usize,
+
+    /// Jumps out of the loop (one per while), filled in by again.
+    breaks: Vec<usize>,
+}
+
+struct Assembler {
+    /// Tokens left, last first.
+    tokens: Vec<Token>,
+
+    /// Line of the last token taken.
+    line: usize,
+
+    image: Vec<uword>,
+    written: Vec<bool>,
+    here: usize,
+
+    /// One past the highest address written.
+    end: usize,
+
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    references: Vec<Reference>,
+    expansions: usize,
+
+    /// Jumps to the else or end of each open if ... begin, with their lines.
+    branches: Vec<(usize, usize)>,
+    loops: Vec<Loop>,
+
+    /// Whether the jump to main reserved at the program start is still in place.
+    main_jump: bool,
+}
+
+/// Splits the source into tokens, dropping comments. Strings ("...") are kept whole.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut chars = line.chars().peekable();
+        loop {
+            while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                chars.next();
+            }
+
+            let mut text = String::new();
+            match chars.peek().cloned() {
+                None | Some('#') => break,
+                Some('"') => {
+                    text.push(chars.next().unwrap());
+                    while let Some(c) = chars.next() {
+                        text.push(c);
+                        if c == '"' {
+                            break;
+                        }
+                    }
+                },
+                Some(_) => {
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        text.push(c);
+                        chars.next();
+                    }
+                },
+            }
+            tokens.push(Token { text, line: line_index + 1 });
+        }
+    }
+
+    tokens
+}
+
+/// Parses an Octo number: decimal, 0x hex or 0b binary, optionally negative.
+fn parse_number(token: &str) -> Option<i64> {
+    let (negative, digits) = if token.starts_with('-') { (true, &token[1..]) } else { (false, token) };
+    let value = if digits.starts_with("0x") || digits.starts_with("0X") {
+        i64::from_str_radix(&digits[2..], 16).ok()
+    } else if digits.starts_with("0b") || digits.starts_with("0B") {
+        i64::from_str_radix(&digits[2..], 2).ok()
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_digit(10)) {
+        digits.parse::<i64>().ok()
+    } else {
+        None
+    };
+    value.map(|value| if negative { -value } else { value })
+}
+
+/// Returns the register named by the token (v0 to vf), ignoring aliases.
+fn parse_register(token: &str) -> Option<usize> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as usize),
+        _ => None,
+    }
+}
+
+fn is_identifier(token: &str) -> bool {
+    match token.chars().next() {
+        Some(c) => (c.is_alphabetic() || c == '_') && token.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-'),
+        None => false,
+    }
+}
+
+fn binary_operation(operator: &str, left: f64, right: f64) -> f64 {
+    let (left_int, right_int) = (left as i64, right as i64);
+    let boolean = |value: bool| if value { 1.0 } else { 0.0 };
+    match operator {
+        "-" => left - right,
+        "+" => left + right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "&" => (left_int & right_int) as f64,
+        "|" => (left_int | right_int) as f64,
+        "^" => (left_int ^ right_int) as f64,
+        "<<" => (left_int << (right_int & 63)) as f64,
+        ">>" => (left_int >> (right_int & 63)) as f64,
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "<" => boolean(left < right),
+        "<=" => boolean(left <= right),
+        "==" => boolean(left == right),
+        "!=" => boolean(left != right),
+        ">=" => boolean(left >= right),
+        ">" => boolean(left > right),
+        _ => unreachable!(),
+    }
+}
+
+fn unary_operation(operator: &str, value: f64) -> f64 {
+    match operator {
+        "-" => -value,
+        "~" => !(value as i64) as f64,
+        "!" => if value == 0.0 { 1.0 } else { 0.0 },
+        "sin" => value.sin(),
+        "cos" => value.cos(),
+        "tan" => value.tan(),
+        "exp" => value.exp(),
+        "log" => value.ln(),
+        "abs" => value.abs(),
+        "sqrt" => value.sqrt(),
+        "sign" => if value > 0.0 { 1.0 } else if value < 0.0 { -1.0 } else { 0.0 },
+        "ceil" => value.ceil(),
+        "floor" => value.floor(),
+        _ => unreachable!(),
+    }
+}
+
+impl Assembler {
+    fn new(source: &str) -> Assembler {
+        let mut tokens = tokenize(source);
+        tokens.reverse();
+
+        let mut aliases = HashMap::new();
+        aliases.insert("compare-temp".to_owned(), 0xF);
+        aliases.insert("unpack-hi".to_owned(), 0x0);
+        aliases.insert("unpack-lo".to_owned(), 0x1);
+
+        Assembler {
+            tokens,
+            line: 0,
+            image: vec![0; ADDRESS_SPACE],
+            written: vec![false; ADDRESS_SPACE],
+            here: PROGRAM_START,
+            end: PROGRAM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases,
+            macros: HashMap::new(),
+            references: Vec::new(),
+            expansions: 0,
+            branches: Vec::new(),
+            loops: Vec::new(),
+            main_jump: false,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, String> {
+        Err(format!("Line {}: {}", self.line, message))
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.pop() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            },
+            None => self.error("Unexpected end of program".to_owned()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("Expected '{}', got '{}'", expected, token));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, byte: uword) -> Result<(), String> {
+        if self.here < PROGRAM_START || self.here >= ADDRESS_SPACE {
+            return self.error(format!("Address 0x{:X} is outside the program", self.here));
+        }
+        if self.written[self.here] {
+            return self.error(format!("Data overlap: address 0x{:X} has already been defined", self.here));
+        }
+
+        self.image[self.here] = byte;
+        self.written[self.here] = true;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn instruction(&mut self, high: uword, low: uword) -> Result<(), String> {
+        self.emit(high)?;
+        self.emit(low)
+    }
+
+    /// Sets the 12 bit address of the instruction at the address given.
+    fn patch_address(&mut self, at: usize, address: usize) {
+        self.image[at] = (self.image[at] & 0xF0) | ((address >> 8) & 0xF) as uword;
+        self.image[at + 1] = address as uword;
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), String> {
+        if !is_identifier(&name) {
+            return self.error(format!("'{}' is not a valid name", name));
+        }
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return self.error(format!("Name '{}' is already defined", name));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    /// Returns the register named by the token (v0 to vf, or an alias).
+    fn register_index(&self, token: &str) -> Option<usize> {
+        parse_register(token).or_else(|| self.aliases.get(token).cloned())
+    }
+
+    fn is_register_next(&self) -> bool {
+        self.peek().and_then(|token| self.register_index(token)).is_some()
+    }
+
+    fn register(&mut self) -> Result<uword, String> {
+        let token = self.next()?;
+        match self.register_index(&token) {
+            Some(register) => Ok(register as uword),
+            None => self.error(format!("Expected a register, got '{}'", token)),
+        }
+    }
+
+    /// Returns the value of the token: a number, constant, label defined so far, or a calc expression
+    /// in braces (read from the following tokens). Returns None for an unknown name.
+    fn value_of(&mut self, token: &str) -> Result<Option<i64>, String> {
+        if token == "{" {
+            return self.calc("}").map(|value| Some(value.floor() as i64));
+        }
+        if let Some(value) = parse_number(token) {
+            return Ok(Some(value));
+        }
+        if let Some(&value) = self.constants.get(token) {
+            return Ok(Some(value.floor() as i64));
+        }
+        Ok(self.labels.get(token).map(|&address| address as i64))
+    }
+
+    /// Reads a value in the range given.
+    fn value(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        let token = self.next()?;
+        match self.value_of(&token)? {
+            Some(value) if value >= min && value <= max => Ok(value),
+            Some(value) => self.error(format!("Value {} ({}) is out of range ({} to {})", token, value, min, max)),
+            None => self.error(format!("Undefined name '{}'", token)),
+        }
+    }
+
+    fn byte_value(&mut self) -> Result<uword, String> {
+        self.value(-128, 255).map(|value| value as uword)
+    }
+
+    fn nibble_value(&mut self) -> Result<uword, String> {
+        self.value(0, 15).map(|value| value as uword)
+    }
+
+    /// Reads an address, recording a reference to fill in later if it names a label not defined yet.
+    fn address(&mut self, kind: ReferenceKind, at: usize) -> Result<usize, String> {
+        let token = self.next()?;
+        self.address_of(token, kind, at)
+    }
+
+    fn address_of(&mut self, token: String, kind: ReferenceKind, at: usize) -> Result<usize, String> {
+        let max = match kind {
+            ReferenceKind::Address | ReferenceKind::HighNibble => 0xFFF,
+            _ => 0xFFFF,
+        };
+
+        match self.value_of(&token)? {
+            Some(value) if value >= 0 && value <= max => Ok(value as usize),
+            Some(value) => self.error(format!("Address {} (0x{:X}) is out of range", token, value)),
+            None if is_identifier(&token) => {
+                self.references.push(Reference { name: token, kind, at, line: self.line });
+                Ok(0)
+            },
+            None => self.error(format!("Expected an address, got '{}'", token)),
+        }
+    }
+
+    /// Records a reference to the same label as the last one read, if it was left unresolved at the
+    /// address given (eg: both halves of an :unpack).
+    fn duplicate_reference(&mut self, at: usize, kind: ReferenceKind, other_at: usize) {
+        let duplicate = match self.references.last() {
+            Some(reference) if reference.at == at => Some(Reference { name: reference.name.clone(), kind, at: other_at, line: reference.line }),
+            _ => None,
+        };
+        if let Some(duplicate) = duplicate {
+            self.references.push(duplicate);
+        }
+    }
+
+    /// Emits an instruction with a 12 bit address (eg: 1NNN), read from the next token.
+    fn address_instruction(&mut self, opcode: uword) -> Result<(), String> {
+        let at = self.here;
+        let address = self.address(ReferenceKind::Address, at)?;
+        self.instruction((opcode << 4) | (address >> 8) as uword, address as uword)
+    }
+
+    fn call(&mut self, name: String) -> Result<(), String> {
+        let at = self.here;
+        let address = self.address_of(name, ReferenceKind::Address, at)?;
+        self.instruction(0x20 | (address >> 8) as uword, address as uword)
+    }
+
+    /// Evaluates a calc expression up to the terminator given.
+    fn calc(&mut self, terminator: &str) -> Result<f64, String> {
+        let value = self.calc_expression()?;
+        self.expect(terminator)?;
+        Ok(value)
+    }
+
+    /// Evaluates binary operators right to left, with no precedence (as Octo does).
+    fn calc_expression(&mut self) -> Result<f64, String> {
+        let left = self.calc_terminal()?;
+        let is_operator = self.peek().map_or(false, |token| BINARY_OPERATORS.contains(&token));
+        if !is_operator {
+            return Ok(left);
+        }
+
+        let operator = self.next()?;
+        let right = self.calc_expression()?;
+        Ok(binary_operation(&operator, left, right))
+    }
+
+    fn calc_terminal(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(consts::PI),
+            "E" => Ok(consts::E),
+            "@" => {
+                let address = self.calc_terminal()? as i64;
+                if address < 0 || address as usize >= ADDRESS_SPACE {
+                    return self.error(format!("Address 0x{:X} is out of range", address));
+                }
+                Ok(self.image[address as usize] as f64)
+            },
+            operator if UNARY_OPERATORS.contains(&operator) => {
+                let value = self.calc_terminal()?;
+                Ok(unary_operation(operator, value))
+            },
+            _ => {
+                if let Some(&value) = self.constants.get(&token) {
+                    return Ok(value);
+                }
+                match self.value_of(&token)? {
+                    Some(value) => Ok(value as f64),
+                    None => self.error(format!("Undefined name '{}' in calc expression", token)),
+                }
+            },
+        }
+    }
+
+    /// Emits the instructions skipping the next one unless the condition holds (negated: if it holds).
+    fn conditional(&mut self, negated: bool) -> Result<(), String> {
+        let register = self.register()?;
+        let mut operator = self.next()?;
+        if negated {
+            let inverse = match operator.as_str() {
+                "==" => "!=",
+                "!=" => "==",
+                "key" => "-key",
+                "-key" => "key",
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                _ => return self.error(format!("Unknown comparison '{}'", operator)),
+            };
+            operator = inverse.to_owned();
+        }
+
+        match operator.as_str() {
+            "==" if self.is_register_next() => {
+                let other = self.register()?;
+                self.instruction(0x90 | register, other << 4)
+            },
+            "==" => {
+                let value = self.byte_value()?;
+                self.instruction(0x40 | register, value)
+            },
+            "!=" if self.is_register_next() => {
+                let other = self.register()?;
+                self.instruction(0x50 | register, other << 4)
+            },
+            "!=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x30 | register, value)
+            },
+            "key" => self.instruction(0xE0 | register, 0xA1),
+            "-key" => self.instruction(0xE0 | register, 0x9E),
+            "<" | ">" | "<=" | ">=" => {
+                // compare-temp := operand, then subtract to set vf (no borrow) and skip on it.
+                let temp = self.aliases["compare-temp"] as uword;
+                if self.is_register_next() {
+                    let other = self.register()?;
+                    self.instruction(0x80 | temp, other << 4)?;
+                } else {
+                    let value = self.byte_value()?;
+                    self.instruction(0x60 | temp, value)?;
+                }
+
+                let (subtract, skip) = match operator.as_str() {
+                    ">" => (0x5, 0x4F),
+                    "<" => (0x7, 0x4F),
+                    ">=" => (0x7, 0x3F),
+                    _ => (0x5, 0x3F),
+                };
+                self.instruction(0x80 | temp, (register << 4) | subtract)?;
+                self.instruction(skip, 0x00)
+            },
+            _ => self.error(format!("Unknown comparison '{}'", operator)),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), String> {
+        let keyword = self.tokens.iter().rev().map(|token| token.text.as_str()).find(|&token| token == "then" || token == "begin");
+        let begin = match keyword {
+            Some("begin") => true,
+            Some(_) => false,
+            None => return self.error("Expected 'then' or 'begin' after 'if'".to_owned()),
+        };
+
+        self.conditional(begin)?;
+        let keyword = self.next()?;
+        if keyword != "then" && keyword != "begin" {
+            return self.error(format!("Expected 'then' or 'begin', got '{}'", keyword));
+        }
+
+        if begin {
+            self.branches.push((self.here, self.line));
+            self.instruction(0x10, 0x00)?;
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, register: uword) -> Result<(), String> {
+        let operator = self.next()?;
+
+        if operator == ":=" {
+            match self.peek() {
+                Some("key") => {
+                    self.next()?;
+                    return self.instruction(0xF0 | register, 0x0A);
+                },
+                Some("delay") => {
+                    self.next()?;
+                    return self.instruction(0xF0 | register, 0x07);
+                },
+                Some("random") => {
+                    self.next()?;
+                    let mask = self.byte_value()?;
+                    return self.instruction(0xC0 | register, mask);
+                },
+                _ => {},
+            }
+        }
+
+        if self.is_register_next() {
+            let other = self.register()?;
+            let operation = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("Unknown operator '{}'", operator)),
+            };
+            return self.instruction(0x80 | register, (other << 4) | operation);
+        }
+
+        match operator.as_str() {
+            ":=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x60 | register, value)
+            },
+            "+=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x70 | register, value)
+            },
+            "-=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x70 | register, value.wrapping_neg())
+            },
+            _ => self.error(format!("Operator '{}' needs a register operand", operator)),
+        }
+    }
+
+    fn i_statement(&mut self) -> Result<(), String> {
+        let operator = self.next()?;
+        match operator.as_str() {
+            "+=" => {
+                let register = self.register()?;
+                self.instruction(0xF0 | register, 0x1E)
+            },
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let register = self.register()?;
+                    self.instruction(0xF0 | register, 0x29)
+                },
+                Some("bighex") => {
+                    self.next()?;
+                    let register = self.register()?;
+                    self.instruction(0xF0 | register, 0x30)
+                },
+                Some("long") => {
+                    self.next()?;
+                    let at = self.here + 2;
+                    let address = self.address(ReferenceKind::Long, at)?;
+                    self.instruction(0xF0, 0x00)?;
+                    self.instruction((address >> 8) as uword, address as uword)
+                },
+                _ => self.address_instruction(0xA),
+            },
+            _ => self.error(format!("Unknown operator '{}' for i", operator)),
+        }
+    }
+
+    /// Emits a save or load (FX55, FX65), or the XO-CHIP range form (5XY2, 5XY3).
+    fn save_load(&mut self, single: uword, range: uword) -> Result<(), String> {
+        let register = self.register()?;
+        if self.peek() == Some("-") {
+            self.next()?;
+            let last = self.register()?;
+            return self.instruction(0x50 | register, (last << 4) | range);
+        }
+        self.instruction(0xF0 | register, single)
+    }
+
+    fn directive(&mut self, directive: &str) -> Result<(), String> {
+        match directive {
+            ":" => {
+                let name = self.next()?;
+                if name == "main" && self.main_jump && self.here == PROGRAM_START + 2 {
+                    // Nothing comes before main, so the jump to it isn't needed.
+                    self.main_jump = false;
+                    self.here = PROGRAM_START;
+                    self.end = PROGRAM_START;
+                    self.written[PROGRAM_START] = false;
+                    self.written[PROGRAM_START + 1] = false;
+                }
+                let here = self.here;
+                self.define_label(name, here)
+            },
+            ":next" => {
+                let name = self.next()?;
+                let here = self.here;
+                self.define_label(name, here + 1)
+            },
+            ":org" => {
+                self.here = self.value(0, ADDRESS_SPACE as i64 - 1)? as usize;
+                Ok(())
+            },
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value(i64::min_value(), i64::max_value())?;
+                self.define_constant(name, value as f64)
+            },
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc("}")?;
+                self.define_constant(name, value)
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                if parse_register(&name).is_some() {
+                    return self.error(format!("Cannot alias register '{}'", name));
+                }
+                self.aliases.insert(name, register as usize);
+                Ok(())
+            },
+            ":unpack" => {
+                let at = self.here;
+                let hi = self.aliases["unpack-hi"] as uword;
+                let lo = self.aliases["unpack-lo"] as uword;
+                if self.peek() == Some("long") {
+                    self.next()?;
+                    let address = self.address(ReferenceKind::HighByte, at + 1)?;
+                    self.duplicate_reference(at + 1, ReferenceKind::LowByte, at + 3);
+                    self.instruction(0x60 | hi, (address >> 8) as uword)?;
+                    return self.instruction(0x60 | lo, address as uword);
+                }
+
+                let nibble = self.nibble_value()?;
+                let address = self.address(ReferenceKind::HighNibble, at + 1)?;
+                self.duplicate_reference(at + 1, ReferenceKind::LowByte, at + 3);
+                self.instruction(0x60 | hi, (nibble << 4) | (address >> 8) as uword)?;
+                self.instruction(0x60 | lo, address as uword)
+            },
+            ":byte" => {
+                let value = self.byte_value()?;
+                self.emit(value)
+            },
+            ":pointer" => {
+                let at = self.here;
+                let address = self.address(ReferenceKind::Long, at)?;
+                self.instruction((address >> 8) as uword, address as uword)
+            },
+            ":call" => self.address_instruction(0x2),
+            ":macro" => self.define_macro(),
+            ":assert" => {
+                let message = match self.peek() {
+                    Some(token) if token.starts_with('"') => Some(token.trim_matches('"').to_owned()),
+                    _ => None,
+                };
+                if message.is_some() {
+                    self.next()?;
+                }
+                self.expect("{")?;
+                if self.calc("}")? == 0.0 {
+                    return self.error(format!("Assertion failed{}", message.map(|message| format!(": {}", message)).unwrap_or_default()));
+                }
+                Ok(())
+            },
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            },
+            ":stringmode" => self.error(":stringmode is not supported".to_owned()),
+            _ => self.error(format!("Unknown directive '{}'", directive)),
+        }
+    }
+
+    fn define_constant(&mut self, name: String, value: f64) -> Result<(), String> {
+        if !is_identifier(&name) {
+            return self.error(format!("'{}' is not a valid name", name));
+        }
+        if self.labels.contains_key(&name) {
+            return self.error(format!("Name '{}' is already defined", name));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    /// Reads a macro definition: the name, argument names, then the body in braces.
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next()?;
+        let mut args = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            args.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = match self.tokens.pop() {
+                Some(token) => token,
+                None => return self.error(format!("Macro '{}' has no closing '}}'", name)),
+            };
+            depth += match token.text.as_str() {
+                "{" => 1,
+                "}" => -1,
+                _ => 0,
+            };
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { args, body });
+        Ok(())
+    }
+
+    /// Replaces the macro invocation with the macro body, arguments substituted.
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return self.error(format!("Too many macro expansions (is '{}' recursive?)", name));
+        }
+
+        let arg_count = self.macros[name].args.len();
+        let mut values = HashMap::new();
+        for index in 0..arg_count {
+            let value = self.next()?;
+            values.insert(self.macros[name].args[index].clone(), value);
+        }
+
+        let line = self.line;
+        let expansion: Vec<Token> = self.macros[name].body.iter().rev().map(|token| Token {
+            text: values.get(&token.text).cloned().unwrap_or_else(|| token.text.clone()),
+            line,
+        }).collect();
+        self.tokens.extend(expansion);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if token.starts_with(':') {
+            return self.directive(&token);
+        }
+        if let Some(register) = self.register_index(&token) {
+            return self.register_statement(register as uword);
+        }
+
+        match token.as_str() {
+            "clear" => self.instruction(0x00, 0xE0),
+            "return" | ";" => self.instruction(0x00, 0xEE),
+            "hires" => self.instruction(0x00, 0xFF),
+            "lores" => self.instruction(0x00, 0xFE),
+            "exit" => self.instruction(0x00, 0xFD),
+            "scroll-down" => {
+                let rows = self.nibble_value()?;
+                self.instruction(0x00, 0xC0 | rows)
+            },
+            "scroll-up" => {
+                let rows = self.nibble_value()?;
+                self.instruction(0x00, 0xD0 | rows)
+            },
+            "scroll-right" => self.instruction(0x00, 0xFB),
+            "scroll-left" => self.instruction(0x00, 0xFC),
+            "audio" => self.instruction(0xF0, 0x02),
+            "plane" => {
+                let planes = self.value(0, 3)? as uword;
+                self.instruction(0xF0 | planes, 0x01)
+            },
+            "bcd" | "saveflags" | "loadflags" => {
+                let register = self.register()?;
+                let operation = match token.as_str() {
+                    "bcd" => 0x33,
+                    "saveflags" => 0x75,
+                    _ => 0x85,
+                };
+                self.instruction(0xF0 | register, operation)
+            },
+            "save" => self.save_load(0x55, 0x2),
+            "load" => self.save_load(0x65, 0x3),
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.nibble_value()?;
+                self.instruction(0xD0 | x, (y << 4) | height)
+            },
+            "jump" => self.address_instruction(0x1),
+            "jump0" => self.address_instruction(0xB),
+            "native" => self.address_instruction(0x0),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let register = self.register()?;
+                let operation = match token.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.instruction(0xF0 | register, operation)
+            },
+            "i" => self.i_statement(),
+            "if" => self.if_statement(),
+            "else" => {
+                let (jump, line) = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.error("'else' without 'begin'".to_owned()),
+                };
+                self.branches.push((self.here, line));
+                self.instruction(0x10, 0x00)?;
+                let here = self.here;
+                self.patch_address(jump, here);
+                Ok(())
+            },
+            "end" => {
+                let (jump, _) = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.error("'end' without 'begin'".to_owned()),
+                };
+                let here = self.here;
+                self.patch_address(jump, here);
+                Ok(())
+            },
+            "loop" => {
+                self.loops.push(Loop { start: self.here, line: self.line, breaks: Vec::new() });
+                Ok(())
+            },
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.error("'while' without 'loop'".to_owned());
+                }
+                self.conditional(true)?;
+                let here = self.here;
+                self.loops.last_mut().unwrap().breaks.push(here);
+                self.instruction(0x10, 0x00)
+            },
+            "again" => {
+                let program_loop = match self.loops.pop() {
+                    Some(program_loop) => program_loop,
+                    None => return self.error("'again' without 'loop'".to_owned()),
+                };
+                self.instruction(0x10 | (program_loop.start >> 8) as uword, program_loop.start as uword)?;
+                let here = self.here;
+                for jump in program_loop.breaks {
+                    self.patch_address(jump, here);
+                }
+                Ok(())
+            },
+            _ if self.labels.contains_key(&token) => self.call(token),
+            _ if self.macros.contains_key(&token) => self.expand_macro(&token),
+            _ => match self.value_of(&token)? {
+                Some(value) if value >= -128 && value <= 255 => self.emit(value as uword),
+                Some(value) => self.error(format!("Value {} ({}) is out of range for a byte", token, value)),
+                None if is_identifier(&token) => self.call(token),
+                None => self.error(format!("Unexpected '{}'", token)),
+            },
+        }
+    }
+
+    /// Fills in the references to labels defined after their use.
+    fn resolve_references(&mut self) -> Result<(), String> {
+        for reference in self.references.drain(..).collect::<Vec<_>>() {
+            self.line = reference.line;
+            let address = match self.labels.get(&reference.name) {
+                Some(&address) => address,
+                None => return self.error(format!("Undefined name '{}'", reference.name)),
+            };
+
+            let at = reference.at;
+            match reference.kind {
+                ReferenceKind::Address if address > 0xFFF => {
+                    return self.error(format!("Label '{}' (0x{:X}) is out of range for a 12 bit address", reference.name, address));
+                },
+                ReferenceKind::HighNibble if address > 0xFFF => {
+                    return self.error(format!("Label '{}' (0x{:X}) is out of range for :unpack", reference.name, address));
+                },
+                ReferenceKind::Address => self.patch_address(at, address),
+                ReferenceKind::Long => {
+                    self.image[at] = (address >> 8) as uword;
+                    self.image[at + 1] = address as uword;
+                },
+                ReferenceKind::HighNibble => self.image[at] = (self.image[at] & 0xF0) | (address >> 8) as uword,
+                ReferenceKind::HighByte => self.image[at] = (address >> 8) as uword,
+                ReferenceKind::LowByte => self.image[at] = address as uword,
+            }
+        }
+        Ok(())
+    }
+
+    fn assemble(mut self) -> Result<Vec<uword>, String> {
+        // Reserve the jump to main, dropped if main comes first.
+        self.instruction(0x00, 0x00)?;
+        self.main_jump = true;
+
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if let Some(&(_, line)) = self.branches.last() {
+            self.line = line;
+            return self.error("'begin' without 'end'".to_owned());
+        }
+        if let Some(line) = self.loops.last().map(|program_loop| program_loop.line) {
+            self.line = line;
+            return self.error("'loop' without 'again'".to_owned());
+        }
+
+        let main = match self.labels.get("main") {
+            Some(&main) => main,
+            None => return Err("This program is missing a 'main' label".to_owned()),
+        };
+        if self.main_jump {
+            self.image[PROGRAM_START] = 0x10;
+            self.patch_address(PROGRAM_START, main);
+        }
+        self.resolve_references()?;
+
+        Ok(self.image[PROGRAM_START..self.end].to_vec())
+    }
+}
+
+/// Assembles the Octo source into an image starting at the program start address.
+pub fn assemble(source: &str) -> Result<Vec<uword>, String> {
+    Assembler::new(source).assemble()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_first_drops_the_jump() {
+        assert_eq!(assemble(": main 0x6E 0x05 clear").unwrap(), vec![0x6E, 0x05, 0x00, 0xE0]);
+        assert_eq!(assemble(": sub return : main sub").unwrap(), vec![0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]);
+        assert!(assemble("clear").is_err());
+    }
+
+    #[test]
+    fn statements() {
+        let source = ": main
+            v1 := 0x20 v1 += 2 v1 -= 1 v2 := v1 v2 <<= v1 v3 := random 0x0F v4 := key v4 := delay
+            i := data i += v1 i := hex v2 delay := v1 buzzer := v1 sprite v1 v2 5
+            save v3 load v3 save v1 - v2 bcd v0 plane 2 audio pitch := v0 i := long data
+            : data 0xAB";
+        assert_eq!(assemble(source).unwrap(), vec![
+            0x61, 0x20, 0x71, 0x02, 0x71, 0xFF, 0x82, 0x10, 0x82, 0x1E, 0xC3, 0x0F, 0xF4, 0x0A, 0xF4, 0x07,
+            0xA2, 0x2E, 0xF1, 0x1E, 0xF2, 0x29, 0xF1, 0x15, 0xF1, 0x18, 0xD1, 0x25,
+            0xF3, 0x55, 0xF3, 0x65, 0x51, 0x22, 0xF0, 0x33, 0xF2, 0x01, 0xF0, 0x02, 0xF0, 0x3A, 0xF0, 0x00, 0x02, 0x2E,
+            0xAB,
+        ]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = ": main
+            if v0 == 1 then v1 := 2
+            if v0 != v1 begin clear else return end
+            loop while v0 key v0 += 1 again";
+        assert_eq!(assemble(source).unwrap(), vec![
+            0x40, 0x01, 0x61, 0x02,
+            0x90, 0x10, 0x12, 0x0C, 0x00, 0xE0, 0x12, 0x0E, 0x00, 0xEE,
+            0xE0, 0x9E, 0x12, 0x16, 0x70, 0x01, 0x12, 0x0E,
+        ]);
+    }
+
+    #[test]
+    fn comparisons_use_compare_temp() {
+        assert_eq!(assemble(": main if v1 > 5 then clear").unwrap(), vec![0x6F, 0x05, 0x8F, 0x15, 0x4F, 0x00, 0x00, 0xE0]);
+        assert_eq!(assemble(": main if v1 <= v2 then clear").unwrap(), vec![0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x00, 0x00, 0xE0]);
+        assert_eq!(assemble(": main if v1 < 5 begin clear end").unwrap(), vec![0x6F, 0x05, 0x8F, 0x17, 0x3F, 0x00, 0x12, 0x0A, 0x00, 0xE0]);
+    }
+
+    #[test]
+    fn directives() {
+        let source = ":const SPEED 3 :alias x v5 :calc DOUBLE { SPEED * 2 + 1 }
+            :macro set reg value { reg := value }
+            : main set x DOUBLE :unpack 0xA data :byte { DOUBLE - 1 } :pointer data
+            :org 0x210 : data :next patch v0 := 0
+            :assert \"data placed\" { data == 0x210 }";
+        assert_eq!(assemble(source).unwrap(), vec![
+            // Calc expressions evaluate right to left: SPEED * (2 + 1).
+            0x65, 0x09, 0x60, 0xA2, 0x61, 0x10, 0x08, 0x02, 0x10, 0, 0, 0, 0, 0, 0, 0,
+            0x60, 0x00,
+        ]);
+        assert!(assemble(": main :assert { 1 == 2 }").unwrap_err().contains("Assertion failed"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(assemble(": main jump nowhere").unwrap_err().contains("Undefined name 'nowhere'"));
+        assert!(assemble(": main v0 := 256").unwrap_err().contains("out of range"));
+        assert!(assemble(": main loop clear").unwrap_err().contains("'loop' without 'again'"));
+        assert!(assemble(": main : main").unwrap_err().contains("already defined"));
+        assert!(assemble(":macro m { m } : main m").unwrap_err().contains("recursive"));
+    }
+}
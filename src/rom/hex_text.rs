@@ -0,0 +1,44 @@
+//! Hex text roms.
+//!
+//! A listing of hex numbers separated by whitespace, eg: "00E0 A22A 600C".
+//! Each number is one or more whole bytes (an optional 0x prefix is allowed).
+//! Comments start with '#', ';' or '//' and run to the end of the line, and
+//! address labels (numbers followed by ':', eg: "0x200:") are ignored.
+
+use common::types::primative::*;
+
+/// Returns the line with any comment removed.
+fn strip_comment(line: &str) -> &str {
+    let end = ["#", ";", "//"].iter()
+        .filter_map(|marker| line.find(marker))
+        .min()
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Decodes hex text into an image starting at the program start address.
+pub fn parse(text: &str) -> Result<Vec<uword>, String> {
+    let mut image = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        for token in strip_comment(line).split_whitespace() {
+            if token.ends_with(':') {
+                continue;
+            }
+
+            let digits = if token.starts_with("0x") || token.starts_with("0X") { &token[2..] } else { token };
+            if !digits.chars().all(|c| c.is_digit(16)) {
+                return Err(format!("Line {}: '{}' is not a hex number", line_index + 1, token));
+            }
+            if digits.is_empty() || digits.len() % 2 != 0 {
+                return Err(format!("Line {}: '{}' is not a whole number of bytes", line_index + 1, token));
+            }
+
+            for index in 0..(digits.len() / 2) {
+                image.push(u8::from_str_radix(&digits[(index * 2)..(index * 2 + 2)], 16).unwrap());
+            }
+        }
+    }
+
+    Ok(image)
+}
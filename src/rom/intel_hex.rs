@@ -0,0 +1,105 @@
+//! Intel HEX roms.
+//!
+//! Supports data, end of file and extended address records (start address
+//! records are ignored). If all data is at or above the program start address
+//! the record addresses are memory addresses, otherwise they are offsets from
+//! the program start.
+
+use std::collections::BTreeMap;
+use common::constants::memory::{MEMORY_SIZE, PROGRAM_START};
+use common::types::primative::*;
+
+const RECORD_DATA: u8 = 0x00;
+const RECORD_EOF: u8 = 0x01;
+const RECORD_EXTENDED_SEGMENT: u8 = 0x02;
+const RECORD_START_SEGMENT: u8 = 0x03;
+const RECORD_EXTENDED_LINEAR: u8 = 0x04;
+const RECORD_START_LINEAR: u8 = 0x05;
+
+/// Returns true if the data looks like Intel HEX (starts with a record).
+pub fn is_intel_hex(data: &[u8]) -> bool {
+    data.iter().find(|&&byte| !(byte as char).is_whitespace()) == Some(&b':')
+}
+
+/// Decodes the records of one line.
+fn parse_record(line: &str) -> Result<Vec<u8>, String> {
+    if !line.starts_with(':') {
+        return Err("Record does not start with ':'".to_owned());
+    }
+
+    let digits = &line[1..];
+    if !digits.chars().all(|c| c.is_digit(16)) {
+        return Err("Record contains an invalid hex digit".to_owned());
+    }
+    if digits.len() % 2 != 0 || digits.len() < 10 {
+        return Err("Record has an invalid length".to_owned());
+    }
+
+    let bytes: Vec<u8> = (0..(digits.len() / 2))
+        .map(|index| u8::from_str_radix(&digits[(index * 2)..(index * 2 + 2)], 16).unwrap())
+        .collect();
+
+    if bytes.len() != bytes[0] as usize + 5 {
+        return Err(format!("Record byte count 0x{:02X} does not match its length", bytes[0]));
+    }
+    if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+        return Err("Record checksum mismatch".to_owned());
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes Intel HEX text into an image starting at the program start address. Gaps are zero filled.
+pub fn parse(text: &str) -> Result<Vec<uword>, String> {
+    let mut memory = BTreeMap::new();
+    let mut base_address = 0;
+    let mut found_eof = false;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if found_eof {
+            return Err(format!("Line {}: data after end of file record", line_index + 1));
+        }
+
+        let record = parse_record(line).map_err(|e| format!("Line {}: {}", line_index + 1, e))?;
+        let address = ((record[1] as usize) << 8) | (record[2] as usize);
+        let data = &record[4..(record.len() - 1)];
+
+        match record[3] {
+            RECORD_DATA => {
+                // Checked here, before the image is allocated, as extended addresses reach up to 4 GiB.
+                let data_end = base_address as u64 + address as u64 + data.len() as u64;
+                if data_end > MEMORY_SIZE as u64 {
+                    return Err(format!("Line {}: data at 0x{:X} is outside memory (0x{:X} bytes)", line_index + 1, base_address + address, MEMORY_SIZE));
+                }
+                for (offset, &byte) in data.iter().enumerate() {
+                    memory.insert(base_address + address + offset, byte);
+                }
+            },
+            RECORD_EOF => found_eof = true,
+            RECORD_EXTENDED_SEGMENT if data.len() == 2 => base_address = (((data[0] as usize) << 8) | (data[1] as usize)) << 4,
+            RECORD_EXTENDED_LINEAR if data.len() == 2 => base_address = (((data[0] as usize) << 8) | (data[1] as usize)) << 16,
+            RECORD_START_SEGMENT | RECORD_START_LINEAR => {},
+            kind => return Err(format!("Line {}: unsupported record type 0x{:02X}", line_index + 1, kind)),
+        }
+    }
+
+    if !found_eof {
+        return Err("Missing end of file record".to_owned());
+    }
+
+    let (start, end) = match (memory.keys().next(), memory.keys().next_back()) {
+        (Some(&start), Some(&end)) => (start, end),
+        _ => return Ok(Vec::new()),
+    };
+    let origin = if start >= PROGRAM_START { PROGRAM_START } else { 0 };
+
+    let mut image = vec![0; end + 1 - origin];
+    for (address, byte) in memory {
+        image[address - origin] = byte;
+    }
+    Ok(image)
+}
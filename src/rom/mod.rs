@@ -0,0 +1,128 @@
+//! Rom file loading.
+//!
+//! Supports raw binaries (.ch8, .c8, .sc8, .xo8), Intel HEX, hex text listings
+//! and Octo cartridge GIFs. The format is detected from the file extension,
+//! falling back to the file contents. All formats are decoded into an image
+//! loaded at the program start address.
+
+pub mod assembler;
+pub mod intel_hex;
+pub mod hex_text;
+pub mod octo;
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use common::types::primative::*;
+use rom::octo::OctoOptions;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RomFormat {
+    Raw,
+    IntelHex,
+    HexText,
+    OctoCartridge,
+}
+
+impl RomFormat {
+    /// Detects the format of a rom from its path and contents.
+    pub fn detect(path: &str, data: &[u8]) -> RomFormat {
+        let extension = Path::new(path).extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_ref().map(|extension| extension.as_str()) {
+            Some("ch8") | Some("c8") | Some("sc8") | Some("xo8") | Some("bin") => RomFormat::Raw,
+            Some("gif") => RomFormat::OctoCartridge,
+            Some("hex") | Some("ihx") | Some("ihex") if intel_hex::is_intel_hex(data) => RomFormat::IntelHex,
+            Some("hex") | Some("txt") => RomFormat::HexText,
+            _ if data.starts_with(b"GIF8") => RomFormat::OctoCartridge,
+            _ => RomFormat::Raw,
+        }
+    }
+}
+
+impl fmt::Display for RomFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomFormat::Raw => write!(f, "raw binary"),
+            RomFormat::IntelHex => write!(f, "Intel HEX"),
+            RomFormat::HexText => write!(f, "hex text"),
+            RomFormat::OctoCartridge => write!(f, "Octo cartridge"),
+        }
+    }
+}
+
+/// A decoded rom.
+pub struct Rom {
+    pub format: RomFormat,
+
+    /// Image loaded at the program start address.
+    pub data: Vec<uword>,
+
+    /// Options embedded in an Octo cartridge.
+    pub options: Option<OctoOptions>,
+}
+
+impl Rom {
+    /// Reads and decodes the rom file, detecting the format.
+    pub fn open(path: &str) -> Result<Rom, String> {
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| format!("Could not read rom file {}: {}", path, e))?;
+
+        let format = RomFormat::detect(path, &data);
+        Rom::decode(format, &data).map_err(|e| format!("Could not load rom file {} ({}): {}", path, format, e))
+    }
+
+    /// Decodes rom file contents of the given format.
+    pub fn decode(format: RomFormat, data: &[u8]) -> Result<Rom, String> {
+        match format {
+            RomFormat::Raw => Ok(Rom {
+                format,
+                data: data.to_vec(),
+                options: None,
+            }),
+            RomFormat::IntelHex => Ok(Rom {
+                format,
+                data: intel_hex::parse(&text(data)?)?,
+                options: None,
+            }),
+            RomFormat::HexText => Ok(Rom {
+                format,
+                data: hex_text::parse(&text(data)?)?,
+                options: None,
+            }),
+            RomFormat::OctoCartridge => {
+                let cartridge = octo::read_cartridge(data)?;
+                Ok(Rom {
+                    format,
+                    data: cartridge.assemble()?,
+                    options: Some(cartridge.options),
+                })
+            },
+        }
+    }
+
+    /// Checks the rom fits in the available program memory (and the cartridge's own limit, if any).
+    pub fn check_size(&self, available: usize) -> Result<(), String> {
+        if self.data.is_empty() {
+            return Err("Rom is empty".to_owned());
+        }
+        if self.data.len() > available {
+            return Err(format!("Rom is too large (0x{:X} bytes, 0x{:X} available)", self.data.len(), available));
+        }
+        if let Some(max_size) = self.options.as_ref().and_then(|options| options.max_size) {
+            if self.data.len() > max_size {
+                return Err(format!("Rom is larger than the cartridge maxSize (0x{:X} bytes, maximum 0x{:X})", self.data.len(), max_size));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn text(data: &[u8]) -> Result<String, String> {
+    String::from_utf8(data.to_vec()).map_err(|_| "File is not valid text".to_owned())
+}
@@ -0,0 +1,153 @@
+//! Octo cartridge roms.
+//!
+//! An Octo cartridge is a GIF with a payload hidden in the low 2 bits of each
+//! pixel's palette index: 4 pixels per byte (most significant bits first),
+//! continuing through every frame. The payload is a 4 byte big endian length
+//! followed by JSON holding the program source and the emulator options.
+//!
+//! The program is Octo source, assembled with rom::assembler.
+
+use std::io::Cursor;
+use gif;
+use gif::SetParameter;
+use serde_json;
+use common::constants::{cpu, timer};
+use common::types::primative::*;
+use video::palette::Palette;
+use rom::assembler;
+
+/// Emulator options stored in an Octo cartridge. Absent options are None.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OctoOptions {
+    /// Instructions executed per 60Hz frame.
+    pub tickrate: Option<u32>,
+
+    /// Palette, as CSS colours (eg: "#FFCC00").
+    pub background_color: Option<String>,
+    pub fill_color: Option<String>,
+    pub fill_color2: Option<String>,
+    pub blend_color: Option<String>,
+    pub buzz_color: Option<String>,
+    pub quiet_color: Option<String>,
+
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub vf_order_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+    pub v_blank_quirks: Option<bool>,
+    pub enable_xo: Option<bool>,
+
+    pub screen_rotation: Option<u32>,
+    pub font_style: Option<String>,
+    pub touch_input_mode: Option<String>,
+
+    /// Maximum rom size in bytes.
+    pub max_size: Option<usize>,
+}
+
+impl OctoOptions {
+    /// Returns the Cpu speed multiplier (see Config::cpu_bias) matching the tickrate, if set.
+    pub fn cpu_bias(&self) -> Option<f64> {
+        match self.tickrate {
+            Some(tickrate) if tickrate > 0 => Some(tickrate as f64 * timer::CLOCK_SPEED / cpu::CLOCK_SPEED),
+            _ => None,
+        }
+    }
+
+    /// Returns the quirks set, by name (see quirks::QUIRK_NAMES). Octo quirks this emulator doesn't
+    /// have (VF order, vblank) are left out.
+    pub fn quirks(&self) -> Vec<(&'static str, bool)> {
+        let quirks = [
+            ("shift", self.shift_quirks),
+            ("load_store", self.load_store_quirks),
+            ("jump", self.jump_quirks),
+            ("logic", self.logic_quirks),
+            ("clip", self.clip_quirks),
+        ];
+        quirks.iter().filter_map(|&(name, enabled)| enabled.map(|enabled| (name, enabled))).collect()
+    }
+
+    /// Returns the palette set, if any: the background and fill colours, along with fill colour 2 and
+    /// the blend colour if both are set.
+    pub fn palette(&self) -> Option<Result<Palette, String>> {
+        let (background, fill) = match (self.background_color.as_ref(), self.fill_color.as_ref()) {
+            (Some(background), Some(fill)) => (background, fill),
+            _ => return None,
+        };
+
+        let mut colours = vec![background.clone(), fill.clone(), fill.clone(), fill.clone()];
+        if let (Some(fill2), Some(blend)) = (self.fill_color2.as_ref(), self.blend_color.as_ref()) {
+            colours[2] = fill2.clone();
+            colours[3] = blend.clone();
+        }
+        // Octo also accepts 3 digit colours.
+        for colour in colours.iter_mut() {
+            if colour.len() == 4 && colour.starts_with('#') {
+                *colour = colour.chars().flat_map(|c| if c == '#' { vec![c] } else { vec![c, c] }).collect();
+            }
+        }
+        Some(Palette::parse(&colours).map_err(|e| format!("Invalid cartridge palette: {}", e)))
+    }
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    program: String,
+    #[serde(default)]
+    options: OctoOptions,
+}
+
+/// The contents of an Octo cartridge.
+pub struct Cartridge {
+    /// Octo source of the program.
+    pub program: String,
+    pub options: OctoOptions,
+}
+
+/// Extracts the payload bytes hidden in the frames of the GIF.
+fn read_payload(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoder = gif::Decoder::new(Cursor::new(data));
+    decoder.set(gif::ColorOutput::Indexed);
+    let mut reader = decoder.read_info().map_err(|e| format!("Invalid GIF: {}", e))?;
+
+    let mut payload = Vec::new();
+    while let Some(frame) = reader.read_next_frame().map_err(|e| format!("Invalid GIF: {}", e))? {
+        for pixels in frame.buffer.chunks(4) {
+            payload.push(pixels.iter().fold(0, |byte, &index| (byte << 2) | (index & 0x3)));
+        }
+    }
+    Ok(payload)
+}
+
+/// Reads the program and options from the cartridge GIF.
+pub fn read_cartridge(data: &[u8]) -> Result<Cartridge, String> {
+    let payload = read_payload(data)?;
+    if payload.len() < 4 {
+        return Err("Cartridge contains no payload".to_owned());
+    }
+
+    let size = payload[..4].iter().fold(0, |size, &byte| (size << 8) | byte as usize);
+    if payload.len() < 4 + size {
+        return Err(format!("Cartridge payload is truncated (0x{:X} of 0x{:X} bytes)", payload.len() - 4, size));
+    }
+
+    // Older cartridges store the JSON one character per byte rather than as UTF-8.
+    let json = &payload[4..(4 + size)];
+    let json = String::from_utf8(json.to_vec()).unwrap_or_else(|_| json.iter().map(|&byte| byte as char).collect());
+
+    let payload: Payload = serde_json::from_str(&json).map_err(|e| format!("Invalid cartridge payload: {}", e))?;
+    Ok(Cartridge {
+        program: payload.program,
+        options: payload.options,
+    })
+}
+
+impl Cartridge {
+    /// Assembles the program source into a binary (see rom::assembler).
+    pub fn assemble(&self) -> Result<Vec<uword>, String> {
+        assembler::assemble(&self.program)
+    }
+}
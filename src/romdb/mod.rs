@@ -0,0 +1,133 @@
+//! Rom metadata database.
+//!
+//! Maps the SHA-1 of a rom image (as loaded into memory, so the same program
+//! matches in any rom format) to its metadata and recommended settings. The
+//! database is a JSON object keyed by lowercase hex SHA-1, eg:
+//!
+//! ```json
+//! {
+//!     "b1e4d5a9d5c0a3d7c54c1e0ebd5c5f3f0e1d2c3b": {
+//!         "title": "Brix",
+//!         "platform": "chip8",
+//!         "cpu_bias": 1.5,
+//!         "quirks": { "shift": true },
+//!         "keymap": { "4": "Q", "6": ["E", "button:a"] },
+//!         "palette": ["#000000", "#FFCC00"]
+//!     }
+//! }
+//! ```
+//!
+//! A user database can be loaded over the default one; its entries override
+//! the default entries field by field.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use serde_json;
+use sha1::Sha1;
+use common::types::primative::*;
+use keymap::KeyBindings;
+
+/// Paths of the default and user databases, relative to the workspace.
+pub const DATABASE_PATH: &'static str = "config/romdb.json";
+pub const USER_DATABASE_PATH: &'static str = "config/romdb.user.json";
+
+/// Metadata and recommended settings of a rom. Absent fields are None (or empty).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RomEntry {
+    pub title: Option<String>,
+
+    /// Platform the rom was written for, eg: "chip8", "schip" or "xochip".
+    pub platform: Option<String>,
+
+    /// Recommended Cpu speed multiplier (see Config::cpu_bias).
+    pub cpu_bias: Option<f64>,
+
+    /// Interpreter quirks by name (see quirks::QUIRK_NAMES), eg: "shift", "load_store".
+    pub quirks: BTreeMap<String, bool>,
+
+    /// Bindings by Chip8 key (hex digit), applied over the configured keymap (see keymap).
+    pub keymap: BTreeMap<String, KeyBindings>,
+
+    /// Display colours (eg: "#FFCC00"), background first: 2 or 4 colours (see video::palette::Palette::parse()).
+    pub palette: Vec<String>,
+}
+
+impl RomEntry {
+    /// Returns the entry with the fields set in overrides replaced.
+    pub fn merge(mut self, overrides: RomEntry) -> RomEntry {
+        self.title = overrides.title.or(self.title);
+        self.platform = overrides.platform.or(self.platform);
+        self.cpu_bias = overrides.cpu_bias.or(self.cpu_bias);
+        self.quirks.extend(overrides.quirks);
+        self.keymap.extend(overrides.keymap);
+        if !overrides.palette.is_empty() {
+            self.palette = overrides.palette;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    entries: BTreeMap<String, RomEntry>,
+}
+
+/// Returns the lowercase hex SHA-1 of the rom image, as used for database keys.
+pub fn rom_hash(data: &[uword]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(data);
+    sha1.digest().to_string()
+}
+
+impl RomDatabase {
+    pub fn new() -> RomDatabase {
+        RomDatabase {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Loads a database file. A missing file gives an empty database.
+    pub fn load(path: &str) -> Result<RomDatabase, String> {
+        let mut database = RomDatabase::new();
+        database.load_overrides(path)?;
+        Ok(database)
+    }
+
+    /// Loads a database file over this one, merging entries with the same hash.
+    /// A missing file is ignored.
+    pub fn load_overrides(&mut self, path: &str) -> Result<(), String> {
+        let mut json = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut json).map_err(|e| format!("Could not read rom database {}: {}", path, e))?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Could not read rom database {}: {}", path, e)),
+        };
+
+        let entries: BTreeMap<String, RomEntry> = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid rom database {}: {}", path, e))?;
+        for (hash, entry) in entries {
+            let hash = hash.to_lowercase();
+            let entry = match self.entries.remove(&hash) {
+                Some(existing) => existing.merge(entry),
+                None => entry,
+            };
+            self.entries.insert(hash, entry);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry for the given SHA-1 (see rom_hash()).
+    pub fn lookup(&self, hash: &str) -> Option<&RomEntry> {
+        self.entries.get(&hash.to_lowercase())
+    }
+}
@@ -0,0 +1,332 @@
+//! Memory access sanitizer.
+//!
+//! Checks each executed instruction and data memory access for common rom
+//! bugs: reading memory that was never written, writing the font set or
+//! interpreter area, executing bytes written as data (self modifying code),
+//! a misaligned pc, and sprites drawn from past the end of memory.
+//!
+//! Each distinct violation (kind, pc and address) is reported once, either as
+//! a logged warning or by stopping emulation through the debugger.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+use common::constants::cpu::INSTRUCTION_SIZE;
+use common::constants::memory::{MEMORY_SIZE, PROGRAM_START};
+use common::types::primative::*;
+use common::types::storage::*;
+use resources::Resources;
+use resources::cpu::instruction::Instruction;
+
+/// What to do when a violation is found.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SanitizerMode {
+    /// Log a warning and continue.
+    Warn,
+
+    /// Log a warning and stop emulation (see StopReason::Sanitizer).
+    Stop,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// Data memory read that was never written.
+    UninitialisedRead(uptr),
+
+    /// Instruction fetched from memory that was never written.
+    UninitialisedExecute(uptr),
+
+    /// Write into the font sets (see Core::font_range()).
+    FontWrite(uptr),
+
+    /// Write into the rest of the interpreter area (below 0x200).
+    InterpreterWrite(uptr),
+
+    /// Instruction fetched from bytes last written as data.
+    DataExecute(uptr),
+
+    /// Pc not aligned to an instruction.
+    MisalignedPc,
+
+    /// Sprite drawn from I with the given height extends past the end of memory.
+    SpriteOutOfBounds { address: uptr, height: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::UninitialisedRead(address) => write!(f, "read of never written memory at 0x{:03X}", address),
+            Violation::UninitialisedExecute(address) => write!(f, "execution of never written memory at 0x{:03X}", address),
+            Violation::FontWrite(address) => write!(f, "write into font area at 0x{:03X}", address),
+            Violation::InterpreterWrite(address) => write!(f, "write into interpreter area at 0x{:03X}", address),
+            Violation::DataExecute(address) => write!(f, "execution of bytes written as data at 0x{:03X}", address),
+            Violation::MisalignedPc => write!(f, "misaligned pc"),
+            Violation::SpriteOutOfBounds { address, height } => write!(f, "sprite at 0x{:03X} (height {}) extends past end of memory", address, height),
+        }
+    }
+}
+
+/// A violation found while executing an instruction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Report {
+    pub pc: uptr,
+    pub cycle: u64,
+    pub violation: Violation,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (pc = 0x{:03X}, cycle = {})", self.violation, self.pc, self.cycle)
+    }
+}
+
+pub struct Sanitizer {
+    mode: SanitizerMode,
+
+    /// Bytes written by the rom loader, the host or the program.
+    written: Vec<bool>,
+
+    /// Bytes last written as data by the program.
+    data: Vec<bool>,
+
+    /// Memory the font sets were loaded into.
+    font: Range<usize>,
+
+    /// Pc and cycle of the instruction currently executing.
+    pc: uptr,
+    cycle: u64,
+
+    /// Violations already reported, by (pc, violation).
+    reported: HashSet<(uptr, Violation)>,
+
+    reports: Vec<Report>,
+}
+
+impl Sanitizer {
+    pub fn new(mode: SanitizerMode) -> Sanitizer {
+        Sanitizer {
+            mode,
+            written: vec![false; MEMORY_SIZE],
+            data: vec![false; MEMORY_SIZE],
+            font: 0..0,
+            pc: 0,
+            cycle: 0,
+            reported: HashSet::new(),
+            reports: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> SanitizerMode {
+        self.mode
+    }
+
+    /// Clears the memory state, used when the machine is reset.
+    /// The given ranges (the font sets and rom) are considered written, and writes into the
+    /// font range given are reported as font writes. Reports are kept.
+    pub fn reset(&mut self, initialised: &[Range<usize>], font: Range<usize>) {
+        self.font = font;
+        for (address, written) in self.written.iter_mut().enumerate() {
+            *written = initialised.iter().any(|range| address >= range.start && address < range.end);
+        }
+        for data in self.data.iter_mut() {
+            *data = false;
+        }
+    }
+
+    /// Returns all violations reported so far.
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    /// Called by the Cpu controller before executing an instruction.
+    /// Returns a report if execution should stop before the instruction.
+    pub fn before_execute(&mut self, res: &Resources, pc: uptr, inst: &Instruction) -> Option<Report> {
+        self.pc = pc;
+        self.cycle = res.cpu.cycles;
+
+        let mut violations = Vec::new();
+        if pc as usize % INSTRUCTION_SIZE != 0 {
+            violations.push(Violation::MisalignedPc);
+        }
+
+        for address in (pc as usize)..(pc as usize + INSTRUCTION_SIZE) {
+            let address = address % MEMORY_SIZE;
+            if !self.written[address] {
+                violations.push(Violation::UninitialisedExecute(address as uptr));
+                break;
+            }
+            if self.data[address] {
+                violations.push(Violation::DataExecute(address as uptr));
+                break;
+            }
+        }
+
+        if inst.mnemonic() == Some("draw") {
+            let address: uptr = res.cpu.i.read(BusContext::Raw, 0);
+            let height = inst.raw().low_nibble() as usize;
+            if address as usize + height > MEMORY_SIZE {
+                violations.push(Violation::SpriteOutOfBounds { address, height });
+            }
+        }
+
+        // Every violation is reported, even when execution stops on the first.
+        let reports: Vec<Report> = violations.into_iter().filter_map(|violation| self.report(violation)).collect();
+        reports.into_iter().next()
+    }
+
+    /// Called by the Cpu controller when the executing instruction reads data memory.
+    /// Returns a report if execution should stop after the instruction.
+    pub fn on_memory_read(&mut self, address: usize) -> Option<Report> {
+        if !self.written[address % MEMORY_SIZE] {
+            return self.report(Violation::UninitialisedRead(address as uptr));
+        }
+        None
+    }
+
+    /// Called by the Cpu controller when the executing instruction writes data memory.
+    /// Returns a report if execution should stop after the instruction.
+    pub fn on_memory_write(&mut self, address: usize) -> Option<Report> {
+        let address = address % MEMORY_SIZE;
+        self.written[address] = true;
+        self.data[address] = true;
+
+        if address >= self.font.start && address < self.font.end {
+            self.report(Violation::FontWrite(address as uptr))
+        } else if address < PROGRAM_START {
+            self.report(Violation::InterpreterWrite(address as uptr))
+        } else {
+            None
+        }
+    }
+
+    /// Called when the host writes memory (eg: through a debugger), which is not checked.
+    pub fn on_host_write(&mut self, address: usize) {
+        let address = address % MEMORY_SIZE;
+        self.written[address] = true;
+        self.data[address] = false;
+    }
+
+    /// Records a violation if not reported before, returning it if execution should stop.
+    fn report(&mut self, violation: Violation) -> Option<Report> {
+        if !self.reported.insert((self.pc, violation)) {
+            return None;
+        }
+
+        let report = Report {
+            pc: self.pc,
+            cycle: self.cycle,
+            violation,
+        };
+        warn!("Sanitizer: {}", report);
+        self.reports.push(report);
+
+        match self.mode {
+            SanitizerMode::Warn => None,
+            SanitizerMode::Stop => Some(report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use resources::bus::Bus;
+    use resources::layout::MemoryLayout;
+
+    /// Returns a sanitizer with the font at 0x50..0xA0 and a rom at 0x200..0x210 written.
+    fn sanitizer(mode: SanitizerMode) -> Sanitizer {
+        let mut sanitizer = Sanitizer::new(mode);
+        sanitizer.reset(&[0x50..0xA0, 0x200..0x210], 0x50..0xA0);
+        sanitizer
+    }
+
+    fn resources() -> Resources {
+        Resources::with_bus(Bus::new(MEMORY_SIZE, 0..0), MemoryLayout::Default)
+    }
+
+    fn violations(sanitizer: &Sanitizer) -> Vec<Violation> {
+        sanitizer.reports().iter().map(|report| report.violation).collect()
+    }
+
+    #[test]
+    fn memory_accesses() {
+        let res = resources();
+        let mut sanitizer = sanitizer(SanitizerMode::Warn);
+        sanitizer.before_execute(&res, 0x200, &Instruction::new(0xF065));
+
+        assert_eq!(sanitizer.on_memory_read(0x208), None);
+        assert_eq!(sanitizer.on_memory_read(0x300), None);
+        assert_eq!(sanitizer.on_memory_write(0x60), None);
+        assert_eq!(sanitizer.on_memory_write(0x10), None);
+        assert_eq!(sanitizer.on_memory_write(0x300), None);
+        assert_eq!(sanitizer.on_memory_read(0x300), None);
+
+        assert_eq!(violations(&sanitizer), vec![
+            Violation::UninitialisedRead(0x300),
+            Violation::FontWrite(0x60),
+            Violation::InterpreterWrite(0x10),
+        ]);
+        assert_eq!(sanitizer.reports()[0].pc, 0x200);
+    }
+
+    #[test]
+    fn execution() {
+        let res = resources();
+        let mut sanitizer = sanitizer(SanitizerMode::Warn);
+
+        sanitizer.before_execute(&res, 0x201, &Instruction::new(0x00E0));
+        sanitizer.before_execute(&res, 0x300, &Instruction::new(0x00E0));
+
+        // Self modifying code, unless the bytes were written by the host.
+        sanitizer.on_memory_write(0x208);
+        sanitizer.before_execute(&res, 0x208, &Instruction::new(0x00E0));
+        sanitizer.on_host_write(0x20A);
+        sanitizer.before_execute(&res, 0x20A, &Instruction::new(0x00E0));
+
+        // A sprite ending exactly at the end of memory is fine.
+        res.cpu.i.write(BusContext::Raw, 0, 0xFFC as uptr);
+        sanitizer.before_execute(&res, 0x20C, &Instruction::new(0xD014));
+        sanitizer.before_execute(&res, 0x20E, &Instruction::new(0xD015));
+
+        assert_eq!(violations(&sanitizer), vec![
+            Violation::MisalignedPc,
+            Violation::UninitialisedExecute(0x300),
+            Violation::DataExecute(0x208),
+            Violation::SpriteOutOfBounds { address: 0xFFC, height: 5 },
+        ]);
+    }
+
+    #[test]
+    fn reported_once() {
+        let res = resources();
+        let mut sanitizer = sanitizer(SanitizerMode::Warn);
+        for _ in 0..2 {
+            sanitizer.before_execute(&res, 0x200, &Instruction::new(0xF065));
+            sanitizer.on_memory_read(0x300);
+        }
+        assert_eq!(sanitizer.reports().len(), 1);
+
+        // The same violation at another pc is reported again.
+        sanitizer.before_execute(&res, 0x202, &Instruction::new(0xF065));
+        sanitizer.on_memory_read(0x300);
+        assert_eq!(sanitizer.reports().len(), 2);
+
+        // Reports are kept across a reset.
+        sanitizer.reset(&[], 0..0);
+        assert_eq!(sanitizer.reports().len(), 2);
+    }
+
+    #[test]
+    fn stop_mode() {
+        let res = resources();
+        let mut sanitizer = sanitizer(SanitizerMode::Stop);
+
+        // Both violations are reported, the first is returned to stop on.
+        let report = sanitizer.before_execute(&res, 0x301, &Instruction::new(0x00E0));
+        assert_eq!(report.map(|report| report.violation), Some(Violation::MisalignedPc));
+        assert_eq!(violations(&sanitizer), vec![Violation::MisalignedPc, Violation::UninitialisedExecute(0x301)]);
+
+        assert!(sanitizer.on_memory_write(0x10).is_some());
+        assert!(sanitizer.on_memory_write(0x10).is_none());
+    }
+}
@@ -0,0 +1,333 @@
+//! Instruction trace recording.
+//!
+//! Records, for each executed instruction within the enabled address range,
+//! the cycle, pc, opcode, the registers it changed and the memory it wrote.
+//! Traces are written as either text (one instruction per line) or a compact
+//! binary format, both of which can be read back with TraceReader.
+
+pub mod reader;
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::ops::Range;
+use common::bytes::{le_u16, le_u64};
+use common::types::primative::*;
+use common::types::storage::*;
+use resources::Resources;
+use resources::cpu::instruction::Instruction;
+
+/// Magic bytes at the start of a binary trace file.
+pub const BINARY_MAGIC: &'static [u8] = b"C8TR\x01";
+
+/// Registers tracked for changes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceRegister {
+    Gpr(usize),
+    I,
+    Sp,
+    Dt,
+    St,
+}
+
+impl TraceRegister {
+    /// Returns the id used in the binary format.
+    pub fn id(&self) -> u8 {
+        match *self {
+            TraceRegister::Gpr(index) => index as u8,
+            TraceRegister::I => 0x10,
+            TraceRegister::Sp => 0x11,
+            TraceRegister::Dt => 0x12,
+            TraceRegister::St => 0x13,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<TraceRegister> {
+        match id {
+            0x0...0xF => Some(TraceRegister::Gpr(id as usize)),
+            0x10 => Some(TraceRegister::I),
+            0x11 => Some(TraceRegister::Sp),
+            0x12 => Some(TraceRegister::Dt),
+            0x13 => Some(TraceRegister::St),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TraceRegister> {
+        match name {
+            "I" => Some(TraceRegister::I),
+            "SP" => Some(TraceRegister::Sp),
+            "DT" => Some(TraceRegister::Dt),
+            "ST" => Some(TraceRegister::St),
+            _ if name.len() == 2 && name.starts_with('V') => {
+                usize::from_str_radix(&name[1..], 16).ok().map(TraceRegister::Gpr)
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TraceRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceRegister::Gpr(index) => write!(f, "V{:X}", index),
+            TraceRegister::I => write!(f, "I"),
+            TraceRegister::Sp => write!(f, "SP"),
+            TraceRegister::Dt => write!(f, "DT"),
+            TraceRegister::St => write!(f, "ST"),
+        }
+    }
+}
+
+/// A single traced instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// Cycle (instructions executed since reset) the instruction executed at.
+    pub cycle: u64,
+    pub pc: uptr,
+    pub opcode: udword,
+
+    /// Registers changed by the instruction, with their new values.
+    pub registers: Vec<(TraceRegister, udword)>,
+
+    /// Memory written by the instruction (address, value).
+    pub memory: Vec<(uptr, uword)>,
+}
+
+impl TraceRecord {
+    /// Returns if two records describe the same instruction with the same effects (ignoring the cycle).
+    pub fn same_effects(&self, other: &TraceRecord) -> bool {
+        self.pc == other.pc && self.opcode == other.opcode && self.registers == other.registers && self.memory == other.memory
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    /// Formats the record as a line of the text trace format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let disassembly = Instruction::new(self.opcode).to_string();
+        write!(f, "{:10} 0x{:03X} {:04X} {:20};", self.cycle, self.pc, self.opcode, disassembly)?;
+        for &(register, value) in self.registers.iter() {
+            write!(f, " {}=0x{:X}", register, value)?;
+        }
+        for &(address, value) in self.memory.iter() {
+            write!(f, " [0x{:03X}]=0x{:02X}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Trace output format.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+/// Register values compared before and after each instruction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Snapshot {
+    gpr: [uword; 16],
+    i: udword,
+    sp: usize,
+    dt: uword,
+    st: uword,
+}
+
+impl Snapshot {
+    fn new(res: &Resources) -> Snapshot {
+        let mut gpr = [0; 16];
+        for (index, value) in gpr.iter_mut().enumerate() {
+            *value = res.gpr(index);
+        }
+
+        Snapshot {
+            gpr,
+            i: res.cpu.i.read(BusContext::Raw, 0),
+            sp: res.stack_depth(),
+            dt: res.timer.counter.read(BusContext::Raw, 0),
+            st: res.spu.counter.read(BusContext::Raw, 0),
+        }
+    }
+
+    /// Returns the registers that differ in the other snapshot, with their new values.
+    fn changes(&self, other: &Snapshot) -> Vec<(TraceRegister, udword)> {
+        let mut changes = Vec::new();
+        for index in 0..16 {
+            if self.gpr[index] != other.gpr[index] {
+                changes.push((TraceRegister::Gpr(index), other.gpr[index] as udword));
+            }
+        }
+        if self.i != other.i {
+            changes.push((TraceRegister::I, other.i));
+        }
+        if self.sp != other.sp {
+            changes.push((TraceRegister::Sp, other.sp as udword));
+        }
+        if self.dt != other.dt {
+            changes.push((TraceRegister::Dt, other.dt as udword));
+        }
+        if self.st != other.st {
+            changes.push((TraceRegister::St, other.st as udword));
+        }
+        changes
+    }
+}
+
+/// Records executed instructions to a trace file.
+pub struct TraceRecorder {
+    writer: BufWriter<File>,
+    format: TraceFormat,
+
+    /// Pc range to record instructions within (all if None).
+    range: Option<Range<uptr>>,
+
+    /// Record being built for the instruction currently executing, if it is within range.
+    current: Option<(TraceRecord, Snapshot)>,
+}
+
+impl TraceRecorder {
+    /// Creates (or truncates) the trace file at the given path.
+    pub fn new(path: &str, format: TraceFormat, range: Option<Range<uptr>>) -> Result<TraceRecorder, String> {
+        let file = File::create(path).map_err(|e| format!("Could not create trace file {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+
+        if format == TraceFormat::Binary {
+            writer.write_all(BINARY_MAGIC).map_err(|e| e.to_string())?;
+        }
+
+        Ok(TraceRecorder {
+            writer,
+            format,
+            range,
+            current: None,
+        })
+    }
+
+    /// Called by the Cpu controller before executing an instruction.
+    pub fn before_execute(&mut self, res: &Resources, pc: uptr, opcode: udword) {
+        let in_range = match self.range {
+            Some(ref range) => pc >= range.start && pc < range.end,
+            None => true,
+        };
+
+        self.current = if in_range {
+            let record = TraceRecord {
+                cycle: res.cpu.cycles,
+                pc,
+                opcode,
+                registers: Vec::new(),
+                memory: Vec::new(),
+            };
+            Some((record, Snapshot::new(res)))
+        } else {
+            None
+        };
+    }
+
+    /// Called by the Cpu controller when the executing instruction writes data memory.
+    pub fn on_memory_write(&mut self, address: usize, value: uword) {
+        if let Some((ref mut record, _)) = self.current {
+            record.memory.push((address as uptr, value));
+        }
+    }
+
+    /// Called by the Cpu controller after executing an instruction; writes the record.
+    pub fn after_execute(&mut self, res: &Resources) -> Result<(), String> {
+        if let Some((mut record, before)) = self.current.take() {
+            record.registers = before.changes(&Snapshot::new(res));
+            self.write_record(&record).map_err(|e| format!("Could not write trace: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes buffered records to the file.
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+
+    fn write_record(&mut self, record: &TraceRecord) -> ::std::io::Result<()> {
+        match self.format {
+            TraceFormat::Text => {
+                writeln!(self.writer, "{}", record)
+            },
+            TraceFormat::Binary => {
+                // Layout (little endian): cycle u64, pc u16, opcode u16,
+                // register count u8, (id u8, value u16)*, write count u8, (address u16, value u8)*.
+                let mut data = Vec::with_capacity(16);
+                data.extend_from_slice(&le_u64(record.cycle));
+                data.extend_from_slice(&le_u16(record.pc));
+                data.extend_from_slice(&le_u16(record.opcode));
+                data.push(record.registers.len() as u8);
+                for &(register, value) in record.registers.iter() {
+                    data.push(register.id());
+                    data.extend_from_slice(&le_u16(value));
+                }
+                data.push(record.memory.len() as u8);
+                for &(address, value) in record.memory.iter() {
+                    data.extend_from_slice(&le_u16(address));
+                    data.push(value);
+                }
+                self.writer.write_all(&data)
+            },
+        }
+    }
+}
+
+impl Drop for TraceRecorder {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::constants::memory::MEMORY_SIZE;
+    use resources::bus::Bus;
+    use resources::layout::MemoryLayout;
+
+    fn record(cycle: u64, registers: Vec<(TraceRegister, udword)>) -> TraceRecord {
+        TraceRecord { cycle, pc: 0x200, opcode: 0x6A42, registers, memory: vec![(0x300, 0x42)] }
+    }
+
+    #[test]
+    fn register_names_and_ids() {
+        for id in 0..0x14 {
+            let register = TraceRegister::from_id(id).unwrap();
+            assert_eq!(register.id(), id);
+            assert_eq!(TraceRegister::from_name(&register.to_string()), Some(register));
+        }
+        assert_eq!(TraceRegister::from_id(0x14), None);
+        assert_eq!(TraceRegister::from_name("VG"), None);
+        assert_eq!(TraceRegister::from_name("V10"), None);
+    }
+
+    #[test]
+    fn same_effects() {
+        let a = record(10, vec![(TraceRegister::Gpr(0xA), 0x42)]);
+
+        // The cycle is left to the caller to compare.
+        assert!(a.same_effects(&record(12, vec![(TraceRegister::Gpr(0xA), 0x42)])));
+        assert!(!a.same_effects(&record(10, vec![(TraceRegister::Gpr(0xA), 0x43)])));
+        assert!(!a.same_effects(&record(10, Vec::new())));
+    }
+
+    #[test]
+    fn snapshot_changes() {
+        let mut res = Resources::with_bus(Bus::new(MEMORY_SIZE, 0..0), MemoryLayout::Default);
+        let before = Snapshot::new(&res);
+        res.set_gpr(0x3, 0x12);
+        res.cpu.i.write(BusContext::Raw, 0, 0x300 as udword);
+        res.push_stack(0x202);
+
+        assert_eq!(before.changes(&Snapshot::new(&res)), vec![
+            (TraceRegister::Gpr(0x3), 0x12),
+            (TraceRegister::I, 0x300),
+            (TraceRegister::Sp, 1),
+        ]);
+        assert!(before.changes(&before).is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+//! Reading back recorded traces (either format).
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use common::bytes::{u16_from_le, u64_from_le};
+use common::types::primative::*;
+use debugger::parse_value;
+use trace::*;
+
+pub struct TraceReader {
+    reader: BufReader<File>,
+    format: TraceFormat,
+
+    /// Line number (text) or record index (binary) of the last record read, for error messages.
+    position: usize,
+}
+
+impl TraceReader {
+    /// Opens a trace file, detecting its format from the binary magic header.
+    pub fn open(path: &str) -> Result<TraceReader, String> {
+        let file = File::open(path).map_err(|e| format!("Could not open trace file {}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+
+        let format = {
+            let buffer = reader.fill_buf().map_err(|e| e.to_string())?;
+            if buffer.starts_with(BINARY_MAGIC) { TraceFormat::Binary } else { TraceFormat::Text }
+        };
+        if format == TraceFormat::Binary {
+            reader.consume(BINARY_MAGIC.len());
+        }
+
+        Ok(TraceReader {
+            reader,
+            format,
+            position: 0,
+        })
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    /// Reads the next record, returning None at the end of the trace.
+    pub fn next_record(&mut self) -> Result<Option<TraceRecord>, String> {
+        self.position += 1;
+        let result = match self.format {
+            TraceFormat::Text => self.next_text_record(),
+            TraceFormat::Binary => self.next_binary_record(),
+        };
+        result.map_err(|e| format!("Invalid trace record {}: {}", self.position, e))
+    }
+
+    fn next_text_record(&mut self) -> Result<Option<TraceRecord>, String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+
+        let (instruction, effects) = match line.find(';') {
+            Some(position) => (&line[..position], &line[position + 1..]),
+            None => return Err("Missing ';' separator".to_owned()),
+        };
+
+        // The disassembly is informational only; the opcode is authoritative.
+        let fields: Vec<&str> = instruction.split_whitespace().collect();
+        if fields.len() < 3 {
+            return Err("Expected cycle, pc and opcode".to_owned());
+        }
+        let cycle = fields[0].parse::<u64>().map_err(|_| format!("Invalid cycle '{}'", fields[0]))?;
+        let pc = parse_value(fields[1])?;
+        let opcode = udword::from_str_radix(fields[2], 16).map_err(|_| format!("Invalid opcode '{}'", fields[2]))?;
+
+        let mut registers = Vec::new();
+        let mut memory = Vec::new();
+        for effect in effects.split_whitespace() {
+            let (target, value) = match effect.find('=') {
+                Some(position) => (&effect[..position], parse_value(&effect[position + 1..])?),
+                None => return Err(format!("Invalid change '{}'", effect)),
+            };
+
+            if target.starts_with('[') && target.ends_with(']') {
+                memory.push((parse_value(&target[1..target.len() - 1])?, value as uword));
+            } else {
+                let register = TraceRegister::from_name(target).ok_or(format!("Unknown register '{}'", target))?;
+                registers.push((register, value));
+            }
+        }
+
+        Ok(Some(TraceRecord { cycle, pc, opcode, registers, memory }))
+    }
+
+    fn next_binary_record(&mut self) -> Result<Option<TraceRecord>, String> {
+        let mut header = [0; 12];
+        if !self.read_exact_or_eof(&mut header)? {
+            return Ok(None);
+        }
+
+        let cycle = u64_from_le(&header[0..8]);
+        let pc = u16_from_le(&header[8..10]);
+        let opcode = u16_from_le(&header[10..12]);
+
+        let mut registers = Vec::new();
+        let count = self.read_u8()?;
+        for _ in 0..count {
+            let mut data = [0; 3];
+            self.read_exact(&mut data)?;
+            let register = TraceRegister::from_id(data[0]).ok_or(format!("Unknown register id {}", data[0]))?;
+            registers.push((register, u16_from_le(&data[1..3])));
+        }
+
+        let mut memory = Vec::new();
+        let count = self.read_u8()?;
+        for _ in 0..count {
+            let mut data = [0; 3];
+            self.read_exact(&mut data)?;
+            memory.push((u16_from_le(&data[0..2]), data[2]));
+        }
+
+        Ok(Some(TraceRecord { cycle, pc, opcode, registers, memory }))
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        let mut data = [0; 1];
+        self.read_exact(&mut data)?;
+        Ok(data[0])
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), String> {
+        self.reader.read_exact(buffer).map_err(|_| "Unexpected end of trace".to_owned())
+    }
+
+    /// Reads a full buffer, returning false if the trace ended cleanly before it.
+    fn read_exact_or_eof(&mut self, buffer: &mut [u8]) -> Result<bool, String> {
+        if self.reader.fill_buf().map_err(|e| e.to_string())?.is_empty() {
+            return Ok(false);
+        }
+        self.read_exact(buffer)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+    use common::constants::memory::MEMORY_SIZE;
+    use resources::Resources;
+    use resources::bus::Bus;
+    use resources::layout::MemoryLayout;
+
+    /// Records two instructions to a temporary trace, returning its path.
+    fn record_trace(name: &str, format: TraceFormat) -> String {
+        let path = env::temp_dir().join(format!("chip8-rs-test-{}.trace", name));
+        let path = path.to_str().unwrap().to_owned();
+        let mut res = Resources::with_bus(Bus::new(MEMORY_SIZE, 0..0), MemoryLayout::Default);
+        let mut recorder = TraceRecorder::new(&path, format, Some(0x200..0x300)).unwrap();
+
+        recorder.before_execute(&res, 0x200, 0x6A42);
+        res.set_gpr(0xA, 0x42);
+        recorder.after_execute(&res).unwrap();
+
+        res.cpu.cycles = 1;
+        recorder.before_execute(&res, 0x202, 0xFA55);
+        recorder.on_memory_write(0x300, 0x42);
+        recorder.after_execute(&res).unwrap();
+
+        // Outside the range, so not recorded.
+        recorder.before_execute(&res, 0x300, 0x00E0);
+        recorder.after_execute(&res).unwrap();
+
+        recorder.flush().unwrap();
+        path
+    }
+
+    fn read_trace(path: &str) -> (TraceFormat, Vec<TraceRecord>) {
+        let mut reader = TraceReader::open(path).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            records.push(record);
+        }
+        (reader.format(), records)
+    }
+
+    fn expected() -> Vec<TraceRecord> {
+        vec![
+            TraceRecord { cycle: 0, pc: 0x200, opcode: 0x6A42, registers: vec![(TraceRegister::Gpr(0xA), 0x42)], memory: Vec::new() },
+            TraceRecord { cycle: 1, pc: 0x202, opcode: 0xFA55, registers: Vec::new(), memory: vec![(0x300, 0x42)] },
+        ]
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let path = record_trace("text", TraceFormat::Text);
+        assert_eq!(read_trace(&path), (TraceFormat::Text, expected()));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let path = record_trace("binary", TraceFormat::Binary);
+        assert_eq!(read_trace(&path), (TraceFormat::Binary, expected()));
+    }
+
+    #[test]
+    fn invalid_records() {
+        let path = env::temp_dir().join("chip8-rs-test-invalid.trace");
+        let path = path.to_str().unwrap();
+
+        File::create(path).and_then(|mut file| file.write_all(b"0 0x200 6A42 LD VA, 0x42 VA=0x42\n")).unwrap();
+        let mut reader = TraceReader::open(path).unwrap();
+        assert_eq!(reader.next_record(), Err("Invalid trace record 1: Missing ';' separator".to_owned()));
+
+        // A binary record cut off after its header.
+        let mut data = BINARY_MAGIC.to_vec();
+        data.extend_from_slice(&[0; 12]);
+        File::create(path).and_then(|mut file| file.write_all(&data)).unwrap();
+        let mut reader = TraceReader::open(path).unwrap();
+        assert_eq!(reader.next_record(), Err("Invalid trace record 1: Unexpected end of trace".to_owned()));
+    }
+}
@@ -0,0 +1,396 @@
+//! Post-processing filters.
+//!
+//! Produces the final RGBA image from the display colours, in this order:
+//!  - Edge smoothing (Scale2x/Scale3x), or none.
+//!  - Integer nearest neighbour scaling, either fixed or the largest that fits the target size.
+//!  - Scanline and pixel grid overlays, darkening the bottom rows and the edges of each
+//!    display pixel.
+//!  - Letterboxing: centring within the target size, keeping the aspect ratio.
+//!
+//! Everything runs on the CPU, so any frontend (or a headless runner) can use it.
+
+use video::palette::Colour;
+
+/// RGBA colour.
+pub type Rgba = [u8; 4];
+
+/// Returns the colour as opaque RGBA.
+pub fn rgba(colour: Colour) -> Rgba {
+    [colour[0], colour[1], colour[2], 0xFF]
+}
+
+/// Names of the scalers, as used in config files.
+pub const SCALER_NAMES: [&'static str; 3] = ["nearest", "scale2x", "scale3x"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+
+    /// Pixels, row by row.
+    pixels: Vec<Rgba>,
+}
+
+impl Image {
+    /// Creates an image filled with the colour.
+    pub fn new(width: usize, height: usize, fill: Rgba) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    /// Creates an image from colours given row by row.
+    pub fn from_colours(width: usize, height: usize, colours: &[Colour]) -> Image {
+        assert_eq!(colours.len(), width * height, "Image size does not match the colours given");
+        Image {
+            width,
+            height,
+            pixels: colours.iter().map(|&colour| rgba(colour)).collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Rgba] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgba {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, colour: Rgba) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    /// Returns the pixel, with the coordinates clamped to the edges.
+    fn clamped_pixel(&self, x: isize, y: isize) -> Rgba {
+        let x = x.max(0).min(self.width as isize - 1) as usize;
+        let y = y.max(0).min(self.height as isize - 1) as usize;
+        self.pixel(x, y)
+    }
+
+    /// Returns the pixels as RGBA bytes, row by row.
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in self.pixels.iter() {
+            bytes.extend_from_slice(pixel);
+        }
+        bytes
+    }
+
+    /// Writes the pixels as RGB24 rows of pitch bytes (eg: into a streaming texture).
+    pub fn write_rgb24(&self, buffer: &mut [u8], pitch: usize) {
+        for (index, pixel) in self.pixels.iter().enumerate() {
+            let offset = (index / self.width) * pitch + (index % self.width) * 3;
+            buffer[offset..(offset + 3)].copy_from_slice(&pixel[0..3]);
+        }
+    }
+}
+
+/// Edge smoothing scaler, applied before the integer scaling.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scaler {
+    /// No smoothing.
+    Nearest,
+
+    /// Scale2x (EPX), doubling the size.
+    Scale2x,
+
+    /// Scale3x (AdvMAME3x), tripling the size.
+    Scale3x,
+}
+
+impl Scaler {
+    /// Parses a scaler name (see SCALER_NAMES).
+    pub fn parse(name: &str) -> Result<Scaler, String> {
+        match name {
+            "nearest" => Ok(Scaler::Nearest),
+            "scale2x" => Ok(Scaler::Scale2x),
+            "scale3x" => Ok(Scaler::Scale3x),
+            _ => Err(format!("Unknown scaler '{}' (expected one of {})", name, SCALER_NAMES.join(", "))),
+        }
+    }
+
+    /// Returns the size multiplier of the scaler.
+    pub fn factor(&self) -> usize {
+        match *self {
+            Scaler::Nearest => 1,
+            Scaler::Scale2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        match *self {
+            Scaler::Nearest => image.clone(),
+            Scaler::Scale2x => scale2x(image),
+            Scaler::Scale3x => scale3x(image),
+        }
+    }
+}
+
+fn scale2x(image: &Image) -> Image {
+    let mut output = Image::new(image.width * 2, image.height * 2, [0; 4]);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let p = image.pixel(x, y);
+            let a = image.clamped_pixel(xi, yi - 1);
+            let b = image.clamped_pixel(xi + 1, yi);
+            let c = image.clamped_pixel(xi - 1, yi);
+            let d = image.clamped_pixel(xi, yi + 1);
+
+            let e0 = if c == a && c != d && a != b { a } else { p };
+            let e1 = if a == b && a != c && b != d { b } else { p };
+            let e2 = if d == c && d != b && c != a { c } else { p };
+            let e3 = if b == d && b != a && d != c { d } else { p };
+
+            output.set_pixel(x * 2, y * 2, e0);
+            output.set_pixel(x * 2 + 1, y * 2, e1);
+            output.set_pixel(x * 2, y * 2 + 1, e2);
+            output.set_pixel(x * 2 + 1, y * 2 + 1, e3);
+        }
+    }
+    output
+}
+
+fn scale3x(image: &Image) -> Image {
+    let mut output = Image::new(image.width * 3, image.height * 3, [0; 4]);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let a = image.clamped_pixel(xi - 1, yi - 1);
+            let b = image.clamped_pixel(xi, yi - 1);
+            let c = image.clamped_pixel(xi + 1, yi - 1);
+            let d = image.clamped_pixel(xi - 1, yi);
+            let e = image.pixel(x, y);
+            let f = image.clamped_pixel(xi + 1, yi);
+            let g = image.clamped_pixel(xi - 1, yi + 1);
+            let h = image.clamped_pixel(xi, yi + 1);
+            let i = image.clamped_pixel(xi + 1, yi + 1);
+
+            let mut block = [e; 9];
+            if b != h && d != f {
+                block[0] = if d == b { d } else { e };
+                block[1] = if (d == b && e != c) || (b == f && e != a) { b } else { e };
+                block[2] = if b == f { f } else { e };
+                block[3] = if (d == b && e != g) || (d == h && e != a) { d } else { e };
+                block[5] = if (b == f && e != i) || (h == f && e != c) { f } else { e };
+                block[6] = if d == h { d } else { e };
+                block[7] = if (d == h && e != i) || (h == f && e != g) { h } else { e };
+                block[8] = if h == f { f } else { e };
+            }
+
+            for (index, &colour) in block.iter().enumerate() {
+                output.set_pixel(x * 3 + index % 3, y * 3 + index / 3, colour);
+            }
+        }
+    }
+    output
+}
+
+/// Scales the image by an integer factor, repeating pixels.
+pub fn scale_nearest(image: &Image, factor: usize) -> Image {
+    let mut output = Image::new(image.width * factor, image.height * factor, [0; 4]);
+    for y in 0..output.height {
+        for x in 0..output.width {
+            output.set_pixel(x, y, image.pixel(x / factor, y / factor));
+        }
+    }
+    output
+}
+
+/// Returns the colour with its brightness reduced by the fraction given (0 to 1).
+fn darken(colour: Rgba, amount: f32) -> Rgba {
+    let channel = |value: u8| (value as f32 * (1.0 - amount)).round() as u8;
+    [channel(colour[0]), channel(colour[1]), channel(colour[2]), colour[3]]
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PostProcess {
+    pub scaler: Scaler,
+
+    /// Integer scale applied after the scaler, when not fitting a target size.
+    pub scale: usize,
+
+    /// Darkening of the bottom rows of each display pixel (0 to disable, 1 for black).
+    pub scanlines: f32,
+
+    /// Darkening of the right and bottom edges of each display pixel (0 to disable, 1 for black).
+    pub grid: f32,
+
+    /// Colour of the letterbox bars.
+    pub border: Rgba,
+}
+
+impl Default for PostProcess {
+    fn default() -> PostProcess {
+        PostProcess {
+            scaler: Scaler::Nearest,
+            scale: 1,
+            scanlines: 0.0,
+            grid: 0.0,
+            border: [0x00, 0x00, 0x00, 0xFF],
+        }
+    }
+}
+
+impl PostProcess {
+    /// Returns the total scale of a display pixel, given the target size (see apply()).
+    pub fn pixel_scale(&self, width: usize, height: usize, target: Option<(usize, usize)>) -> usize {
+        let factor = self.scaler.factor();
+        let scale = match target {
+            Some((target_width, target_height)) => (target_width / (width * factor)).min(target_height / (height * factor)).max(1),
+            None => self.scale.max(1),
+        };
+        factor * scale
+    }
+
+    /// Processes the display image. With a target size, the image is scaled by the largest
+    /// integer factor that fits and letterboxed to the target size.
+    pub fn apply(&self, image: &Image, target: Option<(usize, usize)>) -> Image {
+        let pixel_scale = self.pixel_scale(image.width, image.height, target);
+        let mut output = scale_nearest(&self.scaler.apply(image), pixel_scale / self.scaler.factor());
+
+        if self.scanlines > 0.0 && pixel_scale > 1 {
+            let rows = (pixel_scale / 3).max(1);
+            for y in 0..output.height {
+                if y % pixel_scale >= pixel_scale - rows {
+                    for x in 0..output.width {
+                        let colour = darken(output.pixel(x, y), self.scanlines);
+                        output.set_pixel(x, y, colour);
+                    }
+                }
+            }
+        }
+
+        if self.grid > 0.0 && pixel_scale > 1 {
+            for y in 0..output.height {
+                for x in 0..output.width {
+                    if x % pixel_scale == pixel_scale - 1 || y % pixel_scale == pixel_scale - 1 {
+                        let colour = darken(output.pixel(x, y), self.grid);
+                        output.set_pixel(x, y, colour);
+                    }
+                }
+            }
+        }
+
+        match target {
+            Some((target_width, target_height)) => letterbox(&output, target_width, target_height, self.border),
+            None => output,
+        }
+    }
+}
+
+/// Centres the image within the target size, filling around it with the border colour.
+/// Images larger than the target are cropped.
+pub fn letterbox(image: &Image, width: usize, height: usize, border: Rgba) -> Image {
+    let mut output = Image::new(width, height, border);
+    let left = width.saturating_sub(image.width) / 2;
+    let top = height.saturating_sub(image.height) / 2;
+    for y in 0..image.height.min(height) {
+        for x in 0..image.width.min(width) {
+            output.set_pixel(left + x, top + y, image.pixel(x, y));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ON: Rgba = [0xFF, 0xFF, 0xFF, 0xFF];
+    const OFF: Rgba = [0x00, 0x00, 0x00, 0xFF];
+    const BORDER: Rgba = [0x80, 0x00, 0x00, 0xFF];
+
+    /// Creates an image from rows of '#' (on) and '.' (off).
+    fn image(rows: &[&str]) -> Image {
+        let mut image = Image::new(rows[0].len(), rows.len(), OFF);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                image.set_pixel(x, y, if pixel == '#' { ON } else { OFF });
+            }
+        }
+        image
+    }
+
+    /// Returns the image as rows of '#' (on), '.' (off) and 'b' (border).
+    fn rows(image: &Image) -> Vec<String> {
+        (0..image.height()).map(|y| (0..image.width()).map(|x| match image.pixel(x, y) {
+            ON => '#',
+            OFF => '.',
+            BORDER => 'b',
+            _ => '?',
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn scale2x_edges() {
+        // Diagonals are smoothed, pixels outside the image repeat the edge.
+        assert_eq!(rows(&Scaler::Scale2x.apply(&image(&["#.", ".#"]))), vec!["##..", "#.#.", ".#.#", "..##"]);
+
+        // Lone pixels and straight edges are only scaled.
+        assert_eq!(rows(&Scaler::Scale2x.apply(&image(&["...", ".#.", "..."]))), vec!["......", "......", "..##..", "..##..", "......", "......"]);
+        assert_eq!(rows(&Scaler::Scale2x.apply(&image(&["##", ".."]))), vec!["####", "####", "....", "...."]);
+    }
+
+    #[test]
+    fn scale3x_edges() {
+        assert_eq!(rows(&Scaler::Scale3x.apply(&image(&["#.", ".#"]))), vec!["###...", "##.#..", "#..##.", ".##..#", "..#.##", "...###"]);
+
+        assert_eq!(rows(&Scaler::Scale3x.apply(&image(&["...", ".#.", "..."]))), vec![
+            ".........", ".........", ".........",
+            "...###...", "...###...", "...###...",
+            ".........", ".........", ".........",
+        ]);
+        assert_eq!(rows(&Scaler::Scale3x.apply(&image(&["##", ".."]))), vec!["######", "######", "######", "......", "......", "......"]);
+    }
+
+    #[test]
+    fn pixel_scale_fits_target() {
+        let post_process = PostProcess { scaler: Scaler::Scale2x, scale: 3, ..PostProcess::default() };
+        assert_eq!(post_process.pixel_scale(64, 32, None), 6);
+
+        // The largest integer factor fitting both dimensions, times the scaler's.
+        assert_eq!(post_process.pixel_scale(64, 32, Some((64 * 2 * 4 + 10, 32 * 2 * 5))), 8);
+        assert_eq!(post_process.pixel_scale(64, 32, Some((64 * 2 * 5, 32 * 2 * 4 + 1))), 8);
+
+        // At least the scaler's factor, even if it doesn't fit.
+        assert_eq!(post_process.pixel_scale(64, 32, Some((10, 10))), 2);
+
+        let post_process = PostProcess::default();
+        assert_eq!(post_process.pixel_scale(64, 32, None), 1);
+        assert_eq!(post_process.pixel_scale(64, 32, Some((640, 480))), 10);
+    }
+
+    #[test]
+    fn letterbox_centres() {
+        assert_eq!(rows(&letterbox(&image(&["#.", ".#"]), 6, 4, BORDER)), vec!["bbbbbb", "bb#.bb", "bb.#bb", "bbbbbb"]);
+
+        // Odd margins leave the extra column or row after the image.
+        assert_eq!(rows(&letterbox(&image(&["#.", ".#"]), 5, 3, BORDER)), vec!["b#.bb", "b.#bb", "bbbbb"]);
+
+        let post_process = PostProcess { border: BORDER, ..PostProcess::default() };
+        assert_eq!(rows(&post_process.apply(&image(&["#.", ".#"]), Some((6, 4)))), vec!["b##..b", "b##..b", "b..##b", "b..##b"]);
+    }
+
+    #[test]
+    fn letterbox_crops() {
+        let cropped = letterbox(&image(&["#..", ".#.", "..#"]), 2, 1, BORDER);
+        assert_eq!(rows(&cropped), vec!["#."]);
+
+        // Cropped in one dimension, centred in the other.
+        let cropped = letterbox(&image(&["#..", ".#."]), 2, 4, BORDER);
+        assert_eq!(rows(&cropped), vec!["bb", "#.", ".#", "bb"]);
+    }
+}
@@ -0,0 +1,32 @@
+//! Host-side video.
+//!
+//! Turns the framebuffer into colour images for the frontends: palettes (see
+//! palette), phosphor persistence (see phosphor) and post-processing (see
+//! filter). Terminal frontends draw it as text instead (see terminal).
+
+pub mod palette;
+pub mod phosphor;
+pub mod filter;
+pub mod terminal;
+
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+use video::palette::{Palette, Colour, mix};
+use video::phosphor::Phosphor;
+use video::filter::Image;
+
+/// Returns the colour of each pixel, blending with the phosphor levels if given.
+pub fn colours(framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], palette: &Palette, phosphor: Option<&Phosphor>) -> Vec<Colour> {
+    match phosphor {
+        Some(phosphor) => phosphor.levels().iter()
+            .map(|&level| mix(palette.background(), palette.foreground(), level))
+            .collect(),
+        None => framebuffer.iter()
+            .map(|&pixel| palette.colours[pixel as usize])
+            .collect(),
+    }
+}
+
+/// Returns the framebuffer as a display sized RGBA image, ready for post-processing (see filter::PostProcess).
+pub fn image(framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], palette: &Palette, phosphor: Option<&Phosphor>) -> Image {
+    Image::from_colours(HORIZONTAL_RES, VERTICAL_RES, &colours(framebuffer, palette, phosphor))
+}
@@ -0,0 +1,87 @@
+//! Palettes.
+//!
+//! A palette holds four colours, indexed by the pixel's plane bits as in
+//! XO-CHIP: the background, the foreground (plane 1), plane 2 and the blend of
+//! both planes. Single plane displays only use the first two.
+
+/// RGB colour.
+pub type Colour = [u8; 3];
+
+/// Names of the built-in palettes.
+pub const PRESET_NAMES: [&'static str; 5] = ["mono", "vip", "amber", "lcd", "octo"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Palette {
+    /// Colours by plane bits: background, foreground, plane 2 and blend.
+    pub colours: [Colour; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::preset("mono").unwrap()
+    }
+}
+
+/// Parses a colour as "#RRGGBB".
+pub fn parse_colour(colour: &str) -> Result<Colour, String> {
+    let digits = if colour.starts_with('#') { &colour[1..] } else { "" };
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_digit(16)) {
+        return Err(format!("'{}' is not a colour (expected #RRGGBB)", colour));
+    }
+
+    let component = |index: usize| u8::from_str_radix(&digits[(index * 2)..(index * 2 + 2)], 16).unwrap();
+    Ok([component(0), component(1), component(2)])
+}
+
+/// Returns the colour the given fraction (0 to 1) of the way from one colour to the other.
+pub fn mix(from: Colour, to: Colour, fraction: f32) -> Colour {
+    let channel = |index: usize| (from[index] as f32 + (to[index] as f32 - from[index] as f32) * fraction).round() as u8;
+    [channel(0), channel(1), channel(2)]
+}
+
+impl Palette {
+    /// Returns a built-in palette (see PRESET_NAMES).
+    pub fn preset(name: &str) -> Result<Palette, String> {
+        let colours = match name {
+            // Black and white.
+            "mono" => [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55]],
+
+            // Green phosphor monitor.
+            "vip" => [[0x0A, 0x1A, 0x0A], [0x33, 0xFF, 0x66], [0x1F, 0x99, 0x3D], [0x99, 0xFF, 0xB3]],
+
+            // Amber phosphor monitor.
+            "amber" => [[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00], [0x99, 0x69, 0x00], [0xFF, 0xD8, 0x80]],
+
+            // Green tinted LCD.
+            "lcd" => [[0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F], [0x30, 0x62, 0x30], [0x8B, 0xAC, 0x0F]],
+
+            // Octo's default colours.
+            "octo" => [[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]],
+
+            _ => return Err(format!("Unknown palette '{}' (expected one of {})", name, PRESET_NAMES.join(", "))),
+        };
+        Ok(Palette { colours })
+    }
+
+    /// Parses a list of two (background, foreground) or four colours as "#RRGGBB".
+    /// With two colours, plane 2 and the blend use the foreground.
+    pub fn parse(colours: &[String]) -> Result<Palette, String> {
+        let parsed = colours.iter()
+            .map(|colour| parse_colour(colour))
+            .collect::<Result<Vec<Colour>, String>>()?;
+
+        match parsed.len() {
+            2 => Ok(Palette { colours: [parsed[0], parsed[1], parsed[1], parsed[1]] }),
+            4 => Ok(Palette { colours: [parsed[0], parsed[1], parsed[2], parsed[3]] }),
+            count => Err(format!("Palette must have 2 or 4 colours (had {})", count)),
+        }
+    }
+
+    pub fn background(&self) -> Colour {
+        self.colours[0]
+    }
+
+    pub fn foreground(&self) -> Colour {
+        self.colours[1]
+    }
+}
@@ -0,0 +1,54 @@
+//! Phosphor persistence.
+//!
+//! Simulates the afterglow of a CRT: lit pixels fade out over a few frames
+//! instead of going dark at once. Chip8 games redraw moving sprites by erasing
+//! (XORing) and drawing them again, which flickers on a display without
+//! persistence.
+//!
+//! The afterglow follows emulated time rather than presented frames, so it
+//! doesn't depend on the monitor refresh rate, stays still while paused and
+//! speeds up with fast forward.
+
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+
+/// Duration of a frame (us of emulated time), over which the decay applies.
+pub const FRAME_US: f64 = 1e6 / 60.0;
+
+pub struct Phosphor {
+    /// Fraction of the brightness kept each frame.
+    decay: f32,
+
+    /// Brightness of each pixel (0 to 1).
+    levels: Vec<f32>,
+}
+
+impl Phosphor {
+    /// Creates the phosphor with the given decay (fraction of the brightness kept each frame, below 1).
+    pub fn new(decay: f32) -> Phosphor {
+        Phosphor {
+            decay: decay.max(0.0).min(0.99),
+            levels: vec![0.0; HORIZONTAL_RES * VERTICAL_RES],
+        }
+    }
+
+    /// Advances by the emulated time given (us), lighting the pixels set in the framebuffer.
+    /// Should be called once per Core::run(), with the framebuffer it ended with.
+    pub fn update(&mut self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], elapsed_us: f64) {
+        let decay = self.decay.powf((elapsed_us / FRAME_US) as f32);
+        for (level, &pixel) in self.levels.iter_mut().zip(framebuffer.iter()) {
+            *level = if pixel { 1.0 } else { *level * decay };
+        }
+    }
+
+    /// Returns the brightness of each pixel (0 to 1).
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    /// Turns all pixels off at once, eg when the rom changes.
+    pub fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            *level = 0.0;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Text rendering, for terminal frontends.
+//!
+//! Draws the framebuffer with Unicode block or Braille characters, several
+//! display pixels per character so the display fits an 80 column terminal:
+//!  - Half blocks: 1x2 pixels per character (64x16 characters).
+//!  - Braille: 2x4 pixels per character (32x8 characters).
+//!
+//! Lit pixels are drawn in the terminal's foreground colour; colouring is
+//! left to the frontend.
+
+use common::constants::cpu::{HORIZONTAL_RES, VERTICAL_RES};
+
+/// Names of the text modes, as used in config files.
+pub const TEXT_MODE_NAMES: [&'static str; 2] = ["halfblock", "braille"];
+
+/// Braille dot bits by position within the character (column, row), see the Unicode Braille Patterns block.
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+const BRAILLE_BLANK: u32 = 0x2800;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextMode {
+    HalfBlock,
+    Braille,
+}
+
+impl TextMode {
+    /// Parses a text mode name (see TEXT_MODE_NAMES).
+    pub fn parse(name: &str) -> Result<TextMode, String> {
+        match name {
+            "halfblock" => Ok(TextMode::HalfBlock),
+            "braille" => Ok(TextMode::Braille),
+            _ => Err(format!("Unknown text mode '{}' (expected one of {})", name, TEXT_MODE_NAMES.join(", "))),
+        }
+    }
+
+    /// Returns the number of display pixels covered by a character (width, height).
+    pub fn cell_size(&self) -> (usize, usize) {
+        match *self {
+            TextMode::HalfBlock => (1, 2),
+            TextMode::Braille => (2, 4),
+        }
+    }
+
+    /// Returns the size of the display in characters (columns, rows).
+    pub fn size(&self) -> (usize, usize) {
+        let (width, height) = self.cell_size();
+        (HORIZONTAL_RES / width, VERTICAL_RES / height)
+    }
+
+    /// Returns the character for the pixels at the position given (in characters).
+    fn character(&self, framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], column: usize, row: usize) -> char {
+        let (width, height) = self.cell_size();
+        let pixel = |x: usize, y: usize| framebuffer[(row * height + y) * HORIZONTAL_RES + column * width + x];
+        match *self {
+            TextMode::HalfBlock => {
+                match (pixel(0, 0), pixel(0, 1)) {
+                    (false, false) => ' ',
+                    (true, false) => '\u{2580}',
+                    (false, true) => '\u{2584}',
+                    (true, true) => '\u{2588}',
+                }
+            },
+            TextMode::Braille => {
+                let mut code = BRAILLE_BLANK;
+                for x in 0..width {
+                    for y in 0..height {
+                        if pixel(x, y) {
+                            code |= BRAILLE_DOTS[x][y];
+                        }
+                    }
+                }
+                ::std::char::from_u32(code).unwrap()
+            },
+        }
+    }
+}
+
+/// Returns the framebuffer as lines of text.
+pub fn text(framebuffer: &[bool; HORIZONTAL_RES * VERTICAL_RES], mode: TextMode) -> Vec<String> {
+    let (columns, rows) = mode.size();
+    (0..rows)
+        .map(|row| (0..columns).map(|column| mode.character(framebuffer, column, row)).collect())
+        .collect()
+}